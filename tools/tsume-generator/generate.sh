@@ -9,7 +9,16 @@ TMPDIR=$(mktemp -d)
 echo "Generating $TOTAL tsume with $WORKERS workers ($PER_WORKER each)..."
 
 for i in $(seq 1 $WORKERS); do
-    ./target/release/tsume-generator "$TMPDIR/part_$i.sfen" "$PER_WORKER" &
+    # With SEED set, worker i uses SEED+i so the whole run is reproducible
+    SEED_ARGS=()
+    if [ -n "$SEED" ]; then
+        SEED_ARGS=(--seed $(( SEED + i )))
+    fi
+    # With ENGINE_THREADS set, the threads are shared out among the workers
+    if [ -n "$ENGINE_THREADS" ]; then
+        SEED_ARGS+=(--engine-threads $(( ENGINE_THREADS / WORKERS > 0 ? ENGINE_THREADS / WORKERS : 1 )))
+    fi
+    ./target/release/tsume-generator "$TMPDIR/part_$i.sfen" "$PER_WORKER" "${SEED_ARGS[@]}" &
 done
 
 wait
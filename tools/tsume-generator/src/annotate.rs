@@ -0,0 +1,302 @@
+//! Re-verifying existing puzzle files (`tsume-generator verify`).
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use serde::Serialize;
+
+use crate::engine::Engine;
+use crate::output::OutputFormat;
+use crate::pool::EnginePool;
+use crate::progress::ProgressReporter;
+use crate::resume::{ResumeError, StoredPuzzle, detect_file_format, read_puzzles};
+use crate::sfen::validate_puzzle_sfen;
+use crate::verify::{VerifyResult, verify_puzzle};
+use crate::{ensure_black_to_move, shutdown};
+
+/// Verdict on one puzzle of an input file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PuzzleStatus {
+    /// The engine proved a forced mate for Black
+    Valid,
+    /// The engine found no forced mate
+    NotAMate,
+    /// The line is not a puzzle position with Black to move
+    Unparsable,
+    /// The engine failed before giving a verdict
+    EngineFailed,
+}
+
+/// One line of the annotated output.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnnotatedPuzzle {
+    /// Line number in the input file, from 1
+    pub line: usize,
+    /// Position searched, turned to Black to move if stored as played;
+    /// the line as read if unparsable
+    pub sfen: String,
+    pub status: PuzzleStatus,
+    /// Mate distance in plies (only when valid)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mate_in: Option<i32>,
+    /// Mating line in USI notation, Fairy-Stockfish coordinates (only when valid)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub solution: Option<Vec<String>>,
+}
+
+/// Counts of each [`PuzzleStatus`] over a file.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AnnotationSummary {
+    pub valid: usize,
+    pub not_a_mate: usize,
+    pub unparsable: usize,
+    pub engine_failed: usize,
+}
+
+impl AnnotationSummary {
+    pub fn of(puzzles: &[AnnotatedPuzzle]) -> Self {
+        let mut summary = AnnotationSummary::default();
+        for puzzle in puzzles {
+            match puzzle.status {
+                PuzzleStatus::Valid => summary.valid += 1,
+                PuzzleStatus::NotAMate => summary.not_a_mate += 1,
+                PuzzleStatus::Unparsable => summary.unparsable += 1,
+                PuzzleStatus::EngineFailed => summary.engine_failed += 1,
+            }
+        }
+        summary
+    }
+
+    pub fn print_summary(&self, output: &Path) {
+        let total = self.valid + self.not_a_mate + self.unparsable + self.engine_failed;
+        eprintln!("Done: {} puzzles -> {}", total, output.display());
+        eprintln!("  valid:         {}", self.valid);
+        eprintln!("  not a mate:    {}", self.not_a_mate);
+        eprintln!("  unparsable:    {}", self.unparsable);
+        if self.engine_failed > 0 {
+            eprintln!("  engine failed: {}", self.engine_failed);
+        }
+    }
+}
+
+/// Puzzles of an output file in any format as `(line number, SFEN)`; a
+/// line holding no readable puzzle is kept as read, to be reported
+/// unparsable.
+pub fn read_puzzle_lines(path: &Path) -> Result<Vec<(usize, String)>, ResumeError> {
+    let Some(format) = detect_file_format(path)? else {
+        return Ok(Vec::new());
+    };
+    parse_puzzle_lines(File::open(path)?, format)
+}
+
+fn parse_puzzle_lines(
+    file: impl Read,
+    format: OutputFormat,
+) -> Result<Vec<(usize, String)>, ResumeError> {
+    let mut lines = Vec::new();
+    read_puzzles(file, format, |number, stored, sfen| {
+        let sfen = sfen.unwrap_or_else(|_| match stored {
+            StoredPuzzle::Line(line) => line.to_string(),
+            StoredPuzzle::Row { row, .. } => row.iter().collect::<Vec<_>>().join(","),
+        });
+        lines.push((number, sfen));
+    })?;
+    Ok(lines)
+}
+
+/// Check one puzzle line and search it for `time_ms`.
+pub fn annotate_puzzle(
+    engine: &mut Engine,
+    line: usize,
+    sfen: &str,
+    time_ms: u64,
+) -> AnnotatedPuzzle {
+    let mut puzzle = AnnotatedPuzzle {
+        line,
+        sfen: sfen.to_string(),
+        status: PuzzleStatus::Unparsable,
+        mate_in: None,
+        solution: None,
+    };
+    // Puzzles written as played (`--no-mirror`) may have White to move
+    let sfen = ensure_black_to_move(sfen);
+    if validate_puzzle_sfen(&sfen).is_err() {
+        return puzzle;
+    }
+
+    let verification = verify_puzzle(engine, &sfen, time_ms);
+    puzzle.sfen = sfen;
+    puzzle.status = match verification.result {
+        VerifyResult::Matein(mate) => {
+            puzzle.mate_in = Some(mate);
+            puzzle.solution = verification
+                .pv_infos
+                .into_iter()
+                .find(|pv| pv.multipv == 1)
+                .map(|pv| pv.moves);
+            PuzzleStatus::Valid
+        }
+        VerifyResult::NoMate => PuzzleStatus::NotAMate,
+        VerifyResult::Timeout(_) => PuzzleStatus::EngineFailed,
+    };
+    puzzle
+}
+
+/// Annotate `lines` on `jobs` threads sharing `pool`, in input order,
+/// counting each puzzle done on `reporter`.
+///
+/// Stops early when a shutdown is requested; the puzzles done so far are returned.
+pub fn annotate_puzzles(
+    pool: &EnginePool,
+    lines: &[(usize, String)],
+    time_ms: u64,
+    jobs: usize,
+    reporter: &ProgressReporter,
+) -> Vec<AnnotatedPuzzle> {
+    let next = AtomicUsize::new(0);
+    let mut puzzles: Vec<(usize, AnnotatedPuzzle)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    while !shutdown::requested() {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some((line, sfen)) = lines.get(index) else {
+                            break;
+                        };
+                        let Some(mut engine) = pool.acquire() else {
+                            break;
+                        };
+                        done.push((index, annotate_puzzle(&mut engine, *line, sfen, time_ms)));
+                        reporter.record();
+                    }
+                    done
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Verify worker panicked"))
+            .collect()
+    });
+    puzzles.sort_by_key(|(index, _)| *index);
+    puzzles.into_iter().map(|(_, puzzle)| puzzle).collect()
+}
+
+/// Write `puzzles` as JSON Lines.
+pub fn write_annotated(puzzles: &[AnnotatedPuzzle], path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for puzzle in puzzles {
+        serde_json::to_writer(&mut writer, puzzle)?;
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_puzzle_lines() {
+        let text = "# old run\n1k1/3/R2/3/K1B b G 1\n\n  bkr/p1p/3/P1P/RKB b - 1 \n";
+        let lines = parse_puzzle_lines(text.as_bytes(), OutputFormat::Plain).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                (2, "1k1/3/R2/3/K1B b G 1".to_string()),
+                (4, "bkr/p1p/3/P1P/RKB b - 1".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    fn mate_engine_pool(engines: usize) -> EnginePool {
+        use crate::engine::EngineConfig;
+        use crate::variant::Variant;
+
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let config = EngineConfig {
+            engine: fixtures.join("mate-engine.sh"),
+            variants_ini: fixtures.join("variants.ini"),
+            variant: Variant::Wildcatshogi,
+            ..EngineConfig::default()
+        };
+        EnginePool::new(engines, &config).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_annotate_puzzles_in_parallel() {
+        let pool = mate_engine_pool(2);
+        let text =
+            "1k1/3/R2/3/K1B b G 1\nbkr/p1p/3/P1P/RKB b - 1\nnot a position\n1k1/3/R2/3/K1B b G 1\n";
+        let lines = parse_puzzle_lines(text.as_bytes(), OutputFormat::Plain).unwrap();
+
+        let reporter = ProgressReporter::new("puzzles verified", lines.len());
+        let puzzles = annotate_puzzles(&pool, &lines, 50, 2, &reporter);
+        let statuses: Vec<_> = puzzles.iter().map(|puzzle| puzzle.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                PuzzleStatus::Valid,
+                PuzzleStatus::NotAMate,
+                PuzzleStatus::Unparsable,
+                PuzzleStatus::Valid
+            ]
+        );
+        assert_eq!(puzzles[0].mate_in, Some(3));
+        assert_eq!(
+            puzzles[0].solution.as_deref(),
+            Some(["G*2b", "2a1a", "3c3a"].map(String::from).as_slice())
+        );
+        assert_eq!(puzzles[2].line, 3);
+        assert_eq!(
+            AnnotationSummary::of(&puzzles),
+            AnnotationSummary {
+                valid: 2,
+                not_a_mate: 1,
+                unparsable: 1,
+                engine_failed: 0,
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_verify_jsonl_and_unmirrored_files() {
+        use crate::output::{OutputWriter, PuzzleRecord};
+
+        let temp = |name: &str| {
+            std::env::temp_dir().join(format!("tsume-verify-{}-{}", std::process::id(), name))
+        };
+        let (jsonl, unmirrored) = (temp("puzzles.jsonl"), temp("played.sfen"));
+        let mut writer = OutputWriter::create(OutputFormat::Jsonl, &jsonl).unwrap();
+        writer
+            .write(&PuzzleRecord::new("1k1/3/R2/3/K1B b G 1".to_string(), 9))
+            .unwrap();
+        drop(writer);
+        // The same puzzle as played by White (`--no-mirror`)
+        std::fs::write(&unmirrored, "b1k/3/2r/3/1K1 w g 4\tw\n").unwrap();
+
+        let pool = mate_engine_pool(1);
+        let reporter = ProgressReporter::new("puzzles verified", 2);
+        let mut puzzles = Vec::new();
+        for path in [&jsonl, &unmirrored] {
+            let lines = read_puzzle_lines(path);
+            let _ = std::fs::remove_file(path);
+            puzzles.extend(annotate_puzzles(&pool, &lines.unwrap(), 50, 1, &reporter));
+        }
+
+        assert_eq!(puzzles.len(), 2);
+        for puzzle in &puzzles {
+            assert_eq!(puzzle.status, PuzzleStatus::Valid);
+            assert_eq!(puzzle.sfen, "1k1/3/R2/3/K1B b G 1");
+            assert_eq!(puzzle.mate_in, Some(3));
+        }
+    }
+}
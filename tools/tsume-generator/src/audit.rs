@@ -0,0 +1,79 @@
+//! Independent checks of accepted puzzles against the library (`--audit`).
+//!
+//! A puzzle comes out of a game through a mirror when White won, and its
+//! solution comes from the engine in its own coordinates. These checks read
+//! the final record again with the library alone, so a bug in either path
+//! shows up as a failed audit rather than as a broken puzzle in the output.
+
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::convert_move_files;
+use crate::movegen::legal_moves;
+use crate::output::PuzzleRecord;
+
+/// Check that `record` has Black to move, that Black has a legal move and
+/// that its solution, if verified, starts with one of them.
+///
+/// The error names the broken invariant and the puzzle's SFEN.
+pub fn audit_puzzle(record: &PuzzleRecord) -> Result<(), String> {
+    let sfen = &record.sfen;
+    let fail = |reason: String| Err(format!("{} in {:?}", reason, sfen));
+
+    if sfen.split_whitespace().nth(1) != Some("b") {
+        return fail("side to move is not Black".to_string());
+    }
+    let position = match Position::from_sfen(sfen) {
+        Ok(position) => position,
+        Err(e) => return fail(format!("unparsable position ({})", e)),
+    };
+    let moves = legal_moves(&position);
+    if moves.is_empty() {
+        return fail("Black has no legal move".to_string());
+    }
+    let Some(solution) = &record.solution else {
+        return Ok(());
+    };
+    let Some(first) = solution.first() else {
+        return fail("the solution is empty".to_string());
+    };
+    // Solutions are kept in Fairy-Stockfish coordinates
+    let key = convert_move_files(first);
+    match Move::from_sfen(&key) {
+        Some(key) if moves.contains(&key) => Ok(()),
+        _ => fail(format!("solution move {} is not legal for Black", first)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(sfen: &str, solution: Option<&[&str]>) -> PuzzleRecord {
+        let mut record = PuzzleRecord::new(sfen.to_string(), 0);
+        record.solution = solution.map(|moves| moves.iter().map(|mv| mv.to_string()).collect());
+        record
+    }
+
+    #[test]
+    fn test_sound_puzzle_passes() {
+        // G*2b in Fairy-Stockfish coordinates is G*2b in the library's
+        assert_eq!(
+            audit_puzzle(&record("1k1/3/1R1/3/K1B b G 1", Some(&["G*2b"]))),
+            Ok(())
+        );
+        assert_eq!(audit_puzzle(&record("1k1/3/1R1/3/K1B b G 1", None)), Ok(()));
+    }
+
+    #[test]
+    fn test_violations() {
+        let error = audit_puzzle(&record("1k1/3/1R1/3/K1B w G 1", None)).unwrap_err();
+        assert!(error.contains("not Black"));
+        assert!(error.contains("1k1/3/1R1/3/K1B w G 1"));
+
+        // The rook cannot reach 2a
+        let error = audit_puzzle(&record("1k1/3/1R1/3/K1B b G 1", Some(&["2c2a"]))).unwrap_err();
+        assert!(error.contains("2c2a"));
+        let error = audit_puzzle(&record("1k1/3/1R1/3/K1B b G 1", Some(&[]))).unwrap_err();
+        assert!(error.contains("empty"));
+    }
+}
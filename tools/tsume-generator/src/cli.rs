@@ -0,0 +1,910 @@
+//! Command-line arguments.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::engine::{
+    DRAW_SCORE, EngineConfig, MULTIPV_K, RESIGN_RETRY_FACTOR, SEARCH_TIME_MS, Strength,
+};
+use crate::generator::{
+    Escalation, GenerationMode, GeneratorConfig, MAX_ATTEMPTS, MAX_SEARCH_MS, SELFPLAY_ATTEMPTS,
+    SELFPLAY_RANDOM_PLIES,
+};
+use crate::material::PieceType;
+use crate::openings::OPENING_PLIES;
+use crate::output::{OutputFormat, OutputTarget};
+use crate::simulator::{ENDGAME_MATERIAL, ENDGAME_PLY, MAX_MOVES, SearchBudget, Strategy};
+use crate::variant::Variant;
+
+/// How White is made to play badly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WeakMode {
+    /// Worst of the engine's MultiPV candidates
+    Worstpv,
+    /// Engine move under `UCI_LimitStrength`/`Skill Level`
+    Skill,
+    /// Best move of White's own engine; the default with `--engine-white`
+    Engine,
+}
+
+/// Generate mate tsume for Wild Cat Shogi using Fairy-Stockfish.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Output SFEN file; `-` writes to stdout
+    #[arg(default_value = "results.sfen")]
+    pub output: PathBuf,
+
+    /// Number of puzzles to generate
+    #[arg(default_value_t = 1000)]
+    pub count: usize,
+
+    /// Fairy-Stockfish binary (a bare name is looked up in PATH)
+    #[arg(
+        long,
+        env = "FAIRY_STOCKFISH",
+        default_value = "fairy-stockfish",
+        global = true
+    )]
+    pub engine: PathBuf,
+
+    /// Engine playing Black's moves and verifying puzzles, instead of `--engine`
+    #[arg(long, value_name = "PATH")]
+    pub engine_black: Option<PathBuf>,
+
+    /// Engine playing White's moves, with options of its own
+    #[arg(long, value_name = "PATH")]
+    pub engine_white: Option<PathBuf>,
+
+    /// Print the name and version each engine reports, then exit
+    #[arg(long)]
+    pub check_engine: bool,
+
+    /// variants.ini defining Wild Cat Shogi
+    #[arg(
+        long,
+        env = "WILDCAT_VARIANTS_INI",
+        default_value = "../../variants.ini",
+        global = true
+    )]
+    pub variants_ini: PathBuf,
+
+    /// Variant to generate puzzles for (must be defined in variants.ini)
+    #[arg(long, value_enum, default_value_t = Variant::Wildcatshogi, global = true)]
+    pub variant: Variant,
+
+    /// Engine threads in total, divided evenly across the --jobs engines
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    pub engine_threads: Option<u32>,
+
+    /// Hash table size of each engine process, in MiB
+    #[arg(long, value_name = "MB", value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    pub engine_hash_mb: Option<u32>,
+
+    /// Output format
+    #[arg(long, alias = "output-format", value_enum, default_value_t = OutputFormat::Plain)]
+    pub format: OutputFormat,
+
+    /// Add puzzles to the end of an existing output file instead of overwriting it
+    #[arg(long)]
+    pub append: bool,
+
+    /// With --append, stop if the existing output has unreadable lines instead of skipping them
+    #[arg(long, requires = "append")]
+    pub strict: bool,
+
+    /// Re-check every accepted puzzle with the library and stop at the first that
+    /// is not Black to move or whose solution does not start with a legal move
+    #[arg(long)]
+    pub audit: bool,
+
+    /// Also write the source game of every accepted puzzle to this file, one per line
+    #[arg(long, value_name = "PATH")]
+    pub games_file: Option<PathBuf>,
+
+    /// Append a JSON line for every rejected candidate or failed game to this file
+    #[arg(long, value_name = "PATH")]
+    pub reject_log: Option<PathBuf>,
+
+    /// Write the time each accepted puzzle took, per stage, to this CSV file
+    #[arg(long, value_name = "PATH")]
+    pub timings: Option<PathBuf>,
+
+    /// Add the rendered board to each puzzle: a `diagram` field in JSONL, a
+    /// `diagram` column in CSV, or `# ` comment lines after it in plain output
+    #[arg(long, alias = "csv-diagram")]
+    pub with_diagram: bool,
+
+    /// Write each puzzle as it occurred, with the side that mates, instead of
+    /// turning White's wins so that Black mates
+    #[arg(long)]
+    pub no_mirror: bool,
+
+    /// File of SFEN positions, one per line, to start games from instead of the initial position
+    #[arg(long, value_name = "FILE")]
+    pub seeds: Option<PathBuf>,
+
+    /// Discard games whose mating move comes before this ply
+    #[arg(long, default_value_t = 0)]
+    pub min_game_plies: usize,
+
+    /// Abandon games that last this many plies without a mate
+    #[arg(long, default_value_t = MAX_MOVES)]
+    pub max_game_plies: usize,
+
+    /// Leave the engine's DrawScore at 0 instead of penalizing draws; repeated
+    /// positions still end the game
+    #[arg(long)]
+    pub no_draw_penalty: bool,
+
+    /// Abandon games after this many plies without a capture, drop or pawn move (0 disables)
+    #[arg(long, default_value_t = 50)]
+    pub max_quiet_plies: usize,
+
+    /// Emit the position this many attacker moves before the mate; above 1 requires verification
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub plies_before_mate: u32,
+
+    /// Number of opening plies sampled uniformly from the engine's MultiPV candidates
+    /// [default: 0, or 6 with --mode selfplay]
+    #[arg(long)]
+    pub random_plies: Option<usize>,
+
+    /// Abandon games whose opening was already played this many more times;
+    /// the counts are kept in <output>.openings
+    #[arg(long, value_name = "N")]
+    pub max_opening_reuse: Option<usize>,
+
+    /// Plies that make up a game's opening for --max-opening-reuse
+    #[arg(
+        long,
+        value_name = "PLIES",
+        default_value_t = OPENING_PLIES as u32,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "max_opening_reuse"
+    )]
+    pub opening_plies: u32,
+
+    /// Milliseconds spent proving each candidate is a forced mate (0 disables verification)
+    #[arg(long, default_value_t = 500, global = true)]
+    pub verify_ms: u64,
+
+    /// Discard puzzles whose verified mate is shorter than this many plies
+    #[arg(long)]
+    pub min_mate: Option<i32>,
+
+    /// Discard puzzles whose verified mate is longer than this many plies
+    #[arg(long)]
+    pub max_mate: Option<i32>,
+
+    /// Discard puzzles where more than one first move mates as fast as the solution
+    #[arg(long)]
+    pub unique_solution: bool,
+
+    /// Discard puzzles where any attacker move of the solution is not the only
+    /// one mating as fast; one more verification search per attacker move
+    #[arg(long)]
+    pub strictly_forced: bool,
+
+    /// Milliseconds the defender searches after the key move to confirm it is
+    /// still mated (0 disables the check)
+    #[arg(long, default_value_t = 500)]
+    pub defense_check_ms: u64,
+
+    /// Discard puzzles rated easier than this difficulty
+    #[arg(long)]
+    pub min_difficulty: Option<f64>,
+
+    /// Discard puzzles rated harder than this difficulty
+    #[arg(long)]
+    pub max_difficulty: Option<f64>,
+
+    /// Rate every verified puzzle with strength-limited searches, written as
+    /// `rating` in JSONL output; slow
+    #[arg(long)]
+    pub rate_puzzles: bool,
+
+    /// Seed for all random choices (random if omitted; printed at startup)
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Split the run into this many shards, each in a file of its own
+    #[arg(long, default_value_t = 1)]
+    pub shards: usize,
+
+    /// Shard this process generates, from 0 to --shards - 1
+    #[arg(long, default_value_t = 0)]
+    pub shard_index: usize,
+
+    /// Play the work units from START up to END, each from a seed of its own
+    #[arg(
+        long,
+        value_name = "START:END",
+        value_parser = parse_work_range,
+        conflicts_with_all = ["shards", "shard_index"]
+    )]
+    pub work_range: Option<Range<u64>>,
+
+    /// Keep only puzzles whose mating move is a drop
+    #[arg(long)]
+    pub require_drop: bool,
+
+    /// Keep only puzzles whose mating move promotes
+    #[arg(long)]
+    pub require_promotion: bool,
+
+    /// Keep only puzzles where Black drops a piece somewhere in the solution
+    #[arg(long)]
+    pub require_attacker_drops: bool,
+
+    /// Keep only positions where White has pieces in hand to interpose
+    #[arg(long, conflicts_with = "require_empty_defender_hand")]
+    pub require_defender_hand: bool,
+
+    /// Keep only positions where White has nothing in hand
+    #[arg(long)]
+    pub require_empty_defender_hand: bool,
+
+    /// Keep only positions with at most this many White pieces on the board, king included
+    #[arg(long, value_name = "N")]
+    pub max_defender_pieces: Option<u32>,
+
+    /// Keep only positions with at most this many pieces on the board, kings included
+    #[arg(long, value_name = "N")]
+    pub max_total_pieces: Option<u32>,
+
+    /// Keep only positions where Black has this piece on the board or in hand; repeatable
+    #[arg(long, value_name = "PIECE")]
+    pub require_piece: Vec<PieceType>,
+
+    /// Worker threads, each driving its own engine process
+    #[arg(long, default_value_t = 1, global = true)]
+    pub jobs: usize,
+
+    /// Engine restarts after hangs or crashes allowed before giving up
+    #[arg(long, default_value_t = 10)]
+    pub max_engine_restarts: usize,
+
+    /// How games are played: White playing badly, or both sides at their best
+    #[arg(long, value_enum, default_value_t = GenerationMode::Weak)]
+    pub mode: GenerationMode,
+
+    /// How White plays badly [default: worstpv, or engine with --engine-white]
+    #[arg(long, value_enum)]
+    pub weak_mode: Option<WeakMode>,
+
+    /// Games played per candidate search before starting over
+    /// [default: 10, or 50 with --mode selfplay]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub attempts: Option<u32>,
+
+    /// Search time of each move of the games
+    #[arg(long, default_value_t = SEARCH_TIME_MS, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub search_ms: u64,
+
+    /// Search time of each move once the game reaches the endgame, by
+    /// material or ply; --search-ms throughout if omitted
+    #[arg(long, value_name = "MS")]
+    pub endgame_search_ms: Option<u64>,
+
+    /// Endgame once either side's material, board and hand, is below this (centipawns)
+    #[arg(long, default_value_t = ENDGAME_MATERIAL, requires = "endgame_search_ms")]
+    pub endgame_material: i32,
+
+    /// Endgame from this ply whatever the material
+    #[arg(long, default_value_t = ENDGAME_PLY, requires = "endgame_search_ms")]
+    pub endgame_ply: usize,
+
+    /// Multiply the search time by this to retry a search that resigned without a line
+    #[arg(long, default_value_t = RESIGN_RETRY_FACTOR, value_parser = clap::value_parser!(u64).range(1..))]
+    pub resign_retry_factor: u64,
+
+    /// Multiply the move search time by this after every few games without a mate; 1 disables
+    #[arg(long, default_value_t = 2.0)]
+    pub escalation_factor: f64,
+
+    /// Cap of the raised move search time
+    #[arg(long, default_value_t = MAX_SEARCH_MS, value_name = "MS")]
+    pub max_search_ms: u64,
+
+    /// MultiPV candidates White's worst move is chosen among (worstpv mode);
+    /// Black always searches a single PV
+    #[arg(long, default_value_t = MULTIPV_K as u32, value_parser = clap::value_parser!(u32).range(1..))]
+    pub multipv_weak: u32,
+
+    /// Sample White's move from the MultiPV candidates with weight exp(-score / T),
+    /// T in centipawns; 0 always plays the worst candidate (worstpv mode only)
+    #[arg(long, default_value_t = 0)]
+    pub temperature: u32,
+
+    /// Elo of White in skill mode (UCI_Elo)
+    #[arg(long, conflicts_with = "skill_level")]
+    pub weak_elo: Option<u32>,
+
+    /// Skill Level (0-20) of White in skill mode; defaults to 0
+    #[arg(long, value_parser = clap::value_parser!(i32).range(0..=20))]
+    pub skill_level: Option<i32>,
+}
+
+/// Commands other than generation.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check the puzzles of an existing SFEN file and annotate each with its verdict
+    Verify {
+        /// SFEN file, one puzzle per line; blank lines and `#` comments are skipped
+        input: PathBuf,
+
+        /// Annotated JSON Lines output
+        #[arg(long, default_value = "annotated.jsonl")]
+        output: PathBuf,
+
+        /// Print the runs recorded in the input's manifest instead of verifying it
+        #[arg(long)]
+        show_manifest: bool,
+    },
+    /// Merge output files of several runs or shards into one, each position
+    /// kept once, with their manifests
+    Merge {
+        /// Output files, all in one format; puzzles are kept in this order
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Merged output file
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Take puzzles from recorded games instead of playing games; generation
+    /// options go before `mine`
+    Mine {
+        /// Games, one per line as a USI position argument (see the README);
+        /// a games file written by `--games-file` is one
+        #[arg(long)]
+        games: PathBuf,
+    },
+}
+
+impl Args {
+    /// Engine paths with relative paths resolved against the current directory.
+    pub fn engine_config(&self) -> EngineConfig {
+        EngineConfig {
+            engine: resolve_path(self.engine_black.as_ref().unwrap_or(&self.engine)),
+            variants_ini: resolve_path(&self.variants_ini),
+            variant: self.variant,
+            threads: self
+                .engine_threads
+                .map(|threads| threads as usize / self.jobs.max(1)),
+            hash_mb: self.engine_hash_mb.map(|mb| mb as usize),
+        }
+    }
+
+    /// Fails if `--engine-threads` cannot give each of the `--jobs` engines a
+    /// thread without going over the total.
+    pub fn check_engine_threads(&self) -> Result<(), String> {
+        match self.engine_threads {
+            Some(threads) if (threads as usize) < self.jobs => Err(format!(
+                "--engine-threads {} is fewer than --jobs {}; each engine needs a thread",
+                threads, self.jobs
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Where this process writes its puzzles: stdout, the output file or its shard.
+    pub fn output_target(&self) -> OutputTarget {
+        OutputTarget::new(&self.output, self.shards, self.shard_index)
+    }
+
+    /// Seed of this shard, from the run's `seed`.
+    ///
+    /// Shards of a run share `--seed`; the index goes into the upper half so
+    /// that no worker of one shard plays the games of another, whose seeds
+    /// differ only in the lower half.
+    pub fn shard_seed(&self, seed: u64) -> u64 {
+        seed.wrapping_add((self.shard_index as u64) << 32)
+    }
+
+    /// White's engine, if it is not the one playing Black.
+    pub fn white_engine_config(&self) -> Option<EngineConfig> {
+        let config = EngineConfig {
+            engine: resolve_path(self.engine_white.as_ref()?),
+            ..self.engine_config()
+        };
+        (config.engine != self.engine_config().engine).then_some(config)
+    }
+
+    /// `--weak-mode`, defaulting to White's own engine when it has one.
+    pub fn weak_mode(&self) -> WeakMode {
+        self.weak_mode.unwrap_or(match self.white_engine_config() {
+            Some(_) => WeakMode::Engine,
+            None => WeakMode::Worstpv,
+        })
+    }
+
+    /// `--mode`, or mined when taking puzzles from recorded games.
+    pub fn mode(&self) -> GenerationMode {
+        match self.command {
+            Some(Command::Mine { .. }) => GenerationMode::Mined,
+            _ => self.mode,
+        }
+    }
+
+    /// Move selection for White according to `--weak-mode`; its best move in self-play.
+    pub fn white_strategy(&self) -> Strategy {
+        if self.mode == GenerationMode::Selfplay {
+            return Strategy::Best;
+        }
+        match self.weak_mode() {
+            WeakMode::Worstpv if self.temperature > 0 => Strategy::SampledWorst(self.temperature),
+            WeakMode::Worstpv => Strategy::Worst,
+            WeakMode::Skill => Strategy::Weakened(match self.weak_elo {
+                Some(elo) => Strength::Elo(elo),
+                None => Strength::SkillLevel(self.skill_level.unwrap_or(0)),
+            }),
+            WeakMode::Engine => Strategy::Best,
+        }
+    }
+
+    /// `--random-plies`, or its default for the mode.
+    pub fn random_plies(&self) -> usize {
+        self.random_plies.unwrap_or(match self.mode {
+            GenerationMode::Weak | GenerationMode::Mined => 0,
+            GenerationMode::Selfplay => SELFPLAY_RANDOM_PLIES,
+        })
+    }
+
+    /// `--attempts`, or its default for the mode.
+    pub fn attempts(&self) -> usize {
+        self.attempts.map_or(
+            match self.mode {
+                GenerationMode::Weak | GenerationMode::Mined => MAX_ATTEMPTS,
+                GenerationMode::Selfplay => SELFPLAY_ATTEMPTS,
+            },
+            |attempts| attempts as usize,
+        )
+    }
+
+    /// Whether verified puzzles are rated: needed for filtering or for a
+    /// format with a difficulty field.
+    pub fn rates_puzzles(&self) -> bool {
+        self.verify_ms > 0
+            && (self.format != OutputFormat::Plain
+                || self.min_difficulty.is_some()
+                || self.max_difficulty.is_some())
+    }
+
+    /// Generation settings; games start from `seed_positions` if any.
+    pub fn generator_config(&self, seed_positions: Vec<String>) -> GeneratorConfig {
+        GeneratorConfig {
+            mode: self.mode(),
+            white: self.white_strategy(),
+            weak_multipv: self.multipv_weak as usize,
+            draw_score: if self.no_draw_penalty { 0 } else { DRAW_SCORE },
+            seed_positions,
+            random_plies: self.random_plies(),
+            opening_plies: self.opening_plies as usize,
+            min_game_plies: self.min_game_plies,
+            max_game_plies: self.max_game_plies,
+            plies_before_mate: self.plies_before_mate as usize,
+            max_quiet_plies: Some(self.max_quiet_plies).filter(|&plies| plies > 0),
+            search: SearchBudget {
+                search_ms: self.search_ms,
+                endgame_ms: self.endgame_search_ms,
+                endgame_material: self.endgame_material,
+                endgame_ply: self.endgame_ply,
+            },
+            resign_retry_factor: self.resign_retry_factor,
+            escalation: Escalation {
+                attempts: self.attempts(),
+                factor: self.escalation_factor,
+                max_search_ms: self.max_search_ms,
+                ..Escalation::default()
+            },
+            verify_ms: self.verify_ms,
+            min_mate: self.min_mate,
+            max_mate: self.max_mate,
+            unique_solution: self.unique_solution,
+            strictly_forced: self.strictly_forced,
+            defense_check_ms: self.defense_check_ms,
+            rate: self.rates_puzzles(),
+            min_difficulty: self.min_difficulty,
+            max_difficulty: self.max_difficulty,
+            estimate_rating: self.rate_puzzles,
+        }
+    }
+}
+
+/// Units a `--work-range` may name: beyond them [`unit_seed`] repeats.
+pub const MAX_WORK_UNITS: u64 = 1 << 32;
+
+/// Lower half of every unit's seed, above any worker index a shard adds
+const UNIT_SEED_SALT: u64 = u32::MAX as u64;
+
+/// Seed of work unit `unit` of a `--work-range` run, from the run's `seed`.
+///
+/// The unit goes into the upper half as the index does in
+/// [`Args::shard_seed`], so disjoint ranges never share a seed. The lower
+/// half is [`UNIT_SEED_SALT`], which no worker of a `--shards` run reaches,
+/// so a `--work-range` run replays no shard's games either.
+pub fn unit_seed(seed: u64, unit: u64) -> u64 {
+    seed.wrapping_add(unit << 32).wrapping_add(UNIT_SEED_SALT)
+}
+
+/// `START:END`, END excluded.
+fn parse_work_range(text: &str) -> Result<Range<u64>, String> {
+    let (start, end) = text
+        .split_once(':')
+        .ok_or_else(|| "expected START:END".to_string())?;
+    let parse = |n: &str| n.parse::<u64>().map_err(|e| format!("{}: {}", n, e));
+    let range = parse(start)?..parse(end)?;
+    if range.is_empty() {
+        return Err("END must be above START".to_string());
+    }
+    if range.end > MAX_WORK_UNITS {
+        return Err(format!("END must be at most {}", MAX_WORK_UNITS));
+    }
+    Ok(range)
+}
+
+/// Resolve a relative path against the current directory.
+///
+/// Bare names without a directory component are kept as-is so that they are
+/// still looked up in PATH when spawned.
+fn resolve_path(path: &Path) -> PathBuf {
+    if path.is_absolute() || path.components().count() == 1 {
+        return path.to_path_buf();
+    }
+    match std::env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mate_in_range() {
+        let args = Args::parse_from(["tsume-generator", "--min-mate", "3", "--max-mate", "5"]);
+        let config = args.generator_config(Vec::new());
+        assert!(!config.mate_in_range(1));
+        assert!(config.mate_in_range(3));
+        assert!(config.mate_in_range(5));
+        assert!(!config.mate_in_range(7));
+
+        let args = Args::parse_from(["tsume-generator"]);
+        assert!(args.generator_config(Vec::new()).mate_in_range(1));
+    }
+
+    #[test]
+    fn test_difficulty_filters() {
+        let args = Args::parse_from(["tsume-generator", "--min-difficulty", "2.5"]);
+        assert!(args.rates_puzzles());
+        let config = args.generator_config(Vec::new());
+        assert!(!config.difficulty_in_range(2.0));
+        assert!(config.difficulty_in_range(7.0));
+
+        let args = Args::parse_from(["tsume-generator"]);
+        assert!(!args.rates_puzzles());
+        let args = Args::parse_from(["tsume-generator", "--format", "jsonl", "--verify-ms", "0"]);
+        assert!(!args.rates_puzzles());
+    }
+
+    #[test]
+    fn test_piece_filters() {
+        let args = Args::parse_from([
+            "tsume-generator",
+            "--max-total-pieces",
+            "6",
+            "--require-piece",
+            "bishop",
+            "--require-piece",
+            "gold",
+        ]);
+        assert_eq!(args.max_total_pieces, Some(6));
+        assert_eq!(args.max_defender_pieces, None);
+        assert_eq!(args.require_piece, [PieceType::Bishop, PieceType::Gold]);
+    }
+
+    #[test]
+    fn test_search_budget() {
+        let config = Args::parse_from(["tsume-generator"]).generator_config(Vec::new());
+        assert_eq!(config.search, SearchBudget::default());
+        assert_eq!(config.resign_retry_factor, RESIGN_RETRY_FACTOR);
+
+        let args = Args::parse_from([
+            "tsume-generator",
+            "--search-ms",
+            "20",
+            "--endgame-search-ms",
+            "100",
+            "--endgame-ply",
+            "40",
+            "--resign-retry-factor",
+            "3",
+        ]);
+        let config = args.generator_config(Vec::new());
+        assert_eq!(config.search.search_ms, 20);
+        assert_eq!(config.search.endgame_ms, Some(100));
+        assert_eq!(config.search.endgame_ply, 40);
+        assert_eq!(config.search.endgame_material, ENDGAME_MATERIAL);
+        assert_eq!(config.resign_retry_factor, 3);
+
+        // The endgame bounds mean nothing without an endgame time
+        assert!(Args::try_parse_from(["tsume-generator", "--endgame-ply", "40"]).is_err());
+        assert!(Args::try_parse_from(["tsume-generator", "--search-ms", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_selfplay_mode() {
+        let args = Args::parse_from(["tsume-generator"]);
+        assert_eq!(args.mode, GenerationMode::Weak);
+        assert_eq!((args.random_plies(), args.attempts()), (0, MAX_ATTEMPTS));
+
+        let args = Args::parse_from(["tsume-generator", "--mode", "selfplay"]);
+        assert_eq!(args.white_strategy(), Strategy::Best);
+        assert_eq!(
+            (args.random_plies(), args.attempts()),
+            (SELFPLAY_RANDOM_PLIES, SELFPLAY_ATTEMPTS)
+        );
+        let config = args.generator_config(Vec::new());
+        assert_eq!(config.mode, GenerationMode::Selfplay);
+        assert_eq!(config.escalation.attempts, SELFPLAY_ATTEMPTS);
+
+        let args = Args::parse_from([
+            "tsume-generator",
+            "--mode",
+            "selfplay",
+            "--random-plies",
+            "2",
+            "--attempts",
+            "5",
+        ]);
+        assert_eq!((args.random_plies(), args.attempts()), (2, 5));
+    }
+
+    #[test]
+    fn test_merge_command() {
+        let args = Args::parse_from([
+            "tsume-generator",
+            "merge",
+            "a.sfen",
+            "b.sfen",
+            "--output",
+            "all.sfen",
+        ]);
+        let Some(Command::Merge { inputs, output }) = args.command else {
+            panic!("merge was given");
+        };
+        assert_eq!(inputs, [Path::new("a.sfen"), Path::new("b.sfen")]);
+        assert_eq!(output, Path::new("all.sfen"));
+        assert!(
+            Args::try_parse_from(["tsume-generator", "merge", "--output", "all.sfen"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_mine_command() {
+        let args = Args::parse_from([
+            "tsume-generator",
+            "--max-mate",
+            "3",
+            "mine",
+            "--games",
+            "games.txt",
+        ]);
+        assert!(
+            matches!(&args.command, Some(Command::Mine { games }) if games == Path::new("games.txt"))
+        );
+        assert_eq!(args.max_mate, Some(3));
+        assert_eq!(
+            args.generator_config(Vec::new()).mode,
+            GenerationMode::Mined
+        );
+
+        // Mined is not a mode one can ask for
+        assert!(Args::try_parse_from(["tsume-generator", "--mode", "mined"]).is_err());
+        assert!(Args::try_parse_from(["tsume-generator", "mine"]).is_err());
+    }
+
+    #[test]
+    fn test_white_strategy() {
+        let args = Args::parse_from(["tsume-generator"]);
+        assert_eq!(args.white_strategy(), Strategy::Worst);
+
+        let args = Args::parse_from(["tsume-generator", "--temperature", "80"]);
+        assert_eq!(args.white_strategy(), Strategy::SampledWorst(80));
+
+        let args = Args::parse_from(["tsume-generator", "--weak-mode", "skill"]);
+        assert_eq!(
+            args.white_strategy(),
+            Strategy::Weakened(Strength::SkillLevel(0))
+        );
+
+        let args = Args::parse_from([
+            "tsume-generator",
+            "--weak-mode",
+            "skill",
+            "--weak-elo",
+            "1200",
+        ]);
+        assert_eq!(
+            args.white_strategy(),
+            Strategy::Weakened(Strength::Elo(1200))
+        );
+
+        assert!(
+            Args::try_parse_from([
+                "tsume-generator",
+                "--weak-elo",
+                "1200",
+                "--skill-level",
+                "3"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_two_engines() {
+        let args = Args::parse_from(["tsume-generator", "--engine-black", "/opt/strong"]);
+        assert_eq!(args.engine_config().engine, PathBuf::from("/opt/strong"));
+        assert!(args.white_engine_config().is_none());
+        assert_eq!(args.white_strategy(), Strategy::Worst);
+
+        // The same binary on both sides is still a single engine
+        let args = Args::parse_from(["tsume-generator", "--engine-white", "fairy-stockfish"]);
+        assert!(args.white_engine_config().is_none());
+
+        let args = Args::parse_from([
+            "tsume-generator",
+            "--engine-black",
+            "/opt/strong",
+            "--engine-white",
+            "/opt/weak",
+        ]);
+        let white = args.white_engine_config().unwrap();
+        assert_eq!(white.engine, PathBuf::from("/opt/weak"));
+        assert_eq!(white.variants_ini, args.engine_config().variants_ini);
+        assert_eq!(args.white_strategy(), Strategy::Best);
+
+        let args = Args::parse_from([
+            "tsume-generator",
+            "--engine-white",
+            "/opt/weak",
+            "--weak-mode",
+            "worstpv",
+        ]);
+        assert!(args.white_engine_config().is_some());
+        assert_eq!(args.white_strategy(), Strategy::Worst);
+    }
+
+    #[test]
+    fn test_output_format_aliases() {
+        let format = |args: &[&str]| {
+            Args::parse_from(std::iter::once("tsume-generator").chain(args.iter().copied())).format
+        };
+        assert_eq!(format(&[]), OutputFormat::Plain);
+        assert_eq!(format(&["--output-format", "sfen"]), OutputFormat::Plain);
+        assert_eq!(
+            format(&["--output-format", "json-lines"]),
+            OutputFormat::Jsonl
+        );
+        assert_eq!(format(&["--format", "csv"]), OutputFormat::Csv);
+        assert_eq!(
+            format(&["--format", "lichess-csv"]),
+            OutputFormat::LichessCsv
+        );
+    }
+
+    #[test]
+    fn test_shards() {
+        let args = Args::parse_from([
+            "tsume-generator",
+            "results.sfen",
+            "--shards",
+            "16",
+            "--shard-index",
+            "3",
+        ]);
+        assert_eq!(
+            args.output_target(),
+            OutputTarget::File(PathBuf::from("results.0003.sfen"))
+        );
+        assert_eq!(args.shard_seed(7), 7 + (3 << 32));
+
+        let args = Args::parse_from(["tsume-generator", "-", "10"]);
+        assert_eq!(args.output_target(), OutputTarget::Stdout);
+        assert_eq!(args.shard_seed(7), 7);
+    }
+
+    #[test]
+    fn test_work_range() {
+        let args = Args::parse_from(["tsume-generator", "--work-range", "100:200"]);
+        assert_eq!(args.work_range, Some(100..200));
+        assert_eq!(unit_seed(7, 3), 7 + (3 << 32) + 0xffff_ffff);
+
+        // Unit 3 plays none of the games of shard 3's workers
+        let shard = Args::parse_from(["tsume-generator", "--shards", "16", "--shard-index", "3"]);
+        assert!((0..64).all(|worker| unit_seed(7, 3) != shard.shard_seed(7) + worker));
+
+        for range in ["200:100", "5:5", "5", "a:b", "0:4294967297"] {
+            assert!(Args::try_parse_from(["tsume-generator", "--work-range", range]).is_err());
+        }
+        let sharded = ["tsume-generator", "--work-range", "0:10", "--shards", "2"];
+        assert!(Args::try_parse_from(sharded).is_err());
+    }
+
+    #[test]
+    fn test_engine_threads_are_divided_across_jobs() {
+        let args = Args::parse_from([
+            "tsume-generator",
+            "--engine-threads",
+            "8",
+            "--jobs",
+            "3",
+            "--engine-hash-mb",
+            "256",
+        ]);
+        let config = args.engine_config();
+        assert_eq!(config.threads, Some(2));
+        assert_eq!(config.hash_mb, Some(256));
+
+        assert!(args.check_engine_threads().is_ok());
+        let args = Args::parse_from(["tsume-generator", "--engine-threads", "2", "--jobs", "4"]);
+        assert!(args.check_engine_threads().is_err());
+        assert_eq!(
+            Args::parse_from(["tsume-generator"])
+                .engine_config()
+                .threads,
+            None
+        );
+        assert!(Args::try_parse_from(["tsume-generator", "--engine-threads", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_verify_command() {
+        let args = Args::parse_from([
+            "tsume-generator",
+            "verify",
+            "old.sfen",
+            "--output",
+            "checked.jsonl",
+            "--jobs",
+            "4",
+        ]);
+        let Some(Command::Verify { input, output, .. }) = args.command else {
+            panic!("expected the verify command");
+        };
+        assert_eq!(input, PathBuf::from("old.sfen"));
+        assert_eq!(output, PathBuf::from("checked.jsonl"));
+        assert_eq!(args.jobs, 4);
+
+        let args = Args::parse_from(["tsume-generator", "puzzles.sfen", "10"]);
+        assert!(args.command.is_none());
+        assert_eq!(args.output, PathBuf::from("puzzles.sfen"));
+        assert_eq!(args.count, 10);
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            resolve_path(Path::new("fairy-stockfish")),
+            PathBuf::from("fairy-stockfish")
+        );
+        assert_eq!(
+            resolve_path(Path::new("/usr/bin/fairy-stockfish")),
+            PathBuf::from("/usr/bin/fairy-stockfish")
+        );
+        assert_eq!(
+            resolve_path(Path::new("../../variants.ini")),
+            cwd.join("../../variants.ini")
+        );
+    }
+}
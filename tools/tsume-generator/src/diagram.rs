@@ -0,0 +1,97 @@
+//! Plain-text board diagrams for human review.
+
+/// Render the board and hands of a SFEN as an ASCII diagram.
+///
+/// Files are labelled Fairy-Stockfish style (file 1 on the right) and ranks
+/// `a`-`e` from White's back rank down. Uppercase pieces are Black's.
+pub fn render(sfen: &str) -> String {
+    let mut fields = sfen.split_whitespace();
+    let board = fields.next().unwrap_or("");
+    let _side = fields.next();
+    let hand = fields.next().unwrap_or("-");
+
+    let rows: Vec<Vec<String>> = board.split('/').map(parse_row).collect();
+    let files = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+
+    let mut lines = Vec::new();
+    let header: String = (1..=files)
+        .rev()
+        .map(|file| format!("{:>3}", file))
+        .collect();
+    lines.push(header);
+    for (rank, row) in rows.iter().enumerate() {
+        let cells: String = row.iter().map(|cell| format!("{:>3}", cell)).collect();
+        lines.push(format!("{}  {}", cells, (b'a' + rank as u8) as char));
+    }
+    lines.push(format!("hand: {}", hand));
+    lines.join("\n")
+}
+
+/// [`render`] followed by the side to move and the verified mate length, if
+/// known, e.g. `Black to move, mate in 3`.
+pub fn render_puzzle(sfen: &str, mate_in: Option<i32>) -> String {
+    let side = match sfen.split_whitespace().nth(1) {
+        Some("w") => "White",
+        _ => "Black",
+    };
+    match mate_in {
+        Some(plies) => format!("{}\n{} to move, mate in {}", render(sfen), side, plies),
+        None => format!("{}\n{} to move", render(sfen), side),
+    }
+}
+
+/// Split one SFEN rank into cells, expanding empty squares to `.`.
+fn parse_row(row: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut promoted = false;
+    for c in row.chars() {
+        if c == '+' {
+            promoted = true;
+        } else if let Some(empty) = c.to_digit(10) {
+            cells.extend((0..empty).map(|_| ".".to_string()));
+        } else {
+            cells.push(if promoted {
+                format!("+{}", c)
+            } else {
+                c.to_string()
+            });
+            promoted = false;
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_starting_position() {
+        let expected = [
+            "  3  2  1",
+            "  b  k  r  a",
+            "  p  .  p  b",
+            "  .  .  .  c",
+            "  P  .  P  d",
+            "  R  K  B  e",
+            "hand: -",
+        ]
+        .join("\n");
+        assert_eq!(render("bkr/p1p/3/P1P/RKB b - 1"), expected);
+    }
+
+    #[test]
+    fn test_render_promoted_piece_and_hand() {
+        let diagram = render("k2/+P2/3/3/2K b Rb 1");
+        assert!(diagram.contains(" +P  .  .  b"));
+        assert!(diagram.ends_with("hand: Rb"));
+    }
+
+    #[test]
+    fn test_render_puzzle_caption() {
+        let diagram = render_puzzle("1k1/3/R2/3/K1B b G 1", Some(3));
+        assert!(diagram.ends_with("hand: G\nBlack to move, mate in 3"));
+        let diagram = render_puzzle("1kb/3/2r/3/1K1 w g 1", None);
+        assert!(diagram.ends_with("\nWhite to move"));
+    }
+}
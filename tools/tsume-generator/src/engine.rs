@@ -0,0 +1,1236 @@
+//! Fairy-Stockfish engine wrapper.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::time::Duration;
+
+use rand::Rng;
+use rand::seq::IndexedRandom;
+use serde::{Deserialize, Serialize};
+use usi::{
+    BestMoveParams, EngineCommand, GuiCommand, InfoParams, ScoreKind, ThinkParams, UsiEngineHandler,
+};
+
+use crate::variant::Variant;
+
+/// `DrawScore` penalizing draws so that games end decisively
+pub const DRAW_SCORE: i32 = 1000;
+/// MultiPV candidates searched by default, for the weak side and for verification
+pub const MULTIPV_K: usize = 5;
+/// Milliseconds per move search of the games
+pub const SEARCH_TIME_MS: u64 = 10;
+/// Search time multiplier of the retry after a search resigns without a line
+pub const RESIGN_RETRY_FACTOR: u64 = 5;
+/// Score rank of a mate, placed beyond any centipawn evaluation
+const MATE_SCORE: i32 = 100_000;
+/// Scores are clamped to this many centipawns before temperature sampling
+/// so that a mate does not take all of the probability
+const SAMPLING_SCORE_CLAMP: i32 = 2_000;
+
+/// Locations of the engine binary and the variant definitions it loads.
+#[derive(Debug, Clone, Default)]
+pub struct EngineConfig {
+    pub engine: PathBuf,
+    pub variants_ini: PathBuf,
+    pub variant: Variant,
+    /// `Threads` of the engine process; the engine's default if `None`
+    pub threads: Option<usize>,
+    /// `Hash` in MiB; the engine's default if `None`
+    pub hash_mb: Option<usize>,
+}
+
+/// Reasons an engine could not be started.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The variants.ini file does not exist
+    VariantsIniNotFound(PathBuf),
+    /// The engine process could not be started
+    Spawn {
+        config: EngineConfig,
+        source: usi::Error,
+    },
+    /// The engine started but the USI handshake or option setup failed
+    Setup(usi::Error),
+    /// The engine did not accept a configured option
+    Option {
+        name: &'static str,
+        value: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::VariantsIniNotFound(path) => {
+                write!(f, "variants file not found: {}", path.display())
+            }
+            EngineError::Spawn { config, source } => write!(
+                f,
+                "failed to spawn engine {} with variants {}: {}",
+                config.engine.display(),
+                config.variants_ini.display(),
+                source
+            ),
+            EngineError::Setup(source) => write!(f, "engine setup failed: {}", source),
+            EngineError::Option {
+                name,
+                value,
+                message,
+            } => write!(f, "engine rejected {} = {}: {}", name, value, message),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl EngineError {
+    /// Whether trying again could help; a missing variants file will stay missing.
+    pub fn is_transient(&self) -> bool {
+        !matches!(
+            self,
+            EngineError::VariantsIniNotFound(_) | EngineError::Option { .. }
+        )
+    }
+}
+
+/// Ways a running engine can stop being usable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineFailure {
+    /// A search produced no result within the timeout
+    Hung,
+    /// The process exited or closed its pipes
+    Exited,
+}
+
+impl fmt::Display for EngineFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineFailure::Hung => write!(f, "engine stopped responding"),
+            EngineFailure::Exited => write!(f, "engine process exited"),
+        }
+    }
+}
+
+/// Engine evaluation from the perspective of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Score {
+    /// Centipawns
+    Cp(i32),
+    /// Mate in N plies: positive if the side to move mates, negative if it gets mated
+    Mate(i32),
+}
+
+impl Score {
+    /// Comparable value ranking mates beyond any centipawn score, shorter mates first.
+    pub fn rank(self) -> i32 {
+        match self {
+            Score::Cp(cp) => cp,
+            Score::Mate(plies) if plies > 0 => MATE_SCORE - plies,
+            Score::Mate(plies) => -MATE_SCORE - plies,
+        }
+    }
+}
+
+/// Collected info from engine during a search.
+#[derive(Debug, Clone)]
+pub struct PvInfo {
+    pub multipv: i32,
+    pub score: Score,
+    pub moves: Vec<String>,
+}
+
+/// Engine wrapper that maintains communication channels.
+pub struct Engine {
+    handler: UsiEngineHandler,
+    rx: Receiver<EngineCommand>,
+    /// Set once the engine hangs or dies; the engine must then be replaced
+    failure: Option<EngineFailure>,
+    /// Principal variation score of the most recent search
+    last_score: Option<Score>,
+    /// Strength limit currently configured in the engine
+    strength: Option<Strength>,
+    /// `MultiPV` currently configured in the engine
+    multipv: usize,
+    /// `DrawScore` currently configured in the engine
+    draw_score: i32,
+    /// Candidates the worst-move strategies choose among
+    weak_multipv: usize,
+    /// Time of each move search
+    search_ms: u64,
+    /// Multiple of `search_ms` for the retry after a resignation without a line
+    resign_retry_factor: u64,
+    /// `id name` and `id author` from the handshake
+    id: (String, String),
+    /// Complaints about options, e.g. `No such option: Hash`
+    option_errors: Receiver<String>,
+    /// A search started by [`Engine::go_infinite`] has not ended yet
+    searching: bool,
+}
+
+/// Playing-strength limit applied to an engine's searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strength {
+    /// `UCI_LimitStrength` with this `UCI_Elo`
+    Elo(u32),
+    /// `Skill Level` from 0 (weakest) to 20 (full strength)
+    SkillLevel(i32),
+}
+
+impl fmt::Display for Strength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Strength::Elo(elo) => write!(f, "Elo {}", elo),
+            Strength::SkillLevel(level) => write!(f, "Skill Level {}", level),
+        }
+    }
+}
+
+/// Result of a search - either a move or game end
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SearchResult {
+    Move(String),
+    Checkmate,
+    Resign,
+}
+
+impl Engine {
+    pub fn spawn(config: &EngineConfig) -> Result<Self, EngineError> {
+        // Fail early with a clear message: the engine only reports a missing file obliquely
+        if !config.variants_ini.is_file() {
+            return Err(EngineError::VariantsIniNotFound(
+                config.variants_ini.clone(),
+            ));
+        }
+        // Fairy-Stockfish ignores a spin value out of range without a word
+        let options = [("Threads", config.threads), ("Hash", config.hash_mb)];
+        let options: Vec<(&'static str, usize)> = options
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?)))
+            .collect();
+        if !options.is_empty() {
+            // A handshake of its own: the USI handler keeps only the engine's id
+            let ranges = usi_handshake(&config.engine)
+                .map(|lines| spin_option_ranges(&lines))
+                .unwrap_or_default();
+            for &(name, value) in &options {
+                if let Some(&(min, max)) = ranges.get(name)
+                    && !(min..=max).contains(&(value as i64))
+                {
+                    return Err(EngineError::Option {
+                        name,
+                        value: value.to_string(),
+                        message: format!("outside the engine's range {} to {}", min, max),
+                    });
+                }
+            }
+        }
+
+        let engine_path = config.engine.to_string_lossy();
+        let variants_ini = config.variants_ini.to_string_lossy();
+        let mut handler =
+            UsiEngineHandler::spawn(engine_path.as_ref(), ".", &["load", variants_ini.as_ref()])
+                .map_err(|source| EngineError::Spawn {
+                    config: config.clone(),
+                    source,
+                })?;
+
+        // Set protocol to USI before handshake (required for fairy-stockfish)
+        handler
+            .send_command_before_handshake(&GuiCommand::SetOption(
+                "Protocol".to_string(),
+                Some("usi".to_string()),
+            ))
+            .map_err(EngineError::Setup)?;
+
+        // Complete handshake
+        let info = handler.get_info().map_err(EngineError::Setup)?;
+        let id = (info.name().to_string(), info.author().to_string());
+
+        // Set variant AFTER handshake
+        handler
+            .send_command(&GuiCommand::SetOption(
+                "UCI_Variant".to_string(),
+                Some(config.variant.engine_name().to_string()),
+            ))
+            .map_err(EngineError::Setup)?;
+
+        // Set MultiPV for getting multiple moves
+        handler
+            .send_command(&GuiCommand::SetOption(
+                "MultiPV".to_string(),
+                Some(MULTIPV_K.to_string()),
+            ))
+            .map_err(EngineError::Setup)?;
+
+        // Disable contempt for objective play
+        handler
+            .send_command(&GuiCommand::SetOption(
+                "Contempt".to_string(),
+                Some("0".to_string()),
+            ))
+            .map_err(EngineError::Setup)?;
+
+        // Penalize draws heavily to encourage decisive games
+        handler
+            .send_command(&GuiCommand::SetOption(
+                "DrawScore".to_string(),
+                Some(DRAW_SCORE.to_string()),
+            ))
+            .map_err(EngineError::Setup)?;
+
+        // Disable resignation (set to minimum so engine never resigns)
+        handler
+            .send_command(&GuiCommand::SetOption(
+                "ResignValue".to_string(),
+                Some("-32767".to_string()),
+            ))
+            .map_err(EngineError::Setup)?;
+
+        // Analysis mode prevents early exit on mate
+        handler
+            .send_command(&GuiCommand::SetOption(
+                "UCI_AnalyseMode".to_string(),
+                Some("true".to_string()),
+            ))
+            .map_err(EngineError::Setup)?;
+
+        // TsumeMode disables try rule, focuses on checkmate only
+        handler
+            .send_command(&GuiCommand::SetOption(
+                "TsumeMode".to_string(),
+                Some("true".to_string()),
+            ))
+            .map_err(EngineError::Setup)?;
+
+        handler.prepare().map_err(EngineError::Setup)?;
+        handler
+            .send_command(&GuiCommand::UsiNewGame)
+            .map_err(EngineError::Setup)?;
+
+        // Set up listener channel
+        let (tx, rx): (Sender<EngineCommand>, Receiver<EngineCommand>) = channel();
+        let (errors_tx, option_errors) = channel();
+
+        handler
+            .listen(move |output| -> Result<(), std::io::Error> {
+                if output.raw_str().starts_with("No such option") {
+                    let _ = errors_tx.send(output.raw_str().to_string());
+                }
+                if let Some(cmd) = output.response() {
+                    let _ = tx.send(cmd.clone());
+                }
+                Ok(())
+            })
+            .map_err(EngineError::Setup)?;
+
+        let mut engine = Engine {
+            handler,
+            rx,
+            failure: None,
+            last_score: None,
+            strength: None,
+            multipv: MULTIPV_K,
+            draw_score: DRAW_SCORE,
+            weak_multipv: MULTIPV_K,
+            search_ms: SEARCH_TIME_MS,
+            resign_retry_factor: RESIGN_RETRY_FACTOR,
+            id,
+            option_errors,
+            searching: false,
+        };
+        for (name, value) in options {
+            engine.set_option_checked(name, value.to_string())?;
+        }
+        Ok(engine)
+    }
+
+    /// Spawn an engine, retrying transient failures with exponential backoff.
+    ///
+    /// Waits `base_delay * 2^n` before retry `n + 1` and returns the last error
+    /// once `max_attempts` attempts have failed.
+    pub fn spawn_with_retry(
+        config: &EngineConfig,
+        max_attempts: usize,
+        base_delay: Duration,
+    ) -> Result<Self, EngineError> {
+        retry_with_backoff(max_attempts, base_delay, EngineError::is_transient, || {
+            Engine::spawn(config)
+        })
+    }
+
+    /// The engine's `id name`, e.g. `Fairy-Stockfish 14.0.1`.
+    pub fn id_name(&self) -> &str {
+        &self.id.0
+    }
+
+    /// The engine's `id author`.
+    pub fn id_author(&self) -> &str {
+        &self.id.1
+    }
+
+    /// Why the engine became unusable, if it did.
+    pub fn failure(&self) -> Option<EngineFailure> {
+        self.failure
+    }
+
+    /// Send a command, marking the engine as exited if its pipe is closed.
+    fn send(&mut self, command: &GuiCommand) -> Option<()> {
+        let sent = self.handler.send_command(command).ok();
+        if sent.is_none() {
+            self.failure = Some(EngineFailure::Exited);
+        }
+        sent
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) -> Option<()> {
+        self.send(&GuiCommand::SetOption(
+            name.to_string(),
+            Some(value.to_string()),
+        ))
+    }
+
+    /// Set an option and wait until the engine has applied it, failing if
+    /// the engine does not know it.
+    fn set_option_checked(&mut self, name: &'static str, value: String) -> Result<(), EngineError> {
+        let error = |message: String| EngineError::Option {
+            name,
+            value: value.clone(),
+            message,
+        };
+        if self
+            .set_option(name, &value)
+            .and_then(|()| self.sync())
+            .is_none()
+        {
+            let failure = self.failure.unwrap_or(EngineFailure::Exited);
+            return Err(error(failure.to_string()));
+        }
+        match self.option_errors.try_recv() {
+            Ok(message) => Err(error(message)),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Set any engine option between searches, waiting until the engine has
+    /// applied it; `None` sets a button option.
+    ///
+    /// Returns `None` if the option was not applied: the engine failed, see
+    /// [`Engine::failure`], or, with a warning, a search is still running or
+    /// the engine does not know the option. `MultiPV` and `DrawScore` set this
+    /// way are remembered like those of [`Engine::set_multipv`] and
+    /// [`Engine::set_draw_score`].
+    pub fn set_option_dynamic(&mut self, name: &str, value: Option<&str>) -> Option<()> {
+        let shown = value.map_or(String::new(), |value| format!(" = {}", value));
+        if self.searching {
+            eprintln!(
+                "Warning: not setting {}{} during a search; stop it first",
+                name, shown
+            );
+            return None;
+        }
+        self.send(&GuiCommand::SetOption(
+            name.to_string(),
+            value.map(str::to_string),
+        ))?;
+        self.sync()?;
+        if let Ok(message) = self.option_errors.try_recv() {
+            eprintln!("Warning: engine rejected {}{}: {}", name, shown, message);
+            return None;
+        }
+        if name.eq_ignore_ascii_case("MultiPV") {
+            self.multipv = value.and_then(|v| v.parse().ok()).unwrap_or(self.multipv);
+        } else if name.eq_ignore_ascii_case("DrawScore") {
+            self.draw_score = value
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(self.draw_score);
+        }
+        Some(())
+    }
+
+    /// Limit the strength of subsequent searches; `None` restores full strength.
+    ///
+    /// Options are only sent when the limit changes, so alternating sides
+    /// costs a few `setoption` commands per move at most.
+    pub fn set_strength(&mut self, strength: Option<Strength>) -> Option<()> {
+        if strength == self.strength {
+            return Some(());
+        }
+        match strength {
+            None => {
+                self.set_option("UCI_LimitStrength", "false")?;
+                self.set_option("Skill Level", "20")?;
+            }
+            Some(Strength::Elo(elo)) => {
+                self.set_option("Skill Level", "20")?;
+                self.set_option("UCI_LimitStrength", "true")?;
+                self.set_option("UCI_Elo", &elo.to_string())?;
+            }
+            Some(Strength::SkillLevel(level)) => {
+                self.set_option("UCI_LimitStrength", "false")?;
+                self.set_option("Skill Level", &level.to_string())?;
+            }
+        }
+        self.strength = strength;
+        Some(())
+    }
+
+    /// Set the engine's `MultiPV`, waiting until the engine has applied it.
+    ///
+    /// Like [`Engine::set_strength`] the option is only sent when it changes.
+    pub fn set_multipv(&mut self, k: usize) -> Option<()> {
+        let k = k.max(1);
+        if k == self.multipv {
+            return Some(());
+        }
+        self.set_option("MultiPV", &k.to_string())?;
+        self.sync()?;
+        self.multipv = k;
+        Some(())
+    }
+
+    /// Set the engine's `DrawScore`, waiting until the engine has applied it.
+    pub fn set_draw_score(&mut self, draw_score: i32) -> Option<()> {
+        if draw_score == self.draw_score {
+            return Some(());
+        }
+        self.set_option("DrawScore", &draw_score.to_string())?;
+        self.sync()?;
+        self.draw_score = draw_score;
+        Some(())
+    }
+
+    /// Candidates [`EngineInterface::get_worst_move`] and
+    /// [`EngineInterface::get_sampled_worst_move`]
+    /// choose among; searches for them should run with this `MultiPV`.
+    pub fn weak_multipv(&self) -> usize {
+        self.weak_multipv
+    }
+
+    pub fn set_weak_multipv(&mut self, k: usize) {
+        self.weak_multipv = k.max(1);
+    }
+
+    /// Time [`Engine::search`] gives each move, at least 1 ms.
+    pub fn set_search_ms(&mut self, ms: u64) {
+        self.search_ms = ms.max(1);
+    }
+
+    /// Multiple of the search time [`Engine::search`] retries with when the
+    /// engine resigns without a line, at least 1.
+    pub fn set_resign_retry_factor(&mut self, factor: u64) {
+        self.resign_retry_factor = factor.max(1);
+    }
+
+    /// Wait for `readyok` after `isready`, so options sent before take effect.
+    fn sync(&mut self) -> Option<()> {
+        self.send(&GuiCommand::IsReady)?;
+        loop {
+            match self.rx.recv_timeout(Duration::from_secs(30)) {
+                Ok(EngineCommand::ReadyOk) => return Some(()),
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => {
+                    self.failure = Some(EngineFailure::Hung);
+                    return None;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.failure = Some(EngineFailure::Exited);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Ask the engine to exit, killing the process if it does not.
+    pub fn quit(&mut self) {
+        let _ = self.handler.send_command(&GuiCommand::Quit);
+        let _ = self.handler.kill();
+    }
+
+    /// Set the position reached by playing `move_history` from `start_sfen`.
+    pub fn set_position(&mut self, start_sfen: &str, move_history: &[String]) -> Option<()> {
+        // Note: GuiCommand::Position already prepends "position sfen"
+        let sfen = if move_history.is_empty() {
+            start_sfen.to_string()
+        } else {
+            format!("{} moves {}", start_sfen, move_history.join(" "))
+        };
+        self.send(&GuiCommand::Position(sfen))
+    }
+
+    /// Set an arbitrary position (no move history) for analysis.
+    pub fn set_sfen(&mut self, sfen: &str) -> Option<()> {
+        self.send(&GuiCommand::Position(sfen.to_string()))
+    }
+
+    pub fn search_with_time(&mut self, time_ms: u64) -> Option<(Vec<PvInfo>, SearchResult)> {
+        // Start search with time limit
+        let params = ThinkParams::new().byoyomi(Duration::from_millis(time_ms));
+        self.send(&GuiCommand::Go(params))?;
+        self.collect_search()
+    }
+
+    /// Start a search that runs until [`Engine::stop`].
+    pub fn go_infinite(&mut self) -> Option<()> {
+        self.send(&GuiCommand::Go(ThinkParams::new().infinite()))?;
+        self.searching = true;
+        Some(())
+    }
+
+    /// End a search started by [`Engine::go_infinite`], returning what it
+    /// found by then: the PV of every `MultiPV` line and the `bestmove`.
+    pub fn stop(&mut self) -> Option<(Vec<PvInfo>, SearchResult)> {
+        self.send(&GuiCommand::Stop)?;
+        self.collect_search()
+    }
+
+    /// Collect the info lines of the running search until its `bestmove`.
+    fn collect_search(&mut self) -> Option<(Vec<PvInfo>, SearchResult)> {
+        self.searching = false;
+        let mut pv_infos: Vec<PvInfo> = Vec::new();
+        let mut current_multipv: i32 = 1;
+        let mut current_score = Score::Cp(0);
+        let mut current_moves: Vec<String> = Vec::new();
+
+        loop {
+            match self.rx.recv_timeout(Duration::from_secs(30)) {
+                Ok(cmd) => match cmd {
+                    EngineCommand::Info(params) => {
+                        for param in params {
+                            match param {
+                                InfoParams::MultiPv(pv) => {
+                                    current_multipv = pv;
+                                }
+                                InfoParams::Score(score, kind) => {
+                                    current_score = match kind {
+                                        ScoreKind::CpExact
+                                        | ScoreKind::CpLowerbound
+                                        | ScoreKind::CpUpperbound => Score::Cp(score),
+                                        ScoreKind::MateExact
+                                        | ScoreKind::MateSignOnly
+                                        | ScoreKind::MateLowerbound
+                                        | ScoreKind::MateUpperbound => Score::Mate(score),
+                                    };
+                                }
+                                InfoParams::Pv(moves) => {
+                                    current_moves = moves;
+                                }
+                                _ => {}
+                            }
+                        }
+                        // Store PV info when we have moves
+                        if !current_moves.is_empty() {
+                            if let Some(existing) =
+                                pv_infos.iter_mut().find(|p| p.multipv == current_multipv)
+                            {
+                                existing.score = current_score;
+                                existing.moves = current_moves.clone();
+                            } else {
+                                pv_infos.push(PvInfo {
+                                    multipv: current_multipv,
+                                    score: current_score,
+                                    moves: current_moves.clone(),
+                                });
+                            }
+                        }
+                    }
+                    EngineCommand::BestMove(params) => {
+                        let result = match params {
+                            BestMoveParams::MakeMove(mv, _) => SearchResult::Move(mv),
+                            BestMoveParams::Resign => SearchResult::Resign,
+                            BestMoveParams::Win => SearchResult::Checkmate,
+                        };
+                        return Some((pv_infos, result));
+                    }
+                    _ => {}
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    self.failure = Some(EngineFailure::Hung);
+                    return None;
+                }
+                // The listener thread ends when the engine's output closes
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.failure = Some(EngineFailure::Exited);
+                    return None;
+                }
+            }
+        }
+    }
+
+    pub fn search(&mut self) -> Option<(Vec<PvInfo>, SearchResult)> {
+        self.last_score = None;
+
+        // Try with normal time first
+        let (mut pv_infos, mut result) = self.search_with_time(self.search_ms)?;
+
+        // If we got resign with no PV, retry with longer time
+        if matches!(result, SearchResult::Resign) && pv_infos.is_empty() {
+            let time_ms = self.search_ms.saturating_mul(self.resign_retry_factor);
+            (pv_infos, result) = self.search_with_time(time_ms)?;
+        }
+
+        self.last_score = pv_infos
+            .iter()
+            .find(|pv| pv.multipv == 1)
+            .map(|pv| pv.score);
+        Some((pv_infos, result))
+    }
+
+    /// Principal variation score of the last move search, if it reported one.
+    pub fn last_score(&self) -> Option<Score> {
+        self.last_score
+    }
+}
+
+/// What game simulation needs from an engine: a position, a search and the
+/// options the move strategies set. [`Engine`] answers over USI; a
+/// [`MockEngine`](crate::mock::MockEngine) replays scripted searches.
+///
+/// The move choices built on [`EngineInterface::search`] come with it.
+pub trait EngineInterface {
+    /// Set the position reached by playing `move_history` from `start_sfen`.
+    fn set_position(&mut self, start_sfen: &str, move_history: &[String]) -> Option<()>;
+
+    fn search(&mut self) -> Option<(Vec<PvInfo>, SearchResult)>;
+
+    /// Time each following search gets, for engines searching on a clock.
+    fn set_search_ms(&mut self, _ms: u64) {}
+
+    /// Principal variation score of the last search, if it reported one.
+    fn last_score(&self) -> Option<Score> {
+        None
+    }
+
+    /// Why the engine became unusable, if it did.
+    fn failure(&self) -> Option<EngineFailure> {
+        None
+    }
+
+    /// Limit the strength of subsequent searches; `None` restores full strength.
+    fn set_strength(&mut self, _strength: Option<Strength>) -> Option<()> {
+        Some(())
+    }
+
+    fn set_multipv(&mut self, _k: usize) -> Option<()> {
+        Some(())
+    }
+
+    /// Search the current position once for `time_ms`, as verification does.
+    ///
+    /// By default the time is set with [`EngineInterface::set_search_ms`]
+    /// and kept for the searches after.
+    fn search_with_time(&mut self, time_ms: u64) -> Option<(Vec<PvInfo>, SearchResult)> {
+        self.set_search_ms(time_ms);
+        self.search()
+    }
+
+    /// Score the engine gives a draw, in centipawns.
+    fn set_draw_score(&mut self, _draw_score: i32) -> Option<()> {
+        Some(())
+    }
+
+    /// Multiple of the search time to retry with after a resignation without a line.
+    fn set_resign_retry_factor(&mut self, _factor: u64) {}
+
+    /// Candidates the worst-move choices pick among.
+    fn weak_multipv(&self) -> usize {
+        MULTIPV_K
+    }
+
+    fn set_weak_multipv(&mut self, _k: usize) {}
+
+    fn get_best_move(&mut self) -> Option<SearchResult> {
+        let (pv_infos, result) = self.search()?;
+
+        // Always prefer PV info regardless of result
+        if let Some(pv1) = pv_infos.iter().find(|pv| pv.multipv == 1)
+            && let Some(mv) = pv1.moves.first()
+        {
+            return Some(SearchResult::Move(mv.clone()));
+        }
+
+        // Fallback to bestmove if PV empty
+        match result {
+            SearchResult::Move(best_move) => Some(SearchResult::Move(best_move)),
+            SearchResult::Checkmate => Some(SearchResult::Checkmate),
+            SearchResult::Resign => None, // No move available
+        }
+    }
+
+    fn get_worst_move(&mut self) -> Option<SearchResult> {
+        self.get_worst_move_among(self.weak_multipv())
+    }
+
+    /// Play the engine's own `bestmove`.
+    ///
+    /// With a strength limit the engine deliberately picks a weaker move as
+    /// `bestmove` while its info lines still show the best line.
+    fn get_engine_move(&mut self) -> Option<SearchResult> {
+        let (pv_infos, result) = self.search()?;
+
+        match result {
+            SearchResult::Move(best_move) => Some(SearchResult::Move(best_move)),
+            SearchResult::Checkmate => Some(SearchResult::Checkmate),
+            // Fall back to the principal variation if the engine resigned anyway
+            SearchResult::Resign => pv_infos
+                .iter()
+                .find(|pv| pv.multipv == 1)
+                .and_then(|pv| pv.moves.first())
+                .map(|mv| SearchResult::Move(mv.clone())),
+        }
+    }
+
+    /// Pick the lowest-scoring move among the engine's top `k` candidates.
+    fn get_worst_move_among(&mut self, k: usize) -> Option<SearchResult> {
+        let (pv_infos, result) = self.search()?;
+
+        // Always prefer PV info - pick worst scoring move
+        let worst_move = pv_infos
+            .iter()
+            .filter(|pv| !pv.moves.is_empty() && pv.multipv as usize <= k)
+            .min_by_key(|pv| pv.score.rank())
+            .and_then(|pv| pv.moves.first().cloned());
+
+        if let Some(mv) = worst_move {
+            return Some(SearchResult::Move(mv));
+        }
+
+        // Fallback to bestmove if PV empty
+        match result {
+            SearchResult::Move(best_move) => Some(SearchResult::Move(best_move)),
+            SearchResult::Checkmate => Some(SearchResult::Checkmate),
+            SearchResult::Resign => None, // No move available
+        }
+    }
+
+    /// Sample among the engine's top candidates, favouring low scores.
+    ///
+    /// A candidate is drawn with probability proportional to
+    /// `exp(-score / temperature)`, scores in centipawns. Temperature 0 plays
+    /// the lowest-scoring move like [`EngineInterface::get_worst_move`].
+    fn get_sampled_worst_move(
+        &mut self,
+        temperature: u32,
+        rng: &mut impl Rng,
+    ) -> Option<SearchResult> {
+        if temperature == 0 {
+            return self.get_worst_move();
+        }
+        let (pv_infos, result) = self.search()?;
+
+        let candidates: Vec<&PvInfo> = pv_infos
+            .iter()
+            .filter(|pv| !pv.moves.is_empty() && pv.multipv as usize <= self.weak_multipv())
+            .collect();
+        let scores: Vec<Score> = candidates.iter().map(|pv| pv.score).collect();
+        let weighted: Vec<(&PvInfo, f64)> = candidates
+            .into_iter()
+            .zip(badness_weights(&scores, temperature))
+            .collect();
+        if let Ok((pv, _)) = weighted.choose_weighted(rng, |(_, weight)| *weight) {
+            return Some(SearchResult::Move(pv.moves[0].clone()));
+        }
+
+        // Fallback to bestmove if PV empty
+        match result {
+            SearchResult::Move(best_move) => Some(SearchResult::Move(best_move)),
+            SearchResult::Checkmate => Some(SearchResult::Checkmate),
+            SearchResult::Resign => None, // No move available
+        }
+    }
+
+    /// Pick uniformly among the first moves of all MultiPV candidates.
+    fn get_random_move(&mut self, rng: &mut impl Rng) -> Option<SearchResult> {
+        let (pv_infos, result) = self.search()?;
+
+        let candidates: Vec<&String> = pv_infos.iter().filter_map(|pv| pv.moves.first()).collect();
+        if let Some(mv) = candidates.choose(rng) {
+            return Some(SearchResult::Move((*mv).clone()));
+        }
+
+        // Fallback to bestmove if PV empty
+        match result {
+            SearchResult::Move(best_move) => Some(SearchResult::Move(best_move)),
+            SearchResult::Checkmate => Some(SearchResult::Checkmate),
+            SearchResult::Resign => None, // No move available
+        }
+    }
+}
+
+impl EngineInterface for Engine {
+    fn set_position(&mut self, start_sfen: &str, move_history: &[String]) -> Option<()> {
+        Engine::set_position(self, start_sfen, move_history)
+    }
+
+    fn search(&mut self) -> Option<(Vec<PvInfo>, SearchResult)> {
+        Engine::search(self)
+    }
+
+    fn set_search_ms(&mut self, ms: u64) {
+        Engine::set_search_ms(self, ms)
+    }
+
+    fn last_score(&self) -> Option<Score> {
+        Engine::last_score(self)
+    }
+
+    fn failure(&self) -> Option<EngineFailure> {
+        Engine::failure(self)
+    }
+
+    fn set_strength(&mut self, strength: Option<Strength>) -> Option<()> {
+        Engine::set_strength(self, strength)
+    }
+
+    fn set_multipv(&mut self, k: usize) -> Option<()> {
+        Engine::set_multipv(self, k)
+    }
+
+    fn search_with_time(&mut self, time_ms: u64) -> Option<(Vec<PvInfo>, SearchResult)> {
+        Engine::search_with_time(self, time_ms)
+    }
+
+    fn set_draw_score(&mut self, draw_score: i32) -> Option<()> {
+        Engine::set_draw_score(self, draw_score)
+    }
+
+    fn set_resign_retry_factor(&mut self, factor: u64) {
+        Engine::set_resign_retry_factor(self, factor)
+    }
+
+    fn weak_multipv(&self) -> usize {
+        Engine::weak_multipv(self)
+    }
+
+    fn set_weak_multipv(&mut self, k: usize) {
+        Engine::set_weak_multipv(self, k)
+    }
+}
+
+/// Name and version the engine at `path` reports in its `id name` line,
+/// e.g. `Fairy-Stockfish 14.0.1`.
+///
+/// Only the USI handshake is run, so no variants file is needed.
+pub fn check_fairy_stockfish_version(path: &Path) -> Result<String, io::Error> {
+    let lines = usi_handshake(path)?;
+    let version = lines
+        .iter()
+        .find_map(|line| line.strip_prefix("id name "))
+        .map(|name| name.trim().to_string());
+    version.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "engine did not report an id name",
+        )
+    })
+}
+
+/// Lines the engine at `path` answers `usi` with, up to `usiok`.
+///
+/// Only the handshake is run, so no variants file is needed.
+fn usi_handshake(path: &Path) -> Result<Vec<String>, io::Error> {
+    let mut child = Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let (Some(mut stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        return Err(io::Error::other("engine pipes unavailable"));
+    };
+    writeln!(stdin, "usi")?;
+
+    let mut lines = Vec::new();
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        if line.trim() == "usiok" {
+            break;
+        }
+        lines.push(line.trim().to_string());
+    }
+    // The engine may already be gone; the handshake is all that matters
+    let _ = writeln!(stdin, "quit");
+    drop(stdin);
+    let _ = child.wait();
+    Ok(lines)
+}
+
+/// `(min, max)` of each spin option advertised in handshake `lines`, by
+/// name, e.g. `option name Hash type spin default 16 min 1 max 33554432`.
+fn spin_option_ranges(lines: &[String]) -> HashMap<String, (i64, i64)> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let (name, rest) = line
+                .strip_prefix("option name ")?
+                .split_once(" type spin ")?;
+            let tokens: Vec<&str> = rest.split_whitespace().collect();
+            let bound = |key: &str| {
+                let at = tokens.iter().position(|token| *token == key)?;
+                tokens.get(at + 1)?.parse().ok()
+            };
+            Some((name.to_string(), (bound("min")?, bound("max")?)))
+        })
+        .collect()
+}
+
+/// Softmax weights over negated, clamped scores; the lowest score weighs 1.
+fn badness_weights(scores: &[Score], temperature: u32) -> Vec<f64> {
+    let clamped: Vec<i32> = scores
+        .iter()
+        .map(|score| {
+            score
+                .rank()
+                .clamp(-SAMPLING_SCORE_CLAMP, SAMPLING_SCORE_CLAMP)
+        })
+        .collect();
+    let lowest = clamped.iter().copied().min().unwrap_or(0);
+    clamped
+        .iter()
+        .map(|&score| (-f64::from(score - lowest) / f64::from(temperature)).exp())
+        .collect()
+}
+
+/// Call `attempt` until it succeeds, sleeping `base_delay * 2^n` after failure `n`.
+///
+/// Stops early on errors `retryable` rejects. Each failure is logged.
+fn retry_with_backoff<T, E: fmt::Display>(
+    max_attempts: usize,
+    base_delay: Duration,
+    retryable: impl Fn(&E) -> bool,
+    mut attempt: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut n = 0;
+    loop {
+        match attempt() {
+            Ok(value) => return Ok(value),
+            Err(e) if n + 1 >= max_attempts || !retryable(&e) => return Err(e),
+            Err(e) => {
+                let delay = base_delay * 2u32.saturating_pow(n as u32);
+                eprintln!(
+                    "Attempt {} of {} failed: {}; retrying in {:?}",
+                    n + 1,
+                    max_attempts,
+                    e,
+                    delay
+                );
+                std::thread::sleep(delay);
+                n += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_fairy_stockfish_version() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let version = check_fairy_stockfish_version(&fixtures.join("version-engine.sh")).unwrap();
+        assert_eq!(version, "Fairy-Stockfish 14.0.1");
+
+        assert!(check_fairy_stockfish_version(&fixtures.join("missing-engine.sh")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rejected_option_fails_spawn() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let config = EngineConfig {
+            engine: fixtures.join("options-engine.sh"),
+            variants_ini: fixtures.join("variants.ini"),
+            threads: Some(4),
+            ..EngineConfig::default()
+        };
+        Engine::spawn(&config).unwrap().quit();
+
+        let config = EngineConfig {
+            hash_mb: Some(64),
+            ..config
+        };
+        let err = Engine::spawn(&config).err();
+        assert!(matches!(
+            err,
+            Some(EngineError::Option { name: "Hash", .. })
+        ));
+        assert!(!err.unwrap().is_transient());
+
+        // Out of the advertised range, which the engine would silently ignore
+        let config = EngineConfig {
+            threads: Some(8),
+            hash_mb: None,
+            ..config
+        };
+        let err = Engine::spawn(&config).err().unwrap();
+        assert!(matches!(
+            err,
+            EngineError::Option {
+                name: "Threads",
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("range 1 to 4"));
+    }
+
+    #[test]
+    fn test_spin_option_ranges() {
+        let lines = [
+            "id name Fairy-Stockfish",
+            "option name Hash type spin default 16 min 1 max 33554432",
+            "option name Skill Level type spin default 20 min -20 max 20",
+            "option name Ponder type check default false",
+        ]
+        .map(String::from);
+        let ranges = spin_option_ranges(&lines);
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges["Hash"], (1, 33_554_432));
+        assert_eq!(ranges["Skill Level"], (-20, 20));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stop_ends_an_infinite_search() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let mut engine = Engine::spawn(&EngineConfig {
+            engine: fixtures.join("infinite-engine.sh"),
+            variants_ini: fixtures.join("variants.ini"),
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        engine.set_sfen("bkr/p1p/3/P1P/RKB b - 1").unwrap();
+        engine.go_infinite().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+
+        let started = std::time::Instant::now();
+        let (pv_infos, result) = engine.stop().unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert_eq!(result, SearchResult::Move("1e2d".to_string()));
+        assert_eq!(pv_infos.len(), 2);
+        assert_eq!(pv_infos[0].moves, ["1e2d", "2a2b"]);
+        engine.quit();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_option_dynamic() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let spawn = |name: &str| {
+            Engine::spawn(&EngineConfig {
+                engine: fixtures.join(name),
+                variants_ini: fixtures.join("variants.ini"),
+                ..EngineConfig::default()
+            })
+            .unwrap()
+        };
+        let start = "bkr/p1p/3/P1P/RKB b - 1";
+
+        let mut engine = spawn("multipv-engine.sh");
+        engine.set_sfen(start).unwrap();
+        assert_eq!(engine.search().unwrap().0.len(), 4);
+        engine.set_option_dynamic("MultiPV", Some("1")).unwrap();
+        engine.set_sfen(start).unwrap();
+        assert_eq!(engine.search().unwrap().0.len(), 1);
+        // The cached value follows, so going back to 5 is sent
+        engine.set_multipv(MULTIPV_K).unwrap();
+        engine.set_sfen(start).unwrap();
+        assert_eq!(engine.search().unwrap().0.len(), 4);
+        engine.quit();
+
+        // Refused while a search runs, without failing the engine
+        let mut engine = spawn("infinite-engine.sh");
+        engine.set_sfen(start).unwrap();
+        engine.go_infinite().unwrap();
+        assert_eq!(engine.set_option_dynamic("MultiPV", Some("1")), None);
+        assert_eq!(engine.failure(), None);
+        assert!(engine.stop().is_some());
+        assert!(engine.set_option_dynamic("MultiPV", Some("1")).is_some());
+        engine.quit();
+    }
+
+    #[test]
+    fn test_badness_weights() {
+        let weights = badness_weights(&[Score::Cp(50), Score::Cp(0), Score::Cp(-300)], 100);
+        assert_eq!(weights[2], 1.0);
+        assert!(weights[0] < weights[1] && weights[1] < weights[2]);
+
+        // A mate is clamped, so the other candidates keep a usable weight
+        let weights = badness_weights(&[Score::Mate(-1), Score::Cp(0)], 1000);
+        assert_eq!(weights[0], 1.0);
+        assert!(weights[1] > 0.1);
+    }
+
+    #[test]
+    fn test_retry_recovers_after_failures() {
+        let mut calls = 0;
+        let result = retry_with_backoff(
+            5,
+            Duration::from_millis(1),
+            |_: &String| true,
+            || {
+                calls += 1;
+                if calls < 3 {
+                    Err(format!("spawn failure {}", calls))
+                } else {
+                    Ok(calls)
+                }
+            },
+        );
+        assert_eq!(result, Ok(3));
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_retry_gives_up() {
+        let mut calls = 0;
+        let result: Result<(), String> = retry_with_backoff(
+            3,
+            Duration::from_millis(1),
+            |_| true,
+            || {
+                calls += 1;
+                Err("down".to_string())
+            },
+        );
+        assert_eq!(result, Err("down".to_string()));
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result: Result<(), String> = retry_with_backoff(
+            3,
+            Duration::from_millis(1),
+            |_| false,
+            || {
+                calls += 1;
+                Err("fatal".to_string())
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_missing_variants_file_is_not_retried() {
+        let config = EngineConfig {
+            engine: PathBuf::from("fairy-stockfish"),
+            variants_ini: PathBuf::from("/nonexistent/variants.ini"),
+            variant: Variant::Wildcatshogi,
+            ..EngineConfig::default()
+        };
+        let err = Engine::spawn_with_retry(&config, 3, Duration::from_secs(60)).err();
+        assert!(matches!(err, Some(EngineError::VariantsIniNotFound(_))));
+    }
+
+    #[test]
+    fn test_score_rank_orders_mates_beyond_cp() {
+        assert!(Score::Mate(1).rank() > Score::Mate(3).rank());
+        assert!(Score::Mate(3).rank() > Score::Cp(5000).rank());
+        assert!(Score::Cp(-5000).rank() > Score::Mate(-5).rank());
+        assert!(Score::Mate(-5).rank() > Score::Mate(-1).rank());
+    }
+}
@@ -0,0 +1,374 @@
+//! Static evaluation of positions without the engine.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use shogi::Color;
+use shogi::wildcatshogi::Position;
+
+use crate::material::{count_material, piece_value};
+use crate::movegen::mobility;
+use crate::training::{FEATURE_SIZE, SQUARES, WHITE_OFFSET, encode_board, feature_vector};
+use crate::variant::Variant;
+
+/// Board the squares are laid out on
+const VARIANT: Variant = Variant::Wildcatshogi;
+/// Piece letters of the [`EvalWeights`] tables, in their order
+const WEIGHTED_PIECES: [char; 4] = ['R', 'B', 'G', 'P'];
+
+/// Scores a position in centipawns for the side to move.
+pub trait Evaluator {
+    fn eval(&self, position: &Position) -> i32;
+}
+
+/// Piece-square bonuses for Black, indexed by piece code minus 1 (K, R, B, G,
+/// P, promoted P) and square in SFEN order; White uses them rotated 180°.
+const PIECE_SQUARE: [[i32; SQUARES]; 6] = [
+    // King: safest on its home rank
+    [
+        -30, -30, -30, //
+        -20, -20, -20, //
+        -10, -10, -10, //
+        0, 0, 0, //
+        10, 20, 10,
+    ],
+    // Rook
+    [0; SQUARES],
+    // Bishop: the centre file sees both diagonals
+    [
+        0, 10, 0, //
+        0, 10, 0, //
+        0, 10, 0, //
+        0, 10, 0, //
+        0, 10, 0,
+    ],
+    // Gold
+    [
+        20, 20, 20, //
+        20, 30, 20, //
+        10, 20, 10, //
+        0, 10, 0, //
+        0, 0, 0,
+    ],
+    // Pawn: worth more the closer it is to promotion
+    [
+        0, 0, 0, //
+        40, 40, 40, //
+        20, 20, 20, //
+        0, 0, 0, //
+        0, 0, 0,
+    ],
+    // Promoted pawn
+    [
+        20, 20, 20, //
+        20, 30, 20, //
+        10, 20, 10, //
+        0, 10, 0, //
+        0, 0, 0,
+    ],
+];
+
+/// Material from [`count_material`] plus piece-square bonuses.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaterialEvaluator;
+
+impl Evaluator for MaterialEvaluator {
+    fn eval(&self, position: &Position) -> i32 {
+        let sfen = position.to_sfen();
+        let (black, white) = count_material(position);
+        let board = sfen
+            .split_whitespace()
+            .next()
+            .and_then(encode_board)
+            .unwrap_or([0; SQUARES]);
+        let placement: i32 = board
+            .iter()
+            .enumerate()
+            .filter(|(_, code)| **code != 0)
+            .map(|(square, code)| {
+                let piece = (*code as usize - 1) % WHITE_OFFSET as usize;
+                if *code < WHITE_OFFSET {
+                    PIECE_SQUARE[piece][square]
+                } else {
+                    -PIECE_SQUARE[piece][SQUARES - 1 - square]
+                }
+            })
+            .sum();
+        for_side_to_move(&sfen, black - white + placement)
+    }
+}
+
+/// Linear model over [`feature_vector`]: the dot product with one weight per
+/// feature, in centipawns for Black.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NnueEvaluator {
+    pub weights: Vec<f32>,
+}
+
+impl NnueEvaluator {
+    /// Read weights saved by [`NnueEvaluator::save`]: [`FEATURE_SIZE`]
+    /// little-endian `f32` values.
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        let bytes = fs::read(path)?;
+        if bytes.len() != FEATURE_SIZE * 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "expected {} weights, found {} bytes",
+                    FEATURE_SIZE,
+                    bytes.len()
+                ),
+            ));
+        }
+        let weights = bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().expect("chunk of 4 bytes")))
+            .collect();
+        Ok(NnueEvaluator { weights })
+    }
+
+    pub fn save(weights: &[f32], path: &Path) -> io::Result<()> {
+        let bytes: Vec<u8> = weights.iter().flat_map(|w| w.to_le_bytes()).collect();
+        fs::write(path, bytes)
+    }
+}
+
+impl Evaluator for NnueEvaluator {
+    fn eval(&self, position: &Position) -> i32 {
+        let score: f32 = feature_vector(position)
+            .iter()
+            .zip(&self.weights)
+            .map(|(feature, weight)| feature * weight)
+            .sum();
+        for_side_to_move(&position.to_sfen(), score.round() as i32)
+    }
+}
+
+/// Tunable parameters of a hand-written evaluation, saved as JSON.
+///
+/// The tables are indexed by [`WEIGHTED_PIECES`]: rook, bishop, gold and
+/// pawn. A promoted pawn moves as a gold and is weighted as one; the king
+/// has neither a value nor a table, as it never leaves the board.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvalWeights {
+    /// Centipawns per piece, on the board or in hand
+    pub material: [i32; 4],
+    /// Piece-square bonuses for Black in SFEN order; White's are rotated 180°
+    pub pst: [[i32; SQUARES]; 4],
+    /// Centipawns per legal move more than the opponent has
+    pub mobility_weight: i32,
+    /// Centipawns per own piece next to the own king
+    pub king_safety_weight: i32,
+}
+
+impl Default for EvalWeights {
+    /// The values of [`MaterialEvaluator`], with small mobility and king
+    /// safety terms on top.
+    fn default() -> Self {
+        EvalWeights {
+            material: WEIGHTED_PIECES.map(piece_value),
+            pst: [
+                PIECE_SQUARE[1],
+                PIECE_SQUARE[2],
+                PIECE_SQUARE[3],
+                PIECE_SQUARE[4],
+            ],
+            mobility_weight: 10,
+            king_safety_weight: 15,
+        }
+    }
+}
+
+impl EvalWeights {
+    pub fn load(path: &Path) -> Result<Self, serde_json::Error> {
+        let text = fs::read_to_string(path).map_err(serde_json::Error::io)?;
+        serde_json::from_str(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Table index of a board piece code, `None` for the king.
+    fn index(code: u8) -> Option<usize> {
+        match (code - 1) % WHITE_OFFSET {
+            0 => None,
+            // Promoted pawn
+            5 => Some(2),
+            piece => Some(piece as usize - 1),
+        }
+    }
+}
+
+impl Evaluator for EvalWeights {
+    fn eval(&self, position: &Position) -> i32 {
+        let sfen = position.to_sfen();
+        let mut fields = sfen.split_whitespace();
+        let board = fields.next().and_then(encode_board).unwrap_or([0; SQUARES]);
+        let hand = fields.nth(1).unwrap_or("-");
+
+        let mut score = 0;
+        for (square, &code) in board.iter().enumerate().filter(|(_, code)| **code != 0) {
+            let Some(piece) = EvalWeights::index(code) else {
+                continue;
+            };
+            if code < WHITE_OFFSET {
+                score += self.material[piece] + self.pst[piece][square];
+            } else {
+                score -= self.material[piece] + self.pst[piece][SQUARES - 1 - square];
+            }
+        }
+        let mut count = 0;
+        for c in hand.chars() {
+            if let Some(digit) = c.to_digit(10) {
+                count = count * 10 + digit as i32;
+            } else if let Some(piece) = WEIGHTED_PIECES
+                .iter()
+                .position(|p| *p == c.to_ascii_uppercase())
+            {
+                let value = count.max(1) * self.material[piece];
+                score += if c.is_ascii_uppercase() {
+                    value
+                } else {
+                    -value
+                };
+                count = 0;
+            }
+        }
+
+        let moves = |color| mobility(position, color) as i32;
+        score += self.mobility_weight * (moves(Color::Black) - moves(Color::White));
+        score += self.king_safety_weight * (king_guards(&board, 1) - king_guards(&board, 9));
+        for_side_to_move(&sfen, score)
+    }
+}
+
+/// Pieces of the king coded `king` on the squares next to it.
+fn king_guards(board: &[u8; SQUARES], king: u8) -> i32 {
+    let files = VARIANT.files() as usize;
+    let Some(square) = board.iter().position(|&code| code == king) else {
+        return 0;
+    };
+    let (row, col) = ((square / files) as isize, (square % files) as isize);
+    let own =
+        |code: u8| code != 0 && code != king && (code < WHITE_OFFSET) == (king < WHITE_OFFSET);
+    board
+        .iter()
+        .enumerate()
+        .filter(|(other, code)| {
+            let (r, c) = ((other / files) as isize, (other % files) as isize);
+            (r - row).abs() <= 1 && (c - col).abs() <= 1 && own(**code)
+        })
+        .count() as i32
+}
+
+/// Turn a score for Black into one for the side to move of `sfen`.
+fn for_side_to_move(sfen: &str, black_score: i32) -> i32 {
+    if sfen.split_whitespace().nth(1) == Some("w") {
+        -black_score
+    } else {
+        black_score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_material_evaluator() {
+        // The initial position is symmetric
+        assert_eq!(MaterialEvaluator.eval(&Position::startpos()), 0);
+
+        let took_a_rook = Position::from_sfen("bk1/p1p/3/P1P/RKB b R 1").unwrap();
+        assert_eq!(MaterialEvaluator.eval(&took_a_rook), 1000);
+        let white_to_move = Position::from_sfen("bk1/p1p/3/P1P/RKB w R 1").unwrap();
+        assert_eq!(MaterialEvaluator.eval(&white_to_move), -1000);
+
+        // An advanced pawn earns its bonus
+        let advanced = Position::from_sfen("bkr/p1p/P2/2P/RKB b - 1").unwrap();
+        assert_eq!(MaterialEvaluator.eval(&advanced), 20);
+    }
+
+    #[test]
+    fn test_nnue_evaluator_from_random_weights() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let weights: Vec<f32> = (0..FEATURE_SIZE)
+            .map(|_| rng.random_range(-100.0..100.0))
+            .collect();
+        let path = std::env::temp_dir().join(format!("tsume-nnue-{}.bin", std::process::id()));
+        NnueEvaluator::save(&weights, &path).unwrap();
+        let evaluator = NnueEvaluator::load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(evaluator.weights, weights);
+
+        let position = Position::startpos();
+        let expected: f32 = feature_vector(&position)
+            .iter()
+            .zip(&weights)
+            .map(|(feature, weight)| feature * weight)
+            .sum();
+        assert_eq!(evaluator.eval(&position), expected.round() as i32);
+    }
+
+    #[test]
+    fn test_evaluators_share_the_trait() {
+        let evaluators: Vec<Box<dyn Evaluator>> = vec![
+            Box::new(MaterialEvaluator),
+            Box::new(NnueEvaluator {
+                weights: vec![1.0; FEATURE_SIZE],
+            }),
+        ];
+        let position = Position::startpos();
+        let scores: Vec<i32> = evaluators.iter().map(|e| e.eval(&position)).collect();
+        // Ten pieces on the board, none in hand
+        assert_eq!(scores, [0, 10]);
+    }
+
+    #[test]
+    fn test_eval_weights_round_trip() {
+        let weights = EvalWeights::default();
+        let path = std::env::temp_dir().join(format!("tsume-weights-{}.json", std::process::id()));
+        weights.save(&path).unwrap();
+        let loaded = EvalWeights::load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.material, weights.material);
+        assert_eq!(loaded.pst, weights.pst);
+        assert_eq!(loaded.mobility_weight, weights.mobility_weight);
+        assert_eq!(loaded.king_safety_weight, weights.king_safety_weight);
+
+        // The initial position is symmetric
+        assert_eq!(loaded.eval(&Position::startpos()), 0);
+        let took_a_rook = Position::from_sfen("bk1/p1p/3/P1P/RKB b R 1").unwrap();
+        assert!(loaded.eval(&took_a_rook) >= weights.material[0]);
+    }
+
+    #[test]
+    fn test_eval_weights_invalid_json() {
+        let path =
+            std::env::temp_dir().join(format!("tsume-weights-bad-{}.json", std::process::id()));
+        fs::write(&path, r#"{"material": [500, 400"#).unwrap();
+        let err = EvalWeights::load(&path).unwrap_err();
+        let _ = fs::remove_file(&path);
+        // Cut off mid-object
+        assert!(err.is_eof());
+
+        let missing = Path::new("/nonexistent/tsume-weights.json");
+        assert!(EvalWeights::load(missing).unwrap_err().is_io());
+    }
+
+    #[test]
+    fn test_load_rejects_wrong_length() {
+        let path =
+            std::env::temp_dir().join(format!("tsume-nnue-short-{}.bin", std::process::id()));
+        NnueEvaluator::save(&[1.0; 3], &path).unwrap();
+        let err = NnueEvaluator::load(&path).unwrap_err();
+        let _ = fs::remove_file(&path);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,311 @@
+//! Played games, and the source game records written next to the puzzles.
+
+use std::fmt;
+
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::engine::Score;
+use crate::movegen::{in_check, legal_moves};
+use crate::simulator::SimulationResult;
+
+/// Side that won a game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Winner {
+    Black,
+    White,
+}
+
+/// A game with the engine's view of each move.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Game {
+    pub start_sfen: String,
+    /// Moves in library convention
+    pub moves: Vec<String>,
+    /// Score before each move, from the side to move; may be shorter than `moves`
+    pub scores: Vec<Option<Score>>,
+    /// Comment after each move; may be shorter than `moves`
+    pub comments: Vec<Option<String>>,
+    pub metadata: GameMetadata,
+}
+
+/// Who played a game, where and when, and how it ended.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameMetadata {
+    pub black_player: Option<String>,
+    pub white_player: Option<String>,
+    pub event: Option<String>,
+    pub site: Option<String>,
+    /// Start of the game as written in the header, e.g. `2024/05/01 10:00:00`
+    pub date: Option<String>,
+    pub time_control: Option<String>,
+    pub result: Option<Winner>,
+}
+
+/// Builds [`GameMetadata`] one field at a time; unset fields stay empty.
+#[derive(Debug, Clone, Default)]
+pub struct GameMetadataBuilder {
+    metadata: GameMetadata,
+}
+
+/// A finished game that produced a puzzle.
+///
+/// Written as one line: the puzzle id, the result, the search time of each
+/// move if known and a USI position argument, e.g.
+/// `5f0c6b1e2a9d4c37 black-wins search-ms=10,10 sfen bkr/p1p/3/P1P/RKB b - 1 moves 2e2d 3b3c`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    /// Stable id of the puzzle taken from this game
+    pub puzzle_id: String,
+    pub game: Game,
+    pub winner: Winner,
+    /// Search time in milliseconds each move was given; empty if not recorded
+    pub search_ms: Vec<u64>,
+}
+
+impl GameRecord {
+    /// Record of a game that ended in mate after `moves`; the side that played last won.
+    pub fn mated(puzzle_id: String, start_sfen: String, moves: Vec<String>) -> Self {
+        let black_started = start_sfen.split_whitespace().nth(1) != Some("w");
+        let black_moved_last = black_started == (moves.len() % 2 == 1);
+        GameRecord {
+            puzzle_id,
+            game: Game {
+                start_sfen,
+                moves,
+                ..Game::default()
+            },
+            winner: if black_moved_last {
+                Winner::Black
+            } else {
+                Winner::White
+            },
+            search_ms: Vec::new(),
+        }
+    }
+}
+
+impl GameMetadata {
+    pub fn builder() -> GameMetadataBuilder {
+        GameMetadataBuilder::default()
+    }
+
+    /// KIF header lines of the fields that are set, each ending in a newline.
+    ///
+    /// The result has no header line: a KIF file states it after the moves.
+    pub fn to_kif_header(&self) -> String {
+        let fields = [
+            ("開始日時", &self.date),
+            ("棋戦", &self.event),
+            ("場所", &self.site),
+            ("持ち時間", &self.time_control),
+            ("先手", &self.black_player),
+            ("後手", &self.white_player),
+        ];
+        fields
+            .iter()
+            .filter_map(|(key, value)| value.as_ref().map(|value| format!("{}：{}\n", key, value)))
+            .collect()
+    }
+}
+
+impl GameMetadataBuilder {
+    pub fn black_player(mut self, name: impl Into<String>) -> Self {
+        self.metadata.black_player = Some(name.into());
+        self
+    }
+
+    pub fn white_player(mut self, name: impl Into<String>) -> Self {
+        self.metadata.white_player = Some(name.into());
+        self
+    }
+
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.metadata.event = Some(event.into());
+        self
+    }
+
+    pub fn site(mut self, site: impl Into<String>) -> Self {
+        self.metadata.site = Some(site.into());
+        self
+    }
+
+    pub fn date(mut self, date: impl Into<String>) -> Self {
+        self.metadata.date = Some(date.into());
+        self
+    }
+
+    pub fn time_control(mut self, time_control: impl Into<String>) -> Self {
+        self.metadata.time_control = Some(time_control.into());
+        self
+    }
+
+    pub fn result(mut self, winner: Winner) -> Self {
+        self.metadata.result = Some(winner);
+        self
+    }
+
+    pub fn build(self) -> GameMetadata {
+        self.metadata
+    }
+}
+
+impl From<&SimulationResult> for Game {
+    fn from(result: &SimulationResult) -> Self {
+        Game {
+            start_sfen: result.start_sfen.clone(),
+            moves: result.moves.clone(),
+            scores: result.scores.clone(),
+            ..Game::default()
+        }
+    }
+}
+
+impl Game {
+    /// The start position and the position after each move.
+    ///
+    /// Stops at the first move that does not parse or is not legal; empty if
+    /// the start position does not parse.
+    pub fn positions(&self) -> Vec<Position> {
+        let Ok(mut position) = Position::from_sfen(&self.start_sfen) else {
+            return Vec::new();
+        };
+        let mut positions = vec![position.clone()];
+        for text in &self.moves {
+            let Some(mv) = Move::from_sfen(text) else {
+                break;
+            };
+            if position.make_move(mv).is_err() {
+                break;
+            }
+            positions.push(position.clone());
+        }
+        positions
+    }
+}
+
+/// The position before the mating move of a game that ended in checkmate.
+///
+/// The game must replay to its last move, after which the side to move is
+/// in check with no legal move. None for a game that ended any other way,
+/// stalemate included, or that has no moves.
+pub fn tsume_from_game(game: &Game) -> Option<String> {
+    let positions = game.positions();
+    if game.moves.is_empty() || positions.len() != game.moves.len() + 1 {
+        return None;
+    }
+    let mated = positions.last()?;
+    if !legal_moves(mated).is_empty() || !in_check(mated) {
+        return None;
+    }
+    Some(positions[positions.len() - 2].to_sfen())
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = match self.winner {
+            Winner::Black => "black-wins",
+            Winner::White => "white-wins",
+        };
+        write!(f, "{} {} ", self.puzzle_id, result)?;
+        if !self.search_ms.is_empty() {
+            let times: Vec<String> = self.search_ms.iter().map(u64::to_string).collect();
+            write!(f, "search-ms={} ", times.join(","))?;
+        }
+        write!(f, "sfen {}", self.game.start_sfen)?;
+        if !self.game.moves.is_empty() {
+            write!(f, " moves {}", self.game.moves.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_record_line() {
+        let record = GameRecord::mated(
+            "5f0c6b1e2a9d4c37".to_string(),
+            "bkr/p1p/3/P1P/RKB b - 1".to_string(),
+            vec!["2e2d".to_string(), "3b3c".to_string(), "2d2c".to_string()],
+        );
+        assert_eq!(record.winner, Winner::Black);
+        assert_eq!(
+            record.to_string(),
+            "5f0c6b1e2a9d4c37 black-wins sfen bkr/p1p/3/P1P/RKB b - 1 moves 2e2d 3b3c 2d2c"
+        );
+
+        let record = GameRecord::mated(
+            "0000000000000001".to_string(),
+            "bkr/p1p/3/P1P/RKB w - 1".to_string(),
+            vec!["3b3c".to_string()],
+        );
+        assert_eq!(record.winner, Winner::White);
+
+        let record = GameRecord {
+            search_ms: vec![10, 40],
+            ..record
+        };
+        assert_eq!(
+            record.to_string(),
+            "0000000000000001 white-wins search-ms=10,40 sfen bkr/p1p/3/P1P/RKB w - 1 moves 3b3c"
+        );
+    }
+
+    fn game(start_sfen: &str, moves: &[&str]) -> Game {
+        Game {
+            start_sfen: start_sfen.to_string(),
+            moves: moves.iter().map(|mv| mv.to_string()).collect(),
+            ..Game::default()
+        }
+    }
+
+    #[test]
+    fn test_tsume_from_game() {
+        // The rook steps up, the king runs to the corner and the gold drop mates
+        let start = "1k1/3/R2/3/K1B b G 1";
+        let mated = game(start, &["1c1b", "2a3a", "G*2b"]);
+        assert_eq!(
+            tsume_from_game(&mated).as_deref(),
+            Some("2k/R2/3/3/K1B b G 3")
+        );
+
+        assert_eq!(tsume_from_game(&game(start, &["1c1b", "2a3a"])), None);
+        assert_eq!(
+            tsume_from_game(&game(start, &["1c1b", "9z9z", "G*2b"])),
+            None
+        );
+        assert_eq!(tsume_from_game(&game(start, &[])), None);
+        // White's king has no move but is not in check
+        let stalemate = game("k1K/R2/3/3/3 b - 1", &["1b1c"]);
+        assert_eq!(tsume_from_game(&stalemate), None);
+    }
+
+    #[test]
+    fn test_game_metadata_kif_header() {
+        let metadata = GameMetadata::builder()
+            .black_player("Fairy-Stockfish")
+            .white_player("Fairy-Stockfish (MultiPV worst)")
+            .event("Self-play")
+            .site("localhost")
+            .date("2024/05/01 10:00:00")
+            .time_control("100ms per move")
+            .result(Winner::Black)
+            .build();
+        assert_eq!(metadata.result, Some(Winner::Black));
+        assert_eq!(
+            metadata.to_kif_header(),
+            "開始日時：2024/05/01 10:00:00\n\
+             棋戦：Self-play\n\
+             場所：localhost\n\
+             持ち時間：100ms per move\n\
+             先手：Fairy-Stockfish\n\
+             後手：Fairy-Stockfish (MultiPV worst)\n"
+        );
+
+        let partial = GameMetadata::builder().black_player("A").build();
+        assert_eq!(partial.to_kif_header(), "先手：A\n");
+        assert_eq!(GameMetadata::default().to_kif_header(), "");
+    }
+}
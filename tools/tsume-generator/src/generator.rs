@@ -0,0 +1,1120 @@
+//! Puzzle generation: games, verification and filtering on one engine, or on
+//! two when White has an engine of its own.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use rand::Rng;
+use rand::seq::IndexedRandom;
+use serde::{Deserialize, Serialize};
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::engine::{
+    DRAW_SCORE, Engine, EngineInterface, MULTIPV_K, PvInfo, RESIGN_RETRY_FACTOR, Score,
+};
+use crate::games::Winner;
+use crate::material::{PieceType, black_has_piece, board_piece_counts, hand_sizes};
+use crate::mine::GameCollection;
+use crate::openings::{OPENING_PLIES, OpeningBook};
+use crate::output::PuzzleRecord;
+use crate::rating::{estimate_rating, rate_puzzle};
+use crate::rejects::{RejectRecord, RejectStage};
+use crate::sfen::validate_puzzle_sfen;
+use crate::simulator::{
+    GameResult, GameSimulator, MAX_MOVES, SearchBudget, SimulationError, SimulationResult,
+    Strategy, Stuck, Tsume,
+};
+use crate::stats::GameStats;
+use crate::themes::classify;
+use crate::timings::StageTimes;
+use crate::verify::{VerifyResult, verify_defense, verify_puzzle};
+use crate::{position_only_sfen, shutdown};
+
+/// Games played per call to [`TsumeGenerator::find_candidate`] before giving up
+pub const MAX_ATTEMPTS: usize = 10;
+/// [`MAX_ATTEMPTS`] in self-play, where far fewer games end in mate
+pub const SELFPLAY_ATTEMPTS: usize = 50;
+/// Random opening plies in self-play, so that games between two best-move
+/// players do not all repeat one another
+pub const SELFPLAY_RANDOM_PLIES: usize = 6;
+/// Games without a candidate between two raises of the search time
+pub const ESCALATE_EVERY: usize = 3;
+/// Default cap of the raised search time
+pub const MAX_SEARCH_MS: u64 = 80;
+/// Time the `--strictly-forced` walk of one candidate may take, as a multiple
+/// of the verification searches it needs
+pub const STRICTLY_FORCED_SLACK: u32 = 2;
+
+/// Longer searches for games that keep ending without a mate.
+///
+/// Every `every` failed games in a row the search time is multiplied by
+/// `factor`, up to `max_search_ms`; the next candidate resets it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Escalation {
+    /// Games per call to [`TsumeGenerator::find_candidate`]
+    pub attempts: usize,
+    pub every: usize,
+    /// 1 or less keeps the search time fixed
+    pub factor: f64,
+    pub max_search_ms: u64,
+}
+
+impl Default for Escalation {
+    fn default() -> Self {
+        Escalation {
+            attempts: MAX_ATTEMPTS,
+            every: ESCALATE_EVERY,
+            factor: 2.0,
+            max_search_ms: MAX_SEARCH_MS,
+        }
+    }
+}
+
+impl Escalation {
+    /// Search time after `failed` games in a row without a candidate, from `search_ms`.
+    pub fn next_search_ms(&self, search_ms: u64, failed: usize) -> u64 {
+        if self.factor <= 1.0 || failed == 0 || !failed.is_multiple_of(self.every.max(1)) {
+            return search_ms;
+        }
+        let raised = (search_ms as f64 * self.factor).round() as u64;
+        raised
+            .max(search_ms + 1)
+            .min(self.max_search_ms)
+            .max(search_ms)
+    }
+}
+
+/// How the games puzzles come from are played.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerationMode {
+    /// White plays badly, see `--weak-mode`, so Black mates often
+    #[default]
+    Weak,
+    /// Both sides play their best move after a random opening; mates are
+    /// rarer but come up as they would in real games
+    Selfplay,
+    /// No games are played: puzzles come from recorded games (`mine`)
+    #[value(skip)]
+    Mined,
+}
+
+impl fmt::Display for GenerationMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GenerationMode::Weak => "weak",
+            GenerationMode::Selfplay => "selfplay",
+            GenerationMode::Mined => "mined",
+        })
+    }
+}
+
+/// Extra acceptance test on a verified puzzle: its position (Black to move)
+/// and solution in library convention.
+pub type TsumeFilter = Box<dyn Fn(&Position, &[Move]) -> bool + Send>;
+
+/// Acceptance test on a candidate's position alone (Black to move), checked
+/// before verification.
+pub type PositionFilter = Box<dyn Fn(&Position) -> bool + Send>;
+
+/// How games are played and which puzzles are kept.
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    /// Recorded in every puzzle; the strategies below are what it changes
+    pub mode: GenerationMode,
+    pub white: Strategy,
+    /// MultiPV candidates White's worst-move strategies choose among
+    pub weak_multipv: usize,
+    /// Engine `DrawScore`; [`DRAW_SCORE`] steers both sides away from draws
+    pub draw_score: i32,
+    /// Positions games start from; the initial position if empty
+    pub seed_positions: Vec<String>,
+    pub random_plies: usize,
+    /// Plies of each game the opening guard looks at, with an opening book set
+    pub opening_plies: usize,
+    pub min_game_plies: usize,
+    pub max_game_plies: usize,
+    pub plies_before_mate: usize,
+    /// Abandon games after this many plies without a capture, drop or pawn move
+    pub max_quiet_plies: Option<usize>,
+    /// Search time of the games' moves, before escalation raises it
+    pub search: SearchBudget,
+    /// Multiple of the search time retried after a resignation without a line
+    pub resign_retry_factor: u64,
+    pub escalation: Escalation,
+    /// Verification search time; 0 skips verification and every check after it
+    pub verify_ms: u64,
+    pub min_mate: Option<i32>,
+    pub max_mate: Option<i32>,
+    pub unique_solution: bool,
+    /// Also require every later attacker move of the solution to be the only
+    /// one mating as fast, at one verification search per move, within
+    /// [`strictly_forced_budget`]
+    pub strictly_forced: bool,
+    /// Defender search after the key move; 0 trusts the attacker-side verification
+    pub defense_check_ms: u64,
+    /// Rate verified puzzles and fill in their difficulty
+    pub rate: bool,
+    pub min_difficulty: Option<f64>,
+    pub max_difficulty: Option<f64>,
+    /// Fill in the rating of verified puzzles with [`estimate_rating`]
+    pub estimate_rating: bool,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            mode: GenerationMode::Weak,
+            white: Strategy::Worst,
+            weak_multipv: MULTIPV_K,
+            draw_score: DRAW_SCORE,
+            seed_positions: Vec::new(),
+            random_plies: 0,
+            opening_plies: OPENING_PLIES,
+            min_game_plies: 0,
+            max_game_plies: MAX_MOVES,
+            plies_before_mate: 1,
+            max_quiet_plies: Some(50),
+            search: SearchBudget::default(),
+            resign_retry_factor: RESIGN_RETRY_FACTOR,
+            escalation: Escalation::default(),
+            verify_ms: 500,
+            min_mate: None,
+            max_mate: None,
+            unique_solution: false,
+            strictly_forced: false,
+            defense_check_ms: 500,
+            rate: false,
+            min_difficulty: None,
+            max_difficulty: None,
+            estimate_rating: false,
+        }
+    }
+}
+
+impl GeneratorConfig {
+    /// Whether a verified mate distance falls within `min_mate`/`max_mate`.
+    pub fn mate_in_range(&self, mate: i32) -> bool {
+        self.min_mate.is_none_or(|min| mate >= min) && self.max_mate.is_none_or(|max| mate <= max)
+    }
+
+    /// Whether a difficulty falls within `min_difficulty`/`max_difficulty`.
+    pub fn difficulty_in_range(&self, difficulty: f64) -> bool {
+        self.min_difficulty.is_none_or(|min| difficulty >= min)
+            && self.max_difficulty.is_none_or(|max| difficulty <= max)
+    }
+}
+
+/// A tsume together with the game it came from.
+pub struct Candidate {
+    pub tsume: Tsume,
+    /// Seed position the game started from, if any
+    pub seed_position: Option<String>,
+    pub start_sfen: String,
+    /// Moves of the game in library convention
+    pub moves: Vec<String>,
+    /// Search time each move of the game was given
+    pub search_ms: Vec<u64>,
+    pub mode: GenerationMode,
+}
+
+impl Candidate {
+    /// Check the puzzle position is well formed before anything is written.
+    ///
+    /// The error names the position as played and as flipped for Black, so
+    /// a bad transformation can be traced.
+    pub fn validate(&self) -> Result<(), String> {
+        validate_puzzle_sfen(&self.tsume.sfen).map_err(|e| {
+            format!(
+                "malformed puzzle {:?} (from game position {:?}): {}",
+                self.tsume.sfen, self.tsume.game_sfen, e
+            )
+        })
+    }
+
+    /// Side that won the game: the one to move in the puzzle position as played.
+    pub fn winner(&self) -> Winner {
+        match self.tsume.game_sfen.split_whitespace().nth(1) {
+            Some("w") => Winner::White,
+            _ => Winner::Black,
+        }
+    }
+
+    /// Output record of the puzzle, before verification.
+    pub fn record(&self) -> PuzzleRecord {
+        let mut record = PuzzleRecord::new(self.tsume.sfen.clone(), self.tsume.ply);
+        record.mate_ply = Some(self.tsume.mate_ply);
+        record.seed_position = self.seed_position.clone();
+        record.mode = self.mode;
+        record
+    }
+}
+
+/// Why a candidate was not accepted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    /// The engine found no forced mate, or its solution does not replay
+    Unverified,
+    /// The engine failed during verification; the candidate proves nothing
+    EngineFailed,
+    /// The `strictly_forced` walk ran past [`strictly_forced_budget`]
+    OutOfTime,
+    /// Mate length outside the configured range
+    MateLength(i32),
+    /// More than one first move mates as fast as the solution, or with
+    /// `strictly_forced` more than one move at a later attacker ply
+    MultipleSolutions,
+    /// A longer defender search after the key move found no forced mate, or
+    /// only a longer one than claimed
+    Defended,
+    /// Difficulty outside the configured range
+    Difficulty(f64),
+    /// A filter added with [`TsumeGenerator::add_filter`] or
+    /// [`TsumeGenerator::add_position_filter`] refused it
+    Filtered,
+}
+
+/// Plays games on its engine and turns their mates into verified puzzles.
+///
+/// Generic over the engine so that a [`MockEngine`](crate::mock::MockEngine)
+/// can stand in for Fairy-Stockfish.
+pub struct TsumeGenerator<E: EngineInterface = Engine> {
+    engine: E,
+    /// Engine playing White's moves instead of `engine`, if any
+    white_engine: Option<E>,
+    config: GeneratorConfig,
+    filters: Vec<TsumeFilter>,
+    position_filters: Vec<PositionFilter>,
+    /// Openings played by every worker, shared to keep them varied
+    opening_book: Option<Arc<Mutex<OpeningBook>>>,
+    /// Recorded games candidates are taken from instead of playing games
+    game_collection: Option<Arc<GameCollection>>,
+    /// Candidates of the last game taken from `game_collection` not yet returned
+    mined: VecDeque<Candidate>,
+    games_mined: usize,
+    stuck_games: usize,
+    repetition_games: usize,
+    opening_reuse_games: usize,
+    failed_games: Vec<RejectRecord>,
+    game_stats: GameStats,
+    /// Time spent since the last call to `take_stage_times`
+    stage_times: StageTimes,
+    /// Search time of the games, raised by [`Escalation`]
+    search_ms: u64,
+    /// Games without a candidate since the last one
+    failed_streak: usize,
+    escalations: usize,
+    escalated_candidates: usize,
+}
+
+impl<E: EngineInterface> TsumeGenerator<E> {
+    pub fn new(engine: E, config: GeneratorConfig) -> Self {
+        let search_ms = config.search.search_ms;
+        TsumeGenerator {
+            engine,
+            white_engine: None,
+            config,
+            filters: Vec::new(),
+            position_filters: Vec::new(),
+            opening_book: None,
+            game_collection: None,
+            mined: VecDeque::new(),
+            games_mined: 0,
+            stuck_games: 0,
+            repetition_games: 0,
+            opening_reuse_games: 0,
+            failed_games: Vec::new(),
+            game_stats: GameStats::default(),
+            stage_times: StageTimes::default(),
+            search_ms,
+            failed_streak: 0,
+            escalations: 0,
+            escalated_candidates: 0,
+        }
+    }
+
+    /// Keep only puzzles `filter` accepts; checked after verification.
+    pub fn add_filter(&mut self, filter: impl Fn(&Position, &[Move]) -> bool + Send + 'static) {
+        self.filters.push(Box::new(filter));
+    }
+
+    /// Keep only candidates whose position `filter` accepts; checked first,
+    /// so rejected candidates cost no search.
+    pub fn add_position_filter(&mut self, filter: impl Fn(&Position) -> bool + Send + 'static) {
+        self.position_filters.push(Box::new(filter));
+    }
+
+    /// Abandon games whose first `opening_plies` moves `book` has seen too often.
+    pub fn set_opening_book(&mut self, book: Arc<Mutex<OpeningBook>>) {
+        self.opening_book = Some(book);
+    }
+
+    /// Take candidates from the games of `collection` instead of playing games.
+    pub fn set_game_collection(&mut self, collection: Arc<GameCollection>) {
+        self.game_collection = Some(collection);
+    }
+
+    /// Play White's moves on `engine`; verification stays on the main engine.
+    pub fn set_white_engine(&mut self, engine: E) {
+        self.white_engine = Some(engine);
+    }
+
+    pub fn engine(&mut self) -> &mut E {
+        &mut self.engine
+    }
+
+    pub fn white_engine(&mut self) -> Option<&mut E> {
+        self.white_engine.as_mut()
+    }
+
+    /// Games abandoned as stuck so far, repetitions included.
+    pub fn stuck_games(&self) -> usize {
+        self.stuck_games
+    }
+
+    /// Games abandoned on a repeated position so far.
+    pub fn repetition_games(&self) -> usize {
+        self.repetition_games
+    }
+
+    /// Games abandoned for an opening played too often so far.
+    pub fn opening_reuse_games(&self) -> usize {
+        self.opening_reuse_games
+    }
+
+    /// Games taken from the collection so far.
+    pub fn games_mined(&self) -> usize {
+        self.games_mined
+    }
+
+    /// Whether every candidate of the game collection has been returned;
+    /// never true when games are played.
+    pub fn is_exhausted(&self) -> bool {
+        self.mined.is_empty()
+            && self
+                .game_collection
+                .as_ref()
+                .is_some_and(|games| games.is_exhausted())
+    }
+
+    /// Times the search time was raised so far.
+    pub fn escalations(&self) -> usize {
+        self.escalations
+    }
+
+    /// Candidates found so far while the search time was raised.
+    pub fn escalated_candidates(&self) -> usize {
+        self.escalated_candidates
+    }
+
+    /// Counters over every game played so far.
+    pub fn take_game_stats(&mut self) -> GameStats {
+        std::mem::take(&mut self.game_stats)
+    }
+
+    /// Time spent simulating, verifying and rating since the last call.
+    pub fn take_stage_times(&mut self) -> StageTimes {
+        std::mem::take(&mut self.stage_times)
+    }
+
+    /// Games that produced no candidate since the last call, with the reason.
+    pub fn take_failed_games(&mut self) -> Vec<RejectRecord> {
+        std::mem::take(&mut self.failed_games)
+    }
+
+    /// The engines, the main one first.
+    pub fn into_engines(self) -> impl Iterator<Item = E> {
+        std::iter::once(self.engine).chain(self.white_engine)
+    }
+
+    /// Play games until one ends in mate, returning the tsume and the game it grew from.
+    ///
+    /// Without seed positions every game starts from the initial position; otherwise
+    /// each game starts from a randomly chosen seed. With a game collection
+    /// no game is played and the next position of a recorded game is returned.
+    pub fn find_candidate(&mut self, rng: &mut impl Rng) -> Option<Candidate> {
+        if let Some(games) = self.game_collection.clone() {
+            return self.next_mined(&games);
+        }
+        for _attempt in 1..=self.config.escalation.attempts {
+            let mut failed = false;
+            for engine in std::iter::once(&mut self.engine).chain(self.white_engine.as_mut()) {
+                // Set per game: a restarted engine starts from the defaults
+                engine.set_weak_multipv(self.config.weak_multipv);
+                engine.set_search_ms(self.search_ms);
+                engine.set_resign_retry_factor(self.config.resign_retry_factor);
+                // A failure is caught just below
+                let _ = engine.set_draw_score(self.config.draw_score);
+                failed |= engine.failure().is_some();
+            }
+            if shutdown::requested() || failed {
+                return None;
+            }
+            let seed_position = self.config.seed_positions.choose(rng).cloned();
+            let start = match &seed_position {
+                // Seeds were validated when loaded
+                Some(sfen) => Position::from_sfen(sfen).ok()?,
+                None => Position::startpos(),
+            };
+            let started = Instant::now();
+            let game = self.simulate_game(start, rng);
+            self.stage_times.simulation += started.elapsed();
+            self.game_stats.record(&game);
+            match game.stuck {
+                Some(Stuck::Repetition) => {
+                    self.stuck_games += 1;
+                    self.repetition_games += 1;
+                }
+                Some(Stuck::NoProgress) => self.stuck_games += 1,
+                Some(Stuck::OpeningReuse) => self.opening_reuse_games += 1,
+                None => {}
+            }
+            let failure = match game.result {
+                // Early mates come from White walking into the same few traps
+                GameResult::Checkmate(tsume) if tsume.mate_ply < self.config.min_game_plies => {
+                    RejectRecord::new(RejectStage::TooShort, Some(tsume.sfen), tsume.ply)
+                }
+                GameResult::Checkmate(tsume) => {
+                    if self.search_ms > self.config.search.search_ms {
+                        self.escalated_candidates += 1;
+                    }
+                    self.search_ms = self.config.search.search_ms;
+                    self.failed_streak = 0;
+                    return Some(Candidate {
+                        tsume,
+                        seed_position,
+                        start_sfen: game.start_sfen,
+                        moves: game.moves,
+                        search_ms: game.search_ms,
+                        mode: self.config.mode,
+                    });
+                }
+                GameResult::NoResult if game.stuck == Some(Stuck::Repetition) => {
+                    RejectRecord::new(RejectStage::Repetition, None, game.plies)
+                }
+                GameResult::NoResult if game.stuck == Some(Stuck::OpeningReuse) => {
+                    RejectRecord::new(RejectStage::OpeningReuse, None, game.plies)
+                }
+                GameResult::NoResult if game.stuck.is_some() => {
+                    RejectRecord::new(RejectStage::Stuck, None, game.plies)
+                }
+                GameResult::NoResult => RejectRecord::new(RejectStage::NoMate, None, game.plies),
+                GameResult::Error(SimulationError::Engine) => {
+                    RejectRecord::new(RejectStage::SimulationError, None, game.plies)
+                }
+                GameResult::Error(SimulationError::BadMove { sfen, engine_move }) => RejectRecord {
+                    engine_move: Some(engine_move),
+                    ..RejectRecord::new(RejectStage::SimulationError, Some(sfen), game.plies)
+                },
+                GameResult::Error(SimulationError::MatedAtStart { sfen }) => {
+                    RejectRecord::new(RejectStage::SimulationError, Some(sfen), game.plies)
+                }
+            };
+            self.failed_games.push(failure);
+            // The engine had no chance to mate, so the search time stays
+            if game.stuck == Some(Stuck::OpeningReuse) {
+                continue;
+            }
+
+            self.failed_streak += 1;
+            let raised = self
+                .config
+                .escalation
+                .next_search_ms(self.search_ms, self.failed_streak);
+            if raised > self.search_ms {
+                self.search_ms = raised;
+                self.escalations += 1;
+            }
+        }
+
+        None
+    }
+
+    /// The next candidate of the collection's games, taking a game whenever
+    /// the last one has none left.
+    fn next_mined(&mut self, games: &GameCollection) -> Option<Candidate> {
+        while self.mined.is_empty() {
+            if shutdown::requested() {
+                return None;
+            }
+            self.mined.extend(games.next_game()?.candidates());
+            self.games_mined += 1;
+        }
+        self.mined.pop_front()
+    }
+
+    /// Play one game from `start`; the first `random_plies` moves are sampled
+    /// from the engine's candidates.
+    fn simulate_game(&mut self, start: Position, rng: &mut impl Rng) -> SimulationResult {
+        let book = self.opening_book.clone();
+        let mut admit = |start: &str, moves: &[Move]| {
+            book.as_ref()
+                .is_none_or(|book| book.lock().unwrap().admit(start, moves))
+        };
+        GameSimulator::new(&mut self.engine)
+            .white_engine(self.white_engine.as_mut())
+            .initial_position(start)
+            .black_strategy(Strategy::Best)
+            .white_strategy(self.config.white)
+            .max_moves(self.config.max_game_plies)
+            .plies_before_mate(self.config.plies_before_mate)
+            .max_quiet_plies(self.config.max_quiet_plies)
+            .search_budget(SearchBudget {
+                search_ms: self.search_ms,
+                ..self.config.search
+            })
+            .random_opening(self.config.random_plies, rng)
+            .opening_guard(self.config.opening_plies, &mut admit)
+            .run()
+    }
+
+    /// Prove `record` is a forced mate and apply every check, filling in its
+    /// mate length, solution and difficulty.
+    ///
+    /// Search once; the result serves verification, length and uniqueness filtering.
+    pub fn verify(&mut self, record: &mut PuzzleRecord) -> Result<(), Rejection> {
+        let started = Instant::now();
+        let rating_before = self.stage_times.difficulty;
+        let verdict = self.check(record);
+        let rating = self.stage_times.difficulty - rating_before;
+        self.stage_times.verification += started.elapsed().saturating_sub(rating);
+        verdict
+    }
+
+    /// The checks of [`TsumeGenerator::verify`], with only rating timed.
+    fn check(&mut self, record: &mut PuzzleRecord) -> Result<(), Rejection> {
+        let config = &self.config;
+        if !self.position_filters.is_empty() {
+            let position = Position::from_sfen(&record.sfen).map_err(|_| Rejection::Unverified)?;
+            if !self.position_filters.iter().all(|filter| filter(&position)) {
+                return Err(Rejection::Filtered);
+            }
+        }
+        if config.verify_ms == 0 {
+            return Ok(());
+        }
+
+        let verification = verify_puzzle(&mut self.engine, &record.sfen, config.verify_ms);
+        let mate = match verification.result {
+            VerifyResult::Matein(mate) => mate,
+            VerifyResult::NoMate => return Err(Rejection::Unverified),
+            VerifyResult::Timeout(_) => return Err(Rejection::EngineFailed),
+        };
+        if !config.mate_in_range(mate) {
+            return Err(Rejection::MateLength(mate));
+        }
+        if (config.unique_solution || config.strictly_forced)
+            && count_solutions(&verification.pv_infos, mate) > 1
+        {
+            return Err(Rejection::MultipleSolutions);
+        }
+
+        // The solution must replay in the library, which also catches conversion bugs
+        let position = Position::from_sfen(&record.sfen).map_err(|_| Rejection::Unverified)?;
+        let (pv, variation) = verification
+            .pv_infos
+            .into_iter()
+            .find(|pv| pv.multipv == 1)
+            .and_then(|pv| {
+                let variation = pv.to_variation(&position).ok()?;
+                Some((pv, variation))
+            })
+            .ok_or(Rejection::Unverified)?;
+        if !self
+            .filters
+            .iter()
+            .all(|filter| filter(&position, &variation.moves))
+        {
+            return Err(Rejection::Filtered);
+        }
+        // After a mate in 1 the defender has no reply to search
+        if config.defense_check_ms > 0 && mate > 1 {
+            let mut after_key = position.clone();
+            let key = *variation.moves.first().ok_or(Rejection::Unverified)?;
+            after_key
+                .make_move(key)
+                .map_err(|_| Rejection::Unverified)?;
+            let sfen = position_only_sfen(&after_key.to_sfen());
+            // The defender's best reply must leave exactly the rest of the mate
+            match verify_defense(&mut self.engine, &sfen, config.defense_check_ms) {
+                VerifyResult::Matein(plies) if plies == mate - 1 => {}
+                // The verification search overlooked a faster mate
+                VerifyResult::Matein(plies) if plies < mate - 1 => {
+                    return Err(Rejection::Unverified);
+                }
+                VerifyResult::Matein(_) | VerifyResult::NoMate => return Err(Rejection::Defended),
+                VerifyResult::Timeout(_) => return Err(Rejection::EngineFailed),
+            }
+        }
+        if config.strictly_forced {
+            let positions = variation
+                .apply(&position)
+                .map_err(|_| Rejection::Unverified)?;
+            let verify_ms = config.verify_ms;
+            let budget = strictly_forced_budget(verify_ms, mate);
+            let forced = is_strictly_forced(&positions, mate, budget, |sfen| {
+                let verification = verify_puzzle(&mut self.engine, sfen, verify_ms);
+                let failed = matches!(verification.result, VerifyResult::Timeout(_));
+                (!failed).then_some(verification.pv_infos)
+            })?;
+            if !forced {
+                return Err(Rejection::MultipleSolutions);
+            }
+        }
+        record.mate_in = Some(mate);
+        record.solution = Some(pv.moves);
+        record.themes = classify(&position, &variation.moves);
+
+        if config.rate {
+            let started = Instant::now();
+            let rating = rate_puzzle(&mut self.engine, &record.sfen);
+            self.stage_times.difficulty += started.elapsed();
+            let rating = rating.ok_or(Rejection::EngineFailed)?;
+            if !config.difficulty_in_range(rating.difficulty) {
+                return Err(Rejection::Difficulty(rating.difficulty));
+            }
+            record.difficulty = Some(rating.difficulty);
+        }
+        if config.estimate_rating {
+            let started = Instant::now();
+            let rating = estimate_rating(&mut self.engine, record);
+            self.stage_times.difficulty += started.elapsed();
+            record.rating = Some(rating.ok_or(Rejection::EngineFailed)?);
+        }
+        Ok(())
+    }
+}
+
+/// Count the first moves among the PVs that mate in `mate` plies or fewer.
+fn count_solutions(pv_infos: &[PvInfo], mate: i32) -> usize {
+    pv_infos
+        .iter()
+        .filter(|pv| !pv.moves.is_empty())
+        .filter(|pv| matches!(pv.score, Score::Mate(plies) if plies > 0 && plies <= mate))
+        .count()
+}
+
+/// Time the `strictly_forced` walk of a mate in `mate` plies may take: one
+/// search of `verify_ms` per attacker move after the first, times
+/// [`STRICTLY_FORCED_SLACK`].
+pub fn strictly_forced_budget(verify_ms: u64, mate: i32) -> Duration {
+    let searches = (mate.max(1) as u32 - 1) / 2;
+    Duration::from_millis(verify_ms) * searches * STRICTLY_FORCED_SLACK
+}
+
+/// Whether every attacker move after the first of a mate in `mate` plies is
+/// the only one mating as fast; `positions` are those along the solution, as
+/// [`Variation::apply`](crate::variation::Variation::apply) gives them.
+///
+/// `search` returns the PVs of a position with the attacker to move, or
+/// `None` if the engine failed, which ends the walk with
+/// [`Rejection::EngineFailed`]. The first position is left to the
+/// verification search.
+///
+/// No search starts once the walk has taken `budget`, which ends it with
+/// [`Rejection::OutOfTime`], or once a shutdown is requested, which counts
+/// as an engine failure since nothing was proven.
+fn is_strictly_forced(
+    positions: &[Position],
+    mate: i32,
+    budget: Duration,
+    mut search: impl FnMut(&str) -> Option<Vec<PvInfo>>,
+) -> Result<bool, Rejection> {
+    let started = Instant::now();
+    for (ply, position) in positions.iter().enumerate().skip(2).step_by(2) {
+        // The attacker's last move has been played
+        let remaining = mate - ply as i32;
+        if remaining <= 0 {
+            break;
+        }
+        if shutdown::requested() {
+            return Err(Rejection::EngineFailed);
+        }
+        if started.elapsed() >= budget {
+            return Err(Rejection::OutOfTime);
+        }
+        let pv_infos =
+            search(&position_only_sfen(&position.to_sfen())).ok_or(Rejection::EngineFailed)?;
+        if count_solutions(&pv_infos, remaining) > 1 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Keep solutions of at least `plies` plies.
+pub fn filter_min_length(plies: usize) -> impl Fn(&Position, &[Move]) -> bool + Send + 'static {
+    move |_, solution| solution.len() >= plies
+}
+
+/// Keep solutions of at most `plies` plies.
+pub fn filter_max_length(plies: usize) -> impl Fn(&Position, &[Move]) -> bool + Send + 'static {
+    move |_, solution| solution.len() <= plies
+}
+
+/// Keep puzzles whose mating move is a drop.
+pub fn filter_requires_drop() -> impl Fn(&Position, &[Move]) -> bool + Send + 'static {
+    |_, solution| {
+        solution
+            .last()
+            .is_some_and(|mv| mv.to_string().contains('*'))
+    }
+}
+
+/// Keep puzzles where Black drops a piece somewhere in the solution.
+pub fn filter_requires_attacker_drop() -> impl Fn(&Position, &[Move]) -> bool + Send + 'static {
+    |_, solution| {
+        solution
+            .iter()
+            .step_by(2)
+            .any(|mv| mv.to_string().contains('*'))
+    }
+}
+
+/// Keep positions where White has pieces in hand (`true`) or none (`false`).
+///
+/// An empty defender hand rules out interposing drops, which makes for an
+/// easier puzzle.
+pub fn filter_defender_hand(has_pieces: bool) -> impl Fn(&Position) -> bool + Send + 'static {
+    move |position| (hand_sizes(position).1 > 0) == has_pieces
+}
+
+/// Keep positions with at most `pieces` White pieces on the board, king included.
+pub fn filter_max_defender_pieces(pieces: u32) -> impl Fn(&Position) -> bool + Send + 'static {
+    move |position| board_piece_counts(position).1 <= pieces
+}
+
+/// Keep positions with at most `pieces` pieces on the board, kings included.
+pub fn filter_max_total_pieces(pieces: u32) -> impl Fn(&Position) -> bool + Send + 'static {
+    move |position| {
+        let (black, white) = board_piece_counts(position);
+        black + white <= pieces
+    }
+}
+
+/// Keep positions where Black has a `piece` on the board or in hand to mate with.
+pub fn filter_requires_piece(piece: PieceType) -> impl Fn(&Position) -> bool + Send + 'static {
+    move |position| black_has_piece(position, piece)
+}
+
+/// Keep puzzles whose mating move promotes.
+pub fn filter_requires_promotion() -> impl Fn(&Position, &[Move]) -> bool + Send + 'static {
+    |_, solution| {
+        solution
+            .last()
+            .is_some_and(|mv| mv.to_string().ends_with('+'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::SearchResult;
+    use crate::mock::MockEngine;
+    use crate::variation::Variation;
+
+    fn pv(multipv: i32, score: Score, mv: &str) -> PvInfo {
+        PvInfo {
+            multipv,
+            score,
+            moves: vec![mv.to_string()],
+        }
+    }
+
+    fn moves(texts: &[&str]) -> Vec<Move> {
+        texts.iter().map(|t| Move::from_sfen(t).unwrap()).collect()
+    }
+
+    fn candidate(sfen: &str, game_sfen: &str) -> Candidate {
+        Candidate {
+            tsume: Tsume {
+                sfen: sfen.to_string(),
+                game_sfen: game_sfen.to_string(),
+                ply: 8,
+                mate_ply: 9,
+            },
+            seed_position: None,
+            start_sfen: "bkr/p1p/3/P1P/RKB b - 1".to_string(),
+            moves: Vec::new(),
+            search_ms: Vec::new(),
+            mode: GenerationMode::Weak,
+        }
+    }
+
+    #[test]
+    fn test_validate_candidate() {
+        let sfen = "bkr/p1p/3/P1P/RKB b - 1";
+        assert!(candidate(sfen, sfen).validate().is_ok());
+
+        // A flip that dropped the hand field
+        let err = candidate("bkr/p1p/3/P1P/RKB b 1", "bkr/p1p/3/P1P/RKB w - 1")
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("RKB b 1"));
+        assert!(err.contains("RKB w - 1"));
+    }
+
+    #[test]
+    fn test_count_solutions() {
+        let pv_infos = vec![
+            pv(1, Score::Mate(3), "2c2b"),
+            pv(2, Score::Mate(5), "1c1b"),
+            pv(3, Score::Cp(300), "P*2a"),
+        ];
+        assert_eq!(count_solutions(&pv_infos, 3), 1);
+        assert_eq!(count_solutions(&pv_infos, 5), 2);
+
+        let pv_infos = vec![pv(1, Score::Mate(3), "2c2b"), pv(2, Score::Mate(3), "1c1b")];
+        assert_eq!(count_solutions(&pv_infos, 3), 2);
+    }
+
+    #[test]
+    fn test_strictly_forced() {
+        let solution = Variation {
+            moves: moves(&["2e2d", "3b3c", "1d1c", "2a2b"]),
+            score: None,
+            annotation: None,
+        };
+        let positions = solution.apply(&Position::startpos()).unwrap();
+        let answers = |second: Vec<PvInfo>| {
+            let mut answers = vec![
+                vec![pv(1, Score::Mate(3), "1c1b"), pv(2, Score::Mate(5), "2d2c")],
+                second,
+            ]
+            .into_iter();
+            move |sfen: &str| {
+                assert!(sfen.contains(" b "), "{}", sfen);
+                answers.next()
+            }
+        };
+
+        let forced = vec![pv(1, Score::Mate(1), "1c1b"), pv(2, Score::Cp(400), "2d2c")];
+        assert_eq!(
+            is_strictly_forced(&positions, 5, Duration::MAX, answers(forced)),
+            Ok(true)
+        );
+        // Two ways to mate at the attacker's third move
+        let loose = vec![pv(1, Score::Mate(1), "1c1b"), pv(2, Score::Mate(1), "2d2c")];
+        assert_eq!(
+            is_strictly_forced(&positions, 5, Duration::MAX, answers(loose)),
+            Ok(false)
+        );
+        let mut searches = 0;
+        let failed = is_strictly_forced(&positions, 5, Duration::MAX, |_| {
+            searches += 1;
+            None
+        });
+        assert_eq!((failed, searches), (Err(Rejection::EngineFailed), 1));
+        // Out of time before the first search
+        let mut searches = 0;
+        let late = is_strictly_forced(&positions, 5, Duration::ZERO, |_| {
+            searches += 1;
+            Some(Vec::new())
+        });
+        assert_eq!((late, searches), (Err(Rejection::OutOfTime), 0));
+        // A mate in 1 has no later attacker move
+        assert_eq!(
+            is_strictly_forced(&positions, 1, Duration::ZERO, |_| None),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn test_strictly_forced_budget() {
+        // A mate in 11 has five attacker moves after the first
+        assert_eq!(
+            strictly_forced_budget(500, 11),
+            Duration::from_millis(500) * 5 * STRICTLY_FORCED_SLACK
+        );
+        assert_eq!(strictly_forced_budget(500, 1), Duration::ZERO);
+        assert!(strictly_forced_budget(500, 13) > strictly_forced_budget(500, 11));
+    }
+
+    #[test]
+    fn test_length_filters() {
+        let position = Position::startpos();
+        let min_three = filter_min_length(3);
+        assert!(!min_three(&position, &moves(&["2c2b"])));
+        assert!(!min_three(&position, &moves(&["P*2b"])));
+        assert!(min_three(&position, &moves(&["2c2b", "1a2a", "1c1b"])));
+
+        let max_one = filter_max_length(1);
+        assert!(max_one(&position, &moves(&["2c2b"])));
+        assert!(!max_one(&position, &moves(&["2c2b", "1a2a", "1c1b"])));
+    }
+
+    #[test]
+    fn test_drop_and_promotion_filters() {
+        let position = Position::startpos();
+        let drop = filter_requires_drop();
+        assert!(drop(&position, &moves(&["2c2b", "1a2a", "P*2b"])));
+        assert!(!drop(&position, &moves(&["P*2b", "1a2a", "2c2b"])));
+        assert!(!drop(&position, &[]));
+
+        let promotion = filter_requires_promotion();
+        assert!(promotion(&position, &moves(&["2c2b+"])));
+        assert!(!promotion(&position, &moves(&["2c2b"])));
+
+        // White's drops do not count
+        let attacker_drop = filter_requires_attacker_drop();
+        assert!(attacker_drop(&position, &moves(&["P*2b", "1a2a", "2c2b"])));
+        assert!(!attacker_drop(&position, &moves(&["2c2b", "P*2a", "1c1b"])));
+    }
+
+    #[test]
+    fn test_defender_hand_filters() {
+        let empty = Position::from_sfen("1k1/3/R2/3/K1B b G 1").unwrap();
+        let holding = Position::from_sfen("1k1/3/R2/3/K1B b Gp 1").unwrap();
+        assert!(filter_defender_hand(true)(&holding));
+        assert!(!filter_defender_hand(true)(&empty));
+        assert!(filter_defender_hand(false)(&empty));
+        assert!(!filter_defender_hand(false)(&holding));
+    }
+
+    #[test]
+    fn test_piece_filters() {
+        // Three Black and two White pieces on the board, a gold in Black's hand
+        let position = Position::from_sfen("1kp/3/R2/3/K1B b G 1").unwrap();
+        assert!(filter_max_defender_pieces(2)(&position));
+        assert!(!filter_max_defender_pieces(1)(&position));
+        assert!(filter_max_total_pieces(5)(&position));
+        assert!(!filter_max_total_pieces(4)(&position));
+
+        assert!(filter_requires_piece(PieceType::Gold)(&position));
+        assert!(filter_requires_piece(PieceType::Bishop)(&position));
+        assert!(!filter_requires_piece(PieceType::Pawn)(&position));
+    }
+
+    #[test]
+    fn test_escalation() {
+        let escalation = Escalation::default();
+        assert_eq!(escalation.next_search_ms(10, 1), 10);
+        assert_eq!(escalation.next_search_ms(10, 3), 20);
+        assert_eq!(escalation.next_search_ms(40, 6), 80);
+        // Capped
+        assert_eq!(escalation.next_search_ms(80, 9), 80);
+
+        let fixed = Escalation {
+            factor: 1.0,
+            ..Escalation::default()
+        };
+        assert_eq!(fixed.next_search_ms(10, 3), 10);
+        // A small factor still raises the time
+        let slow = Escalation {
+            factor: 1.01,
+            every: 1,
+            ..Escalation::default()
+        };
+        assert_eq!(slow.next_search_ms(10, 1), 11);
+    }
+
+    #[cfg(unix)]
+    fn fixture_generator(script: &str) -> TsumeGenerator {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let engine = Engine::spawn(&crate::engine::EngineConfig {
+            engine: fixtures.join(script),
+            variants_ini: fixtures.join("variants.ini"),
+            variant: crate::variant::Variant::Wildcatshogi,
+            ..crate::engine::EngineConfig::default()
+        })
+        .unwrap();
+        let config = GeneratorConfig {
+            verify_ms: 50,
+            defense_check_ms: 50,
+            ..GeneratorConfig::default()
+        };
+        TsumeGenerator::new(engine, config)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_defense_must_not_stretch_the_mate() {
+        let mut generator = fixture_generator("defense-engine.sh");
+        let mut record = PuzzleRecord::new("1k1/3/2R/3/B1K b G 1".to_string(), 0);
+        assert_eq!(generator.verify(&mut record), Ok(()));
+        assert_eq!(record.mate_in, Some(3));
+
+        let mut record = PuzzleRecord::new("1k1/3/R2/3/K1B b G 1".to_string(), 0);
+        assert_eq!(generator.verify(&mut record), Err(Rejection::Defended));
+        assert_eq!(record.mate_in, None);
+    }
+
+    /// A search answering `moves` (Fairy-Stockfish convention) scored `score`.
+    fn line(score: Score, moves: &[&str]) -> (Vec<PvInfo>, SearchResult) {
+        let pv = PvInfo {
+            multipv: 1,
+            score,
+            moves: moves.iter().map(|mv| mv.to_string()).collect(),
+        };
+        (vec![pv], SearchResult::Move(moves[0].to_string()))
+    }
+
+    fn mock_generator(
+        script: impl IntoIterator<Item = (Vec<PvInfo>, SearchResult)>,
+        config: GeneratorConfig,
+    ) -> TsumeGenerator<MockEngine> {
+        TsumeGenerator::new(MockEngine::new(script), config)
+    }
+
+    #[test]
+    fn test_check_with_mock_engine() {
+        const TSUME: &str = "1k1/3/2R/3/B1K b G 1";
+        let solved = || line(Score::Mate(3), &["G*2b", "2a2b", "1c1b"]);
+        let check = |generator: &mut TsumeGenerator<MockEngine>| {
+            let mut record = PuzzleRecord::new(TSUME.to_string(), 0);
+            generator.verify(&mut record).map(|()| record)
+        };
+
+        let mut generator = mock_generator(
+            [solved(), line(Score::Mate(-2), &["2a2b", "1c1b"])],
+            GeneratorConfig::default(),
+        );
+        let record = check(&mut generator).unwrap();
+        assert_eq!(record.mate_in, Some(3));
+        assert_eq!(
+            record.solution,
+            Some(["G*2b", "2a2b", "1c1b"].map(String::from).to_vec())
+        );
+        // The defender searched after the key move
+        assert_eq!(generator.engine().positions[1], "1k1/1G1/2R/3/B1K w - 2");
+        assert_eq!(generator.engine().search_ms, [500, 500]);
+
+        let no_mate = line(Score::Cp(300), &["G*2b"]);
+        let mut generator = mock_generator([no_mate], GeneratorConfig::default());
+        assert_eq!(check(&mut generator).unwrap_err(), Rejection::Unverified);
+
+        let mut generator = mock_generator([], GeneratorConfig::default());
+        assert_eq!(check(&mut generator).unwrap_err(), Rejection::EngineFailed);
+
+        let short = GeneratorConfig {
+            max_mate: Some(1),
+            ..GeneratorConfig::default()
+        };
+        let mut generator = mock_generator([solved()], short);
+        assert_eq!(check(&mut generator).unwrap_err(), Rejection::MateLength(3));
+
+        let unique = GeneratorConfig {
+            unique_solution: true,
+            ..GeneratorConfig::default()
+        };
+        let (mut pv_infos, result) = solved();
+        pv_infos.push(pv(2, Score::Mate(3), "G*1b"));
+        let mut generator = mock_generator([(pv_infos, result)], unique);
+        assert_eq!(
+            check(&mut generator).unwrap_err(),
+            Rejection::MultipleSolutions
+        );
+
+        let stalled = line(Score::Mate(-4), &["2a2b", "1c1b"]);
+        let mut generator = mock_generator([solved(), stalled], GeneratorConfig::default());
+        assert_eq!(check(&mut generator).unwrap_err(), Rejection::Defended);
+
+        let mut generator = mock_generator([solved()], GeneratorConfig::default());
+        generator.add_position_filter(|_| false);
+        assert_eq!(check(&mut generator).unwrap_err(), Rejection::Filtered);
+        assert!(generator.engine().positions.is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+//! Tsume (checkmate puzzle) generator for Wild Cat Shogi.
+//!
+//! Simulates a game between a high-rated player (Black) and a low-rated player (White).
+//! The low-rated player uses MultiPV to select the worst move from the top K moves.
+//! The resulting tsume is the SFEN of the position before checkmate.
+
+pub mod annotate;
+pub mod audit;
+pub mod cli;
+pub mod diagram;
+pub mod engine;
+pub mod eval;
+pub mod games;
+pub mod generator;
+pub mod manifest;
+pub mod material;
+pub mod merge;
+pub mod mine;
+pub mod mock;
+pub mod movegen;
+pub mod openings;
+pub mod output;
+pub mod pgn;
+pub mod pool;
+pub mod progress;
+pub mod queue;
+pub mod rating;
+pub mod rejects;
+pub mod resume;
+pub mod review;
+pub mod seeds;
+pub mod sfen;
+pub mod shutdown;
+pub mod simulator;
+pub mod stats;
+pub mod themes;
+pub mod timings;
+pub mod training;
+pub mod variant;
+pub mod variants_ini;
+pub mod variation;
+pub mod verify;
+
+use shogi::sfen::mirror_sfen;
+use variant::Variant;
+
+/// Extract just the position SFEN (without move history) from a full SFEN string.
+pub fn position_only_sfen(sfen: &str) -> String {
+    if let Some(idx) = sfen.find(" moves") {
+        sfen[..idx].to_string()
+    } else {
+        sfen.to_string()
+    }
+}
+
+/// Mirror a position SFEN if needed so that Black is to move, resetting the move counter.
+pub fn ensure_black_to_move(sfen: &str) -> String {
+    if sfen.split_whitespace().nth(1) != Some("w") {
+        return sfen.to_string();
+    }
+
+    let flipped = mirror_sfen(sfen);
+    let parts: Vec<&str> = flipped.split_whitespace().collect();
+    if parts.len() >= 4 {
+        format!("{} {} {} 1", parts[0], parts[1], parts[2])
+    } else {
+        flipped
+    }
+}
+
+/// Convert wildcatshogi move file numbers between Fairy-Stockfish and library conventions.
+///
+/// The library's `Position` type is Wild Cat Shogi's, so its board width applies.
+pub fn convert_move_files(sfen: &str) -> String {
+    convert_move_files_for(Variant::Wildcatshogi, sfen)
+}
+
+/// Convert move file numbers between Fairy-Stockfish and library conventions.
+///
+/// Fairy-Stockfish uses: file 1 = rightmost, file N = leftmost
+/// shogi-rs uses: file 1 = leftmost, file N = rightmost
+///
+/// Conversion formula: new_file = N + 1 - old_file
+pub fn convert_move_files_for(variant: Variant, sfen: &str) -> String {
+    let mirror = |file: u32| variant.files() + 1 - file;
+    let on_board = |c: char| {
+        c.to_digit(10)
+            .filter(|file| (1..=variant.files()).contains(file))
+    };
+    let chars: Vec<char> = sfen.chars().collect();
+
+    // Drop move (e.g., "P*2b")
+    if chars.len() >= 4 && chars[1] == '*' {
+        let piece = chars[0];
+        let dest_file = chars[2];
+        let dest_rank = chars[3];
+
+        if let Some(digit) = on_board(dest_file) {
+            let converted_file = mirror(digit);
+            return format!("{}*{}{}", piece, converted_file, dest_rank);
+        }
+        return sfen.to_string();
+    }
+
+    // Normal move (e.g., "3a1c" or "3a1c+")
+    if chars.len() >= 4 {
+        let from_file = chars[0];
+        let from_rank = chars[1];
+        let to_file = chars[2];
+        let to_rank = chars[3];
+        let promotion = if chars.len() > 4 { &sfen[4..] } else { "" };
+
+        if let (Some(ff), Some(tf)) = (on_board(from_file), on_board(to_file)) {
+            let converted_from = mirror(ff);
+            let converted_to = mirror(tf);
+            return format!(
+                "{}{}{}{}{}",
+                converted_from, from_rank, converted_to, to_rank, promotion
+            );
+        }
+    }
+
+    sfen.to_string()
+}
+
+/// Turn a USI move half a turn with its board, as [`mirror_sfen`] turns
+/// positions: the move White would play in the mirror of Black's position.
+///
+/// Files and ranks are both reversed, so the result is in the same file
+/// convention as `mv`. Text that is not a move is returned unchanged.
+pub fn rotate_move(mv: &str) -> String {
+    let variant = Variant::Wildcatshogi;
+    let file = |c: char| {
+        c.to_digit(10)
+            .filter(|file| (1..=variant.files()).contains(file))
+            .map(|file| variant.files() + 1 - file)
+    };
+    let rank = |c: char| {
+        let rank = (c as u32).checked_sub('a' as u32)?;
+        (rank < variant.ranks()).then(|| char::from(b'a' + (variant.ranks() - 1 - rank) as u8))
+    };
+    let chars: Vec<char> = mv.chars().collect();
+    let square = |f: char, r: char| Some(format!("{}{}", file(f)?, rank(r)?));
+
+    let rotated = match chars[..] {
+        // Drop move (e.g., "P*2b")
+        [piece, '*', f, r] => square(f, r).map(|to| format!("{}*{}", piece, to)),
+        // Normal move (e.g., "3a1c" or "3a1c+")
+        [ff, fr, tf, tr, ref promotion @ ..] => square(ff, fr)
+            .zip(square(tf, tr))
+            .map(|(from, to)| format!("{}{}{}", from, to, String::from_iter(promotion))),
+        _ => None,
+    };
+    rotated.unwrap_or_else(|| mv.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_only_sfen() {
+        assert_eq!(
+            position_only_sfen("bkr/p1p/3/P1P/RKB b - 1"),
+            "bkr/p1p/3/P1P/RKB b - 1"
+        );
+        assert_eq!(
+            position_only_sfen("bkr/p1p/3/P1P/RKB b - 1 moves 1e2d 3a2b"),
+            "bkr/p1p/3/P1P/RKB b - 1"
+        );
+    }
+
+    #[test]
+    fn test_ensure_black_to_move_already_black() {
+        let sfen = "bkr/p1p/3/P1P/RKB b - 1";
+        assert_eq!(ensure_black_to_move(sfen), sfen);
+    }
+
+    #[test]
+    fn test_ensure_black_to_move_white_to_move() {
+        let sfen = "bkr/p1p/3/P1P/RKB w - 1";
+        let result = ensure_black_to_move(sfen);
+        assert!(
+            result.contains(" b "),
+            "Should be Black to move after mirror"
+        );
+    }
+
+    #[test]
+    fn test_convert_move_files_normal() {
+        // Fairy-Stockfish "1e2d" -> shogi-rs "3e2d"
+        assert_eq!(convert_move_files("1e2d"), "3e2d");
+        assert_eq!(convert_move_files("3a2b"), "1a2b");
+        assert_eq!(convert_move_files("2c2c"), "2c2c"); // file 2 stays 2
+    }
+
+    #[test]
+    fn test_convert_move_files_drop() {
+        // Fairy-Stockfish "P*2c" -> shogi-rs "P*2c" (file 2 stays 2)
+        assert_eq!(convert_move_files("P*2c"), "P*2c");
+        assert_eq!(convert_move_files("P*1a"), "P*3a");
+        assert_eq!(convert_move_files("B*3e"), "B*1e");
+    }
+
+    #[test]
+    fn test_rotate_move() {
+        assert_eq!(rotate_move("1e2d"), "3a2b");
+        assert_eq!(rotate_move("3a3b+"), "1e1d+");
+        assert_eq!(rotate_move("G*2b"), "G*2d");
+        assert_eq!(rotate_move(&rotate_move("P*1c")), "P*1c");
+        assert_eq!(rotate_move("resign"), "resign");
+    }
+
+    #[test]
+    fn test_convert_move_files_promotion() {
+        assert_eq!(convert_move_files("1a1b+"), "3a3b+");
+        assert_eq!(convert_move_files("3d3e+"), "1d1e+");
+    }
+}
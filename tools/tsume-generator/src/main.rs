@@ -1,502 +1,821 @@
-//! Tsume (checkmate puzzle) generator for Wild Cat Shogi.
-//!
-//! Simulates a game between a high-rated player (Black) and a low-rated player (White).
-//! The low-rated player uses MultiPV to select the worst move from the top K moves.
-//! The resulting tsume is the SFEN of the position before checkmate.
-
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::time::Duration;
-
-use shogi::sfen::mirror_sfen;
-use shogi::wildcatshogi::{Move, Position, STARTING_SFEN};
-use usi::{
-    BestMoveParams, EngineCommand, GuiCommand, InfoParams, ScoreKind, ThinkParams,
-    UsiEngineHandler,
+//! Command-line entry point of the tsume generator.
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use tsume_generator::annotate::{
+    AnnotationSummary, annotate_puzzles, read_puzzle_lines, write_annotated,
 };
+use tsume_generator::audit::audit_puzzle;
+use tsume_generator::cli::{self, WeakMode};
+use tsume_generator::engine::{
+    Engine, EngineConfig, EngineError, EngineFailure, check_fairy_stockfish_version,
+};
+use tsume_generator::games::GameRecord;
+use tsume_generator::generator::{
+    GenerationMode, Rejection, TsumeGenerator, filter_defender_hand, filter_max_defender_pieces,
+    filter_max_total_pieces, filter_requires_attacker_drop, filter_requires_drop,
+    filter_requires_piece, filter_requires_promotion,
+};
+use tsume_generator::manifest::{EngineIdentity, Manifest, Shard};
+use tsume_generator::merge::{MergedFile, merge_outputs};
+use tsume_generator::mine::GameCollection;
+use tsume_generator::openings::OpeningBook;
+use tsume_generator::output::{OutputFormat, OutputTarget, PuzzleRecord, duplicate_key};
+use tsume_generator::pool::EnginePool;
+use tsume_generator::progress::ProgressReporter;
+use tsume_generator::queue::TsumeWorkQueue;
+use tsume_generator::rejects::{RejectRecord, RejectStage};
+use tsume_generator::resume::{ExistingOutput, read_existing_output};
+use tsume_generator::seeds::load_seed_positions;
+use tsume_generator::shutdown;
+use tsume_generator::simulator::Strategy;
+use tsume_generator::stats::RunStats;
+use tsume_generator::timings::PuzzleTiming;
+use tsume_generator::variants_ini::parse_variants_ini;
+
+/// Attempts to start each engine process before giving up
+const SPAWN_ATTEMPTS: usize = 3;
+/// Wait before the first spawn retry; doubled for each further retry
+const SPAWN_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Finished puzzles buffered between the workers and the output file
+const QUEUE_BUFFER: usize = 64;
 
-const VARIANTS_INI_PATH: &str = "../../variants.ini";
-const FAIRY_STOCKFISH: &str = "fairy-stockfish";
-const MAX_MOVES: usize = 300;
-const MULTIPV_K: i32 = 5;
-const SEARCH_TIME_MS: u64 = 10;
-const MAX_ATTEMPTS: usize = 10;
-
-/// Extract just the position SFEN (without move history) from a full SFEN string.
-fn position_only_sfen(sfen: &str) -> String {
-    if let Some(idx) = sfen.find(" moves") {
-        sfen[..idx].to_string()
-    } else {
-        sfen.to_string()
+fn main() {
+    let args = cli::Args::parse();
+    if args.check_engine {
+        run_check_engine(&args);
     }
-}
-
-/// Convert wildcatshogi move file numbers between Fairy-Stockfish and library conventions.
-///
-/// Fairy-Stockfish uses: file 1 = rightmost, file 3 = leftmost
-/// shogi-rs uses: file 1 = leftmost, file 3 = rightmost
-///
-/// Conversion formula: new_file = 4 - old_file
-fn convert_move_files(sfen: &str) -> String {
-    let chars: Vec<char> = sfen.chars().collect();
-
-    // Drop move (e.g., "P*2b")
-    if chars.len() >= 4 && chars[1] == '*' {
-        let piece = chars[0];
-        let dest_file = chars[2];
-        let dest_rank = chars[3];
-
-        if let Some(digit) = dest_file.to_digit(10) {
-            let converted_file = 4 - digit;
-            return format!("{}*{}{}", piece, converted_file, dest_rank);
-        }
-        return sfen.to_string();
-    }
-
-    // Normal move (e.g., "3a1c" or "3a1c+")
-    if chars.len() >= 4 {
-        let from_file = chars[0];
-        let from_rank = chars[1];
-        let to_file = chars[2];
-        let to_rank = chars[3];
-        let promotion = if chars.len() > 4 { &sfen[4..] } else { "" };
-
-        if let (Some(ff), Some(tf)) = (from_file.to_digit(10), to_file.to_digit(10)) {
-            let converted_from = 4 - ff;
-            let converted_to = 4 - tf;
-            return format!(
-                "{}{}{}{}{}",
-                converted_from, from_rank, converted_to, to_rank, promotion
-            );
+    if let Some(cli::Command::Verify {
+        input,
+        output,
+        show_manifest,
+    }) = &args.command
+    {
+        if *show_manifest {
+            run_show_manifest(input);
         }
+        run_verify(&args, input, output);
     }
-
-    sfen.to_string()
-}
-
-/// Collected info from engine during a search.
-#[derive(Debug, Clone)]
-struct PvInfo {
-    multipv: i32,
-    score: i32,
-    moves: Vec<String>,
-}
-
-/// Engine wrapper that maintains communication channels.
-struct Engine {
-    handler: UsiEngineHandler,
-    rx: Receiver<EngineCommand>,
-}
-
-/// Result of a search - either a move or game end
-#[derive(Debug)]
-enum SearchResult {
-    Move(String),
-    Checkmate,
-    Resign,
-}
-
-impl Engine {
-    fn spawn() -> Option<Self> {
-        let mut handler =
-            UsiEngineHandler::spawn(FAIRY_STOCKFISH, ".", &["load", VARIANTS_INI_PATH]).ok()?;
-
-        // Set protocol to USI before handshake (required for fairy-stockfish)
-        handler
-            .send_command_before_handshake(&GuiCommand::SetOption(
-                "Protocol".to_string(),
-                Some("usi".to_string()),
-            ))
-            .ok()?;
-
-        // Complete handshake
-        handler.get_info().ok()?;
-
-        // Set variant AFTER handshake
-        handler
-            .send_command(&GuiCommand::SetOption(
-                "UCI_Variant".to_string(),
-                Some("wildcatshogi".to_string()),
-            ))
-            .ok()?;
-
-        // Set MultiPV for getting multiple moves
-        handler
-            .send_command(&GuiCommand::SetOption(
-                "MultiPV".to_string(),
-                Some(MULTIPV_K.to_string()),
-            ))
-            .ok()?;
-
-        // Disable contempt for objective play
-        handler
-            .send_command(&GuiCommand::SetOption(
-                "Contempt".to_string(),
-                Some("0".to_string()),
-            ))
-            .ok()?;
-
-        // Penalize draws heavily to encourage decisive games
-        handler
-            .send_command(&GuiCommand::SetOption(
-                "DrawScore".to_string(),
-                Some("1000".to_string()),
-            ))
-            .ok()?;
-
-        // Disable resignation (set to minimum so engine never resigns)
-        handler
-            .send_command(&GuiCommand::SetOption(
-                "ResignValue".to_string(),
-                Some("-32767".to_string()),
-            ))
-            .ok()?;
-
-        // Analysis mode prevents early exit on mate
-        handler
-            .send_command(&GuiCommand::SetOption(
-                "UCI_AnalyseMode".to_string(),
-                Some("true".to_string()),
-            ))
-            .ok()?;
-
-        // TsumeMode disables try rule, focuses on checkmate only
-        handler
-            .send_command(&GuiCommand::SetOption(
-                "TsumeMode".to_string(),
-                Some("true".to_string()),
-            ))
-            .ok()?;
-
-        handler.prepare().ok()?;
-        handler.send_command(&GuiCommand::UsiNewGame).ok()?;
-
-        // Set up listener channel
-        let (tx, rx): (Sender<EngineCommand>, Receiver<EngineCommand>) = channel();
-
-        handler
-            .listen(move |output| -> Result<(), std::io::Error> {
-                if let Some(cmd) = output.response() {
-                    let _ = tx.send(cmd.clone());
-                }
-                Ok(())
-            })
-            .ok()?;
-
-        Some(Engine { handler, rx })
+    if let Some(cli::Command::Merge { inputs, output }) = &args.command {
+        run_merge(inputs, output);
+    }
+    if args.verify_ms == 0
+        && (args.min_mate.is_some()
+            || args.max_mate.is_some()
+            || args.unique_solution
+            || args.strictly_forced
+            || args.min_difficulty.is_some()
+            || args.max_difficulty.is_some()
+            || args.plies_before_mate > 1
+            || args.require_drop
+            || args.require_promotion
+            || args.require_attacker_drops)
+    {
+        eprintln!(
+            "--min-mate/--max-mate/--unique-solution/--strictly-forced/--min-difficulty/\
+             --max-difficulty/--plies-before-mate/--require-drop/--require-promotion/\
+             --require-attacker-drops require verification (--verify-ms > 0)"
+        );
+        std::process::exit(2);
     }
 
-    fn set_position(&mut self, move_history: &[String]) -> Option<()> {
-        // Note: GuiCommand::Position already prepends "position sfen"
-        let sfen = if move_history.is_empty() {
-            STARTING_SFEN.to_string()
-        } else {
-            format!("{} moves {}", STARTING_SFEN, move_history.join(" "))
-        };
-        self.handler.send_command(&GuiCommand::Position(sfen)).ok()
-    }
-
-    fn search_with_time(&mut self, time_ms: u64) -> Option<(Vec<PvInfo>, SearchResult)> {
-        // Start search with time limit
-        let params = ThinkParams::new().byoyomi(Duration::from_millis(time_ms));
-        self.handler.send_command(&GuiCommand::Go(params)).ok()?;
-
-        // Collect PV info and wait for bestmove
-        let mut pv_infos: Vec<PvInfo> = Vec::new();
-        let mut current_multipv: i32 = 1;
-        let mut current_score: i32 = 0;
-        let mut current_moves: Vec<String> = Vec::new();
-
-        loop {
-            match self.rx.recv_timeout(Duration::from_secs(30)) {
-                Ok(cmd) => match cmd {
-                    EngineCommand::Info(params) => {
-                        for param in params {
-                            match param {
-                                InfoParams::MultiPv(pv) => {
-                                    current_multipv = pv;
-                                }
-                                InfoParams::Score(score, kind) => {
-                                    current_score = match kind {
-                                        ScoreKind::CpExact
-                                        | ScoreKind::CpLowerbound
-                                        | ScoreKind::CpUpperbound => score,
-                                        ScoreKind::MateExact
-                                        | ScoreKind::MateSignOnly
-                                        | ScoreKind::MateLowerbound
-                                        | ScoreKind::MateUpperbound => {
-                                            if score > 0 { 10000 } else { -10000 }
-                                        }
-                                    };
-                                }
-                                InfoParams::Pv(moves) => {
-                                    current_moves = moves;
-                                }
-                                _ => {}
-                            }
-                        }
-                        // Store PV info when we have moves
-                        if !current_moves.is_empty() {
-                            if let Some(existing) =
-                                pv_infos.iter_mut().find(|p| p.multipv == current_multipv)
-                            {
-                                existing.score = current_score;
-                                existing.moves = current_moves.clone();
-                            } else {
-                                pv_infos.push(PvInfo {
-                                    multipv: current_multipv,
-                                    score: current_score,
-                                    moves: current_moves.clone(),
-                                });
-                            }
-                        }
-                    }
-                    EngineCommand::BestMove(params) => {
-                        let result = match params {
-                            BestMoveParams::MakeMove(mv, _) => SearchResult::Move(mv),
-                            BestMoveParams::Resign => SearchResult::Resign,
-                            BestMoveParams::Win => SearchResult::Checkmate,
-                        };
-                        return Some((pv_infos, result));
-                    }
-                    _ => {}
-                },
-                Err(_) => {
-                    return None;
+    if args.mode == GenerationMode::Selfplay
+        && (args.weak_mode.is_some()
+            || args.temperature > 0
+            || args.weak_elo.is_some()
+            || args.skill_level.is_some())
+    {
+        eprintln!(
+            "--weak-mode/--temperature/--weak-elo/--skill-level do not apply to --mode selfplay"
+        );
+        std::process::exit(2);
+    }
+    let weak_mode = args.weak_mode();
+    if weak_mode != WeakMode::Skill && (args.weak_elo.is_some() || args.skill_level.is_some()) {
+        eprintln!("--weak-elo/--skill-level require --weak-mode skill");
+        std::process::exit(2);
+    }
+    if weak_mode != WeakMode::Worstpv && args.temperature > 0 {
+        eprintln!("--temperature requires --weak-mode worstpv");
+        std::process::exit(2);
+    }
+    if args.rate_puzzles && (args.format != OutputFormat::Jsonl || args.verify_ms == 0) {
+        eprintln!("--rate-puzzles requires --format jsonl and verification");
+        std::process::exit(2);
+    }
+    let game_collection = match &args.command {
+        Some(cli::Command::Mine { games }) => {
+            if args.verify_ms == 0 {
+                eprintln!("mine requires verification (--verify-ms > 0)");
+                std::process::exit(2);
+            }
+            let seeded = args.seeds.is_some() || args.max_opening_reuse.is_some();
+            if seeded || args.work_range.is_some() {
+                eprintln!("--seeds/--max-opening-reuse/--work-range do not apply to mine");
+                std::process::exit(2);
+            }
+            match GameCollection::load(games) {
+                Ok(games) => Some(Arc::new(games)),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
                 }
             }
         }
+        _ => None,
+    };
+    if args.jobs == 0 {
+        eprintln!("--jobs must be at least 1");
+        std::process::exit(2);
     }
-
-    fn search(&mut self) -> Option<(Vec<PvInfo>, SearchResult)> {
-        // Try with normal time first
-        let (pv_infos, result) = self.search_with_time(SEARCH_TIME_MS)?;
-
-        // If we got resign with no PV, retry with longer time
-        if matches!(result, SearchResult::Resign) && pv_infos.is_empty() {
-            return self.search_with_time(SEARCH_TIME_MS * 5);
-        }
-
-        Some((pv_infos, result))
+    if let Err(e) = args.check_engine_threads() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+    if args.shards == 0 || args.shard_index >= args.shards {
+        eprintln!("--shard-index must be below --shards");
+        std::process::exit(2);
+    }
+    let target = args.output_target();
+    if args.append && target == OutputTarget::Stdout {
+        eprintln!("--append requires an output file");
+        std::process::exit(2);
     }
 
-    fn get_best_move(&mut self) -> Option<SearchResult> {
-        let (pv_infos, result) = self.search()?;
-
-        // Always prefer PV info regardless of result
-        if let Some(pv1) = pv_infos.iter().find(|pv| pv.multipv == 1) {
-            if let Some(mv) = pv1.moves.first() {
-                return Some(SearchResult::Move(mv.clone()));
+    let seed_positions = match &args.seeds {
+        Some(path) => match load_seed_positions(path) {
+            Ok(positions) => positions,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
             }
-        }
+        },
+        None => Vec::new(),
+    };
 
-        // Fallback to bestmove if PV empty
-        match result {
-            SearchResult::Move(best_move) => Some(SearchResult::Move(best_move)),
-            SearchResult::Checkmate => Some(SearchResult::Checkmate),
-            SearchResult::Resign => None, // No move available
-        }
+    if let Err(e) = shutdown::install() {
+        eprintln!("Warning: could not install Ctrl-C handler: {}", e);
     }
 
-    fn get_worst_move(&mut self) -> Option<SearchResult> {
-        let (pv_infos, result) = self.search()?;
+    let seed = args.seed.unwrap_or_else(rand::random);
+    eprintln!("Seed: {}", seed);
+
+    check_variants_ini(&args);
+    let existing = match target.path().filter(|_| args.append) {
+        Some(path) => read_existing(&args, path),
+        None => ExistingOutput::default(),
+    };
+
+    // Start every engine up front so a bad configuration fails before any work
+    let engine_config = args.engine_config();
+    let white_engine_config = args.white_engine_config();
+    let spawn = |config| Engine::spawn_with_retry(config, SPAWN_ATTEMPTS, SPAWN_RETRY_DELAY);
+    let engines = match (0..args.jobs)
+        .map(|_| {
+            let white_engine = white_engine_config.as_ref().map(spawn).transpose()?;
+            Ok((spawn(&engine_config)?, white_engine))
+        })
+        .collect::<Result<Vec<_>, EngineError>>()
+    {
+        Ok(engines) => engines,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let earlier_runs = match target.path().filter(|_| args.append) {
+        Some(path) => match Manifest::read_all(path) {
+            Ok(runs) => runs,
+            Err(e) => {
+                eprintln!("Error: cannot read manifest: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => Vec::new(),
+    };
+    let mut manifest = Manifest::new(seed);
+    manifest.shard = (args.shards > 1).then_some(Shard {
+        index: args.shard_index,
+        count: args.shards,
+    });
+    manifest.work_range = args.work_range.clone();
+    let (engine, white_engine) = &engines[0];
+    manifest.engine = Some(EngineIdentity::new(&engine_config.engine, engine));
+    manifest.white_engine = white_engine
+        .as_ref()
+        .zip(white_engine_config.as_ref())
+        .map(|(engine, config)| EngineIdentity::new(&config.engine, engine));
+    if let Err(e) = manifest.set_variants_ini(&engine_config.variants_ini) {
+        eprintln!("Warning: cannot hash variants file: {}", e);
+    }
 
-        // Always prefer PV info - pick worst scoring move
-        let worst_move = pv_infos
-            .iter()
-            .filter(|pv| !pv.moves.is_empty())
-            .min_by_key(|pv| pv.score)
-            .and_then(|pv| pv.moves.first().cloned());
+    // Openings are only counted from the output's earlier runs with --append
+    let opening_book = args.max_opening_reuse.map(|max_reuse| {
+        let book = match target.path().filter(|_| args.append) {
+            Some(path) => OpeningBook::load(path, max_reuse).unwrap_or_else(|e| {
+                eprintln!("Error: cannot read opening counts: {}", e);
+                std::process::exit(1);
+            }),
+            None => OpeningBook::new(max_reuse),
+        };
+        Arc::new(Mutex::new(book))
+    });
+
+    let (queue, collector) = TsumeWorkQueue::new(QUEUE_BUFFER);
+    let collector = collector
+        .format(args.format)
+        .diagram(args.with_diagram)
+        .append(args.append)
+        .run_to(target.clone());
+    // Written up front so a run that dies still leaves a record; a run to
+    // stdout has no file to keep one next to
+    if let Some(path) = target.path()
+        && let Err(e) = manifest.write(path, &earlier_runs)
+    {
+        eprintln!("Error: cannot write manifest: {}", e);
+        std::process::exit(1);
+    }
 
-        if let Some(mv) = worst_move {
-            return Some(SearchResult::Move(mv));
+    let games_file = match args.games_file.as_deref().map(File::create).transpose() {
+        Ok(file) => file.map(|file| Mutex::new(LineWriter::new(file))),
+        Err(e) => {
+            eprintln!("Error: cannot create games file: {}", e);
+            std::process::exit(1);
         }
-
-        // Fallback to bestmove if PV empty
-        match result {
-            SearchResult::Move(best_move) => Some(SearchResult::Move(best_move)),
-            SearchResult::Checkmate => Some(SearchResult::Checkmate),
-            SearchResult::Resign => None, // No move available
+    };
+    let reject_log = args
+        .reject_log
+        .as_deref()
+        .map(|path| OpenOptions::new().create(true).append(true).open(path))
+        .transpose();
+    let reject_log = match reject_log {
+        Ok(file) => file.map(|file| Mutex::new(LineWriter::new(file))),
+        Err(e) => {
+            eprintln!("Error: cannot open reject log: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let progress = Progress {
+        accepted: AtomicUsize::new(0),
+        seen: Mutex::new(existing.fingerprints),
+        engine_restarts: AtomicUsize::new(0),
+        fatal: OnceLock::new(),
+        games_file,
+        reject_log,
+        reporter: ProgressReporter::new("puzzles found", args.count),
+        units: args.work_range.as_ref().map(|range| WorkUnits {
+            seed,
+            next: AtomicU64::new(range.start),
+            end: range.end,
+        }),
+    };
+    let mut stats = RunStats::default();
+    stats.mode = args.mode();
+    thread::scope(|scope| {
+        let workers: Vec<_> = engines
+            .into_iter()
+            .enumerate()
+            .map(|(worker, (engine, white_engine))| {
+                let queue = queue.clone();
+                let (args, progress) = (&args, &progress);
+                let mut generator =
+                    TsumeGenerator::new(engine, args.generator_config(seed_positions.clone()));
+                if let Some(white_engine) = white_engine {
+                    generator.set_white_engine(white_engine);
+                }
+                if let Some(book) = &opening_book {
+                    generator.set_opening_book(book.clone());
+                }
+                if let Some(games) = &game_collection {
+                    generator.set_game_collection(games.clone());
+                }
+                if args.require_drop {
+                    generator.add_filter(filter_requires_drop());
+                }
+                if args.require_promotion {
+                    generator.add_filter(filter_requires_promotion());
+                }
+                if args.require_attacker_drops {
+                    generator.add_filter(filter_requires_attacker_drop());
+                }
+                if args.require_defender_hand || args.require_empty_defender_hand {
+                    generator.add_position_filter(filter_defender_hand(args.require_defender_hand));
+                }
+                if let Some(pieces) = args.max_defender_pieces {
+                    generator.add_position_filter(filter_max_defender_pieces(pieces));
+                }
+                if let Some(pieces) = args.max_total_pieces {
+                    generator.add_position_filter(filter_max_total_pieces(pieces));
+                }
+                for piece in &args.require_piece {
+                    generator.add_position_filter(filter_requires_piece(*piece));
+                }
+                // Every random choice of a worker flows from its own seeded generator
+                let mut rng =
+                    StdRng::seed_from_u64(args.shard_seed(seed).wrapping_add(worker as u64));
+                scope.spawn(move || {
+                    let stats = run_worker(&mut generator, args, &mut rng, progress, &queue);
+                    generator
+                        .into_engines()
+                        .for_each(|mut engine| engine.quit());
+                    stats
+                })
+            })
+            .collect();
+        for worker in workers {
+            stats.merge(worker.join().expect("Worker thread panicked"));
+        }
+    });
+    drop(queue);
+    collector
+        .join()
+        .expect("Collector thread panicked")
+        .expect("Failed to write to file");
+
+    let k = args.multipv_weak;
+    match args.white_strategy() {
+        // No game was played
+        _ if game_collection.is_some() => {}
+        Strategy::Best if args.mode == GenerationMode::Selfplay => {
+            eprintln!("Self-play: both sides play their best move")
+        }
+        Strategy::Best => match args.white_engine_config() {
+            Some(config) => eprintln!("White weak mode: engine ({})", config.engine.display()),
+            None => eprintln!("White weak mode: engine"),
+        },
+        Strategy::Weakened(strength) => eprintln!("White weak mode: skill ({})", strength),
+        Strategy::SampledWorst(temperature) => {
+            eprintln!(
+                "White weak mode: worstpv (MultiPV {}, temperature {})",
+                k, temperature
+            )
         }
+        _ => eprintln!("White weak mode: worstpv (MultiPV {})", k),
+    }
+    if game_collection.is_none() {
+        eprintln!("Random opening plies: {}", args.random_plies());
+    }
+    stats.print_summary(&target);
+    match &game_collection {
+        Some(games) => eprintln!(
+            "Games mined: {} of {} ({} without a result skipped)",
+            stats.games_mined,
+            games.len(),
+            games.skipped
+        ),
+        None => stats.games.print_summary(),
+    }
+    stats.timings.print_summary();
+    // The puzzles are written; a file kept next to them failing still fails the run
+    let mut unwritten = false;
+    if let Some(path) = &args.timings
+        && let Err(e) = stats.timings.write_rows(path)
+    {
+        eprintln!("Error: cannot write timings: {}", e);
+        unwritten = true;
+    }
+    if let Some((book, path)) = opening_book.as_ref().zip(target.path())
+        && let Err(e) = book.lock().unwrap().save(path)
+    {
+        eprintln!("Error: cannot write opening counts: {}", e);
+        unwritten = true;
+    }
+    manifest.finish(stats);
+    if let Some(path) = target.path()
+        && let Err(e) = manifest.write(path, &earlier_runs)
+    {
+        eprintln!("Error: cannot write manifest: {}", e);
+        unwritten = true;
+    }
+    if let Some(message) = progress.fatal.get() {
+        eprintln!("Error: {}", message);
+        std::process::exit(1);
+    }
+    if unwritten {
+        std::process::exit(1);
+    }
+    if shutdown::requested() {
+        std::process::exit(shutdown::INTERRUPTED_EXIT_CODE);
     }
-
 }
 
-fn main() {
-    use std::env;
-    use std::fs::File;
-    use std::io::Write;
-
-    let args: Vec<String> = env::args().collect();
-    let output_file = args.get(1).map(|s| s.as_str()).unwrap_or("results.sfen");
-    let target_count: usize = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(1000);
-
-    let mut engine = Engine::spawn().expect("Failed to spawn engine");
-    let mut file = File::create(output_file).expect("Failed to create output file");
-    let mut count = 0;
-
-    while count < target_count {
-        if let Some(sfen) = generate_tsume(&mut engine) {
-            writeln!(file, "{}", sfen).expect("Failed to write to file");
-            count += 1;
+/// Print the version of Black's engine, and of White's if separate, and exit.
+fn run_check_engine(args: &cli::Args) -> ! {
+    let configs = std::iter::once(args.engine_config()).chain(args.white_engine_config());
+    let mut failed = false;
+    for config in configs {
+        match check_fairy_stockfish_version(&config.engine) {
+            Ok(version) => println!("{}: {}", config.engine.display(), version),
+            Err(e) => {
+                eprintln!("Error: {}: {}", config.engine.display(), e);
+                failed = true;
+            }
         }
     }
-
-    eprintln!("Done: {} -> {}", count, output_file);
+    std::process::exit(if failed { 1 } else { 0 });
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Print every run recorded in the manifest of `input` and exit.
+fn run_show_manifest(input: &Path) -> ! {
+    match Manifest::read_all(input) {
+        Ok(runs) if runs.is_empty() => {
+            eprintln!("No manifest: {}", Manifest::path_for(input).display());
+            std::process::exit(1);
+        }
+        Ok(runs) => {
+            for (i, run) in runs.iter().enumerate() {
+                println!("Run {}: {}", i + 1, run);
+            }
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Error: cannot read manifest: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    #[test]
-    fn test_position_only_sfen() {
-        assert_eq!(
-            position_only_sfen("bkr/p1p/3/P1P/RKB b - 1"),
-            "bkr/p1p/3/P1P/RKB b - 1"
+/// Puzzles already in the output file, reporting unreadable lines.
+///
+/// Exits if the file is in another format, or with `--strict` on any unreadable line.
+fn read_existing(args: &cli::Args, output: &Path) -> ExistingOutput {
+    let existing = match read_existing_output(output, args.format) {
+        Ok(existing) => existing,
+        Err(e) => {
+            eprintln!("Error: cannot append to {}: {}", output.display(), e);
+            std::process::exit(1);
+        }
+    };
+    for (line, reason) in &existing.malformed {
+        eprintln!(
+            "Warning: {}:{}: unreadable puzzle: {}",
+            output.display(),
+            line,
+            reason
         );
-        assert_eq!(
-            position_only_sfen("bkr/p1p/3/P1P/RKB b - 1 moves 1e2d 3a2b"),
-            "bkr/p1p/3/P1P/RKB b - 1"
+    }
+    if args.strict && !existing.malformed.is_empty() {
+        eprintln!(
+            "Error: {} unreadable lines in {} (--strict)",
+            existing.malformed.len(),
+            output.display()
         );
+        std::process::exit(1);
     }
+    eprintln!(
+        "Appending to {}: {} puzzles already present",
+        output.display(),
+        existing.fingerprints.len()
+    );
+    existing
+}
 
-    #[test]
-    fn test_ensure_black_to_move_already_black() {
-        let sfen = "bkr/p1p/3/P1P/RKB b - 1";
-        assert_eq!(ensure_black_to_move(sfen), sfen);
+/// Merge output files into one, report what was left out of each and exit.
+fn run_merge(inputs: &[PathBuf], output: &Path) -> ! {
+    let merged = match merge_outputs(inputs, output) {
+        Ok(merged) => merged,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    for file in &merged {
+        eprintln!(
+            "{}: {} puzzles, {} duplicates within the file, {} found in an earlier file, {} unreadable",
+            file.path.display(),
+            file.puzzles,
+            file.duplicates,
+            file.cross_file_duplicates,
+            file.unreadable
+        );
     }
+    let total = |count: fn(&MergedFile) -> usize| merged.iter().map(count).sum::<usize>();
+    eprintln!(
+        "Merged {} puzzles into {}; {} duplicates across files",
+        total(|file| file.puzzles),
+        output.display(),
+        total(|file| file.cross_file_duplicates)
+    );
+    std::process::exit(0);
+}
 
-    #[test]
-    fn test_ensure_black_to_move_white_to_move() {
-        let sfen = "bkr/p1p/3/P1P/RKB w - 1";
-        let result = ensure_black_to_move(sfen);
-        assert!(result.contains(" b "), "Should be Black to move after mirror");
+/// Exit unless variants.ini defines the variant as the library plays it.
+///
+/// A missing file is left to the engine spawn, which reports it.
+fn check_variants_ini(args: &cli::Args) {
+    let path = args.engine_config().variants_ini;
+    if !path.is_file() {
+        return;
     }
-
-    #[test]
-    fn test_convert_move_files_normal() {
-        // Fairy-Stockfish "1e2d" -> shogi-rs "3e2d"
-        assert_eq!(convert_move_files("1e2d"), "3e2d");
-        assert_eq!(convert_move_files("3a2b"), "1a2b");
-        assert_eq!(convert_move_files("2c2c"), "2c2c"); // file 2 stays 2
+    let checked =
+        parse_variants_ini(&path, args.variant).and_then(|config| config.check(args.variant));
+    if let Err(e) = checked {
+        eprintln!("Error: {}: {}", path.display(), e);
+        std::process::exit(1);
     }
+}
 
-    #[test]
-    fn test_convert_move_files_drop() {
-        // Fairy-Stockfish "P*2c" -> shogi-rs "P*2c" (file 2 stays 2)
-        assert_eq!(convert_move_files("P*2c"), "P*2c");
-        assert_eq!(convert_move_files("P*1a"), "P*3a");
-        assert_eq!(convert_move_files("B*3e"), "B*1e");
+/// Verify every puzzle of `input`, write the annotations to `output` and exit.
+fn run_verify(args: &cli::Args, input: &Path, output: &Path) -> ! {
+    if args.verify_ms == 0 || args.jobs == 0 {
+        eprintln!("verify requires --verify-ms > 0 and --jobs of at least 1");
+        std::process::exit(2);
     }
-
-    #[test]
-    fn test_convert_move_files_promotion() {
-        assert_eq!(convert_move_files("1a1b+"), "3a3b+");
-        assert_eq!(convert_move_files("3d3e+"), "1d1e+");
+    if let Err(e) = args.check_engine_threads() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+    let lines = match read_puzzle_lines(input) {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("Error: cannot read {}: {}", input.display(), e);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = shutdown::install() {
+        eprintln!("Warning: could not install Ctrl-C handler: {}", e);
+    }
+    check_variants_ini(args);
+    let pool = match EnginePool::new(args.jobs, &args.engine_config()) {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let reporter = ProgressReporter::new("puzzles verified", lines.len());
+    let puzzles = annotate_puzzles(&pool, &lines, args.verify_ms, args.jobs, &reporter);
+    drop(pool);
+    if let Err(e) = write_annotated(&puzzles, output) {
+        eprintln!("Error: cannot write {}: {}", output.display(), e);
+        std::process::exit(1);
     }
+    AnnotationSummary::of(&puzzles).print_summary(output);
+    if shutdown::requested() {
+        std::process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+    }
+    std::process::exit(0);
 }
 
-/// Result of a single game simulation
-enum GameResult {
-    /// Game ended in checkmate
-    Checkmate(String),
-    /// Game did not end within move limit
-    NoResult,
-    /// Error during simulation
-    Error,
+/// State shared by all workers of a run.
+struct Progress {
+    /// Puzzles claimed for output so far
+    accepted: AtomicUsize,
+    /// Duplicate keys of positions already claimed
+    seen: Mutex<HashSet<String>>,
+    /// Engine restarts across all workers
+    engine_restarts: AtomicUsize,
+    /// Error that stops every worker
+    fatal: OnceLock<String>,
+    /// Source games of accepted puzzles (`--games-file`)
+    games_file: Option<Mutex<LineWriter<File>>>,
+    /// Rejected candidates and failed games (`--reject-log`)
+    reject_log: Option<Mutex<LineWriter<File>>>,
+    /// Progress lines as puzzles are accepted
+    reporter: ProgressReporter,
+    /// Units left to play with `--work-range`
+    units: Option<WorkUnits>,
 }
 
-fn simulate_game(engine: &mut Engine) -> GameResult {
-    let mut position = Position::startpos();
-    let mut move_history: Vec<String> = Vec::new();
-    let mut is_black_turn = true;
-    let mut sfen_before_last_move = String::new(); // SFEN before the last move was made
+/// Work units of a `--work-range` run, handed out to the workers in order.
+struct WorkUnits {
+    /// Seed of the run, from which each unit's seed is derived
+    seed: u64,
+    /// Next unit to hand out
+    next: AtomicU64,
+    /// End of the range, excluded
+    end: u64,
+}
 
-    for _move_num in 0..MAX_MOVES {
-        let current_sfen = position_only_sfen(&position.to_sfen());
+impl WorkUnits {
+    /// Random number generator of the next unit; none once the range is used up.
+    fn next_rng(&self) -> Option<StdRng> {
+        let unit = self
+            .next
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.end).then_some(n + 1)
+            })
+            .ok()?;
+        Some(StdRng::seed_from_u64(cli::unit_seed(self.seed, unit)))
+    }
+}
 
-        if engine.set_position(&move_history).is_none() {
-            return GameResult::Error;
-        }
+impl Progress {
+    fn is_done(&self, count: usize) -> bool {
+        self.accepted.load(Ordering::SeqCst) >= count || self.fatal.get().is_some()
+    }
 
-        // Black (sente) plays best, White (gote) plays worst -> Black will checkmate White
-        let result = if is_black_turn {
-            engine.get_best_move()
-        } else {
-            engine.get_worst_move()
+    /// Append `record` to the reject log, if any; a write error stops the run.
+    fn log_reject(&self, record: RejectRecord) {
+        let Some(reject_log) = &self.reject_log else {
+            return;
         };
+        if let Err(e) = writeln!(reject_log.lock().unwrap(), "{}", record) {
+            let _ = self.fatal.set(format!("failed to write reject log: {}", e));
+        }
+    }
 
-        let result = match result {
-            Some(r) => r,
-            None => {
-                // No legal moves = loss in shogi (no stalemate)
-                // If Black lost (White won), flip the board so Black is the attacker
-                let sfen = if is_black_turn {
-                    let flipped = mirror_sfen(&sfen_before_last_move);
-                    let parts: Vec<&str> = flipped.split_whitespace().collect();
-                    if parts.len() >= 4 {
-                        format!("{} {} {} 1", parts[0], parts[1], parts[2])
-                    } else {
-                        flipped
-                    }
-                } else {
-                    sfen_before_last_move
-                };
-                return GameResult::Checkmate(sfen);
+    /// Reserve one of the `count` output slots; fails once all are taken.
+    fn claim_slot(&self, count: usize) -> bool {
+        self.accepted
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < count).then_some(n + 1)
+            })
+            .is_ok()
+    }
+}
+
+/// Generate puzzles on one engine until the run has enough, submitting them to `queue`.
+fn run_worker(
+    generator: &mut TsumeGenerator,
+    args: &cli::Args,
+    rng: &mut StdRng,
+    progress: &Progress,
+    queue: &TsumeWorkQueue,
+) -> RunStats {
+    let mut stats = RunStats::default();
+    let mut puzzle_started = Instant::now();
+
+    while !progress.is_done(args.count) && !shutdown::requested() {
+        if let Some(failure) = generator.engine().failure() {
+            let config = args.engine_config();
+            if let Err(message) =
+                restart_engine(generator.engine(), &config, args, progress, failure)
+            {
+                let _ = progress.fatal.set(message);
+                break;
             }
-        };
+            stats.engine_restarts += 1;
+        }
+        if let Some(engine) = generator.white_engine()
+            && let Some(failure) = engine.failure()
+        {
+            let config = args
+                .white_engine_config()
+                .unwrap_or_else(|| args.engine_config());
+            if let Err(message) = restart_engine(engine, &config, args, progress, failure) {
+                let _ = progress.fatal.set(message);
+                break;
+            }
+            stats.engine_restarts += 1;
+        }
 
-        match result {
-            SearchResult::Move(chosen_move) => {
-                // Save position BEFORE this move (for tsume: position before checkmate)
-                sfen_before_last_move = current_sfen.clone();
-
-                let converted_move = convert_move_files(&chosen_move);
-                let mv = match Move::from_sfen(&converted_move) {
-                    Some(m) => m,
-                    None => return GameResult::Error,
-                };
-                if position.make_move(mv).is_err() {
-                    return GameResult::Error;
+        // With --work-range each candidate is searched from its unit's own seed
+        let mut unit_rng;
+        let rng = match &progress.units {
+            Some(units) => match units.next_rng() {
+                Some(next) => {
+                    unit_rng = next;
+                    &mut unit_rng
                 }
-
-                move_history.push(chosen_move);
-                is_black_turn = !is_black_turn;
+                None => break,
+            },
+            None => &mut *rng,
+        };
+        let candidate = generator.find_candidate(rng);
+        for failure in generator.take_failed_games() {
+            progress.log_reject(failure);
+        }
+        let Some(candidate) = candidate else {
+            if generator.is_exhausted() {
+                break;
             }
-            SearchResult::Checkmate => {
-                // If White wins (Black lost), flip the board so Black is the attacker
-                let sfen = if !is_black_turn {
-                    let flipped = mirror_sfen(&sfen_before_last_move);
-                    let parts: Vec<&str> = flipped.split_whitespace().collect();
-                    if parts.len() >= 4 {
-                        format!("{} {} {} 1", parts[0], parts[1], parts[2])
-                    } else {
-                        flipped
-                    }
-                } else {
-                    sfen_before_last_move
-                };
-                return GameResult::Checkmate(sfen);
+            continue;
+        };
+        if let Err(message) = candidate.validate() {
+            eprintln!("Warning: {}", message);
+            stats.record_malformed();
+            let (sfen, plies) = (candidate.tsume.sfen, candidate.tsume.ply);
+            progress.log_reject(RejectRecord::new(RejectStage::BadSfen, Some(sfen), plies));
+            continue;
+        }
+        let mut record = candidate.record();
+        let reject = |stage, record: &PuzzleRecord| {
+            let sfen = Some(record.sfen.clone());
+            progress.log_reject(RejectRecord::new(stage, sfen, record.ply_of_game));
+        };
+        // The same for the record turned or reflected, so computed only once
+        let key = duplicate_key(&record.sfen);
+        if progress.seen.lock().unwrap().contains(&key) {
+            stats.record_duplicate();
+            reject(RejectStage::Duplicate, &record);
+            continue;
+        }
+
+        if let Err(rejection) = generator.verify(&mut record) {
+            reject(rejection.into(), &record);
+            match rejection {
+                Rejection::Unverified => stats.record_unverified(),
+                // A failed engine proves nothing about the candidate
+                Rejection::EngineFailed => {}
+                Rejection::MateLength(mate) => stats.record_mate_length_rejected(mate),
+                Rejection::MultipleSolutions => stats.record_multiple_solutions(),
+                Rejection::Defended => stats.record_defended(),
+                Rejection::OutOfTime => stats.record_out_of_time(),
+                Rejection::Difficulty(_) => stats.record_difficulty_rejected(),
+                Rejection::Filtered => stats.record_filtered(),
             }
-            SearchResult::Resign => {
-                // Should not reach here - get_best_move/get_worst_move return None instead
-                unreachable!("Resign should be handled by returning None from move functions");
+            continue;
+        }
+
+        if args.audit
+            && let Err(violation) = audit_puzzle(&record)
+        {
+            let _ = progress.fatal.set(format!("audit failed: {}", violation));
+            break;
+        }
+        if args.no_mirror {
+            record.unmirror(&candidate.tsume.game_sfen);
+        }
+
+        // Another worker may have found the same position while this one was verifying
+        if !progress.seen.lock().unwrap().insert(key) {
+            stats.record_duplicate();
+            reject(RejectStage::Duplicate, &record);
+            continue;
+        }
+        if !progress.claim_slot(args.count) {
+            break;
+        }
+        let (mate_in, id, themes) = (record.mate_in, record.id.clone(), record.themes.clone());
+        let writing = Instant::now();
+        if queue.submit(record).is_err() {
+            // The collector stopped on a write error, reported when it is joined
+            break;
+        }
+        stats.record_accepted(mate_in);
+        stats.record_themes(&themes);
+        progress.reporter.record();
+
+        if let Some(games_file) = &progress.games_file {
+            let game = GameRecord {
+                // A mined game may have been resigned rather than mated
+                winner: candidate.winner(),
+                search_ms: candidate.search_ms,
+                ..GameRecord::mated(id.clone(), candidate.start_sfen, candidate.moves)
+            };
+            if let Err(e) = writeln!(games_file.lock().unwrap(), "{}", game) {
+                let message = format!("failed to write games file: {}", e);
+                let _ = progress.fatal.set(message);
+                break;
             }
         }
+        let mut stages = generator.take_stage_times();
+        stages.writing = writing.elapsed();
+        stats.timings.record_puzzle(PuzzleTiming {
+            id,
+            wall: puzzle_started.elapsed(),
+            stages,
+        });
+        puzzle_started = Instant::now();
     }
 
-    GameResult::NoResult
+    stats.timings.record_unused(generator.take_stage_times());
+    stats.stuck_games = generator.stuck_games();
+    stats.repetition_games = generator.repetition_games();
+    stats.opening_reuse_games = generator.opening_reuse_games();
+    stats.games_mined = generator.games_mined();
+    stats.escalations = generator.escalations();
+    stats.escalated_candidates = generator.escalated_candidates();
+    stats.games = generator.take_game_stats();
+    stats
 }
 
-fn generate_tsume(engine: &mut Engine) -> Option<String> {
-    for _attempt in 1..=MAX_ATTEMPTS {
-        match simulate_game(engine) {
-            GameResult::Checkmate(sfen) => {
-                return Some(sfen);
-            }
-            GameResult::NoResult | GameResult::Error => {}
-        }
+/// Replace a hung or crashed engine with a fresh process, within the run's restart budget.
+///
+/// Each game starts from scratch, so no state needs to be replayed.
+fn restart_engine(
+    engine: &mut Engine,
+    config: &EngineConfig,
+    args: &cli::Args,
+    progress: &Progress,
+    failure: EngineFailure,
+) -> Result<(), String> {
+    let restarts = progress.engine_restarts.fetch_add(1, Ordering::SeqCst) + 1;
+    if restarts > args.max_engine_restarts {
+        return Err(format!(
+            "{} and the restart limit (--max-engine-restarts {}) is used up",
+            failure, args.max_engine_restarts
+        ));
     }
 
-    None
+    eprintln!(
+        "Restarting engine: {} (restart {} of {})",
+        failure, restarts, args.max_engine_restarts
+    );
+    engine.quit();
+    *engine = Engine::spawn_with_retry(config, SPAWN_ATTEMPTS, SPAWN_RETRY_DELAY)
+        .map_err(|e| format!("{} and restarting it failed: {}", failure, e))?;
+    Ok(())
 }
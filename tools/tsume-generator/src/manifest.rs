@@ -0,0 +1,247 @@
+//! Run manifest written next to the output file.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::Engine;
+use crate::output::fnv1a;
+use crate::stats::RunStats;
+
+/// How one run generated, or added to, a puzzle file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    /// Version of the tsume generator
+    pub tool_version: String,
+    /// Command line of the run, program name included
+    pub args: Vec<String>,
+    /// Black's engine, which also plays White unless `white_engine` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engine: Option<EngineIdentity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub white_engine: Option<EngineIdentity>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variants_ini: Option<PathBuf>,
+    /// FNV-1a hash of the variants.ini contents, as 16 hex digits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variants_ini_hash: Option<String>,
+    /// Seed of the generator's random number generator
+    pub seed: u64,
+    /// Shard of a `--shards` run this file holds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard: Option<Shard>,
+    /// Work units of a `--work-range` run, END excluded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_range: Option<Range<u64>>,
+    /// Unix time in seconds
+    pub started_at: u64,
+    /// Unix time in seconds; missing while the run is going or if it died
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    /// Final statistics of the run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<RunStats>,
+}
+
+/// An engine as it introduced itself in the USI handshake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngineIdentity {
+    pub path: PathBuf,
+    /// `id name`
+    pub name: String,
+    /// `id author`
+    pub author: String,
+}
+
+/// One shard of a run split with `--shards`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    /// From 0
+    pub index: usize,
+    pub count: usize,
+}
+
+impl EngineIdentity {
+    pub fn new(path: &Path, engine: &Engine) -> Self {
+        EngineIdentity {
+            path: path.to_path_buf(),
+            name: engine.id_name().to_string(),
+            author: engine.id_author().to_string(),
+        }
+    }
+}
+
+impl Manifest {
+    /// A manifest of a run starting now with the command line of this process.
+    pub fn new(seed: u64) -> Self {
+        Manifest {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            args: std::env::args().collect(),
+            seed,
+            started_at: unix_time(),
+            ..Manifest::default()
+        }
+    }
+
+    /// Manifest path for an output file: `<output>.manifest.json`.
+    pub fn path_for(output: &Path) -> PathBuf {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".manifest.json");
+        PathBuf::from(path)
+    }
+
+    /// Record the variants.ini the engines load, hashing its contents.
+    pub fn set_variants_ini(&mut self, path: &Path) -> io::Result<()> {
+        let hash = fnv1a(&fs::read(path)?);
+        self.variants_ini = Some(path.to_path_buf());
+        self.variants_ini_hash = Some(format!("{:016x}", hash));
+        Ok(())
+    }
+
+    /// Mark the run finished now with its final statistics.
+    pub fn finish(&mut self, stats: RunStats) {
+        self.finished_at = Some(unix_time());
+        self.stats = Some(stats);
+    }
+
+    /// Every run recorded for `output`, oldest first; none if there is no manifest.
+    ///
+    /// Manifests of earlier versions hold a single run as a bare object.
+    pub fn read_all(output: &Path) -> io::Result<Vec<Manifest>> {
+        let text = match fs::read_to_string(Self::path_for(output)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Runs {
+            Many(Vec<Manifest>),
+            One(Box<Manifest>),
+        }
+        Ok(match serde_json::from_str(&text)? {
+            Runs::Many(runs) => runs,
+            Runs::One(run) => vec![*run],
+        })
+    }
+
+    /// Write the manifest of `output` as the runs `earlier` followed by this one.
+    pub fn write(&self, output: &Path, earlier: &[Manifest]) -> io::Result<()> {
+        let runs: Vec<&Manifest> = earlier.iter().chain([self]).collect();
+        Self::write_runs(output, &runs)
+    }
+
+    /// Write the manifest of `output` as exactly `runs`, as for a merged file.
+    pub fn write_runs(output: &Path, runs: &[&Manifest]) -> io::Result<()> {
+        let file = File::create(Self::path_for(output))?;
+        serde_json::to_writer_pretty(file, runs)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "tsume-generator {}", self.tool_version)?;
+        writeln!(f, "  command: {}", self.args.join(" "))?;
+        for (side, engine) in [
+            ("engine", &self.engine),
+            ("white engine", &self.white_engine),
+        ] {
+            if let Some(engine) = engine {
+                writeln!(
+                    f,
+                    "  {}: {} ({} by {})",
+                    side,
+                    engine.path.display(),
+                    engine.name,
+                    engine.author
+                )?;
+            }
+        }
+        if let (Some(path), Some(hash)) = (&self.variants_ini, &self.variants_ini_hash) {
+            writeln!(f, "  variants: {} ({})", path.display(), hash)?;
+        }
+        writeln!(f, "  seed: {}", self.seed)?;
+        if let Some(shard) = self.shard {
+            writeln!(f, "  shard: {} of {}", shard.index, shard.count)?;
+        }
+        match self.finished_at {
+            Some(finished_at) => writeln!(
+                f,
+                "  ran: {} to {} ({}s)",
+                self.started_at,
+                finished_at,
+                finished_at.saturating_sub(self.started_at)
+            )?,
+            None => writeln!(f, "  started: {} (did not finish)", self.started_at)?,
+        }
+        if let Some(stats) = &self.stats {
+            writeln!(
+                f,
+                "  accepted: {} of {} candidates, {} duplicates",
+                stats.accepted,
+                stats.candidates(),
+                stats.duplicates
+            )?;
+            writeln!(f, "  games: {} played", stats.games.total_games)?;
+        }
+        Ok(())
+    }
+}
+
+/// Seconds since the Unix epoch.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_for_appends_suffix() {
+        assert_eq!(
+            Manifest::path_for(Path::new("out/results.sfen")),
+            PathBuf::from("out/results.sfen.manifest.json")
+        );
+    }
+
+    #[test]
+    fn test_runs_accumulate() {
+        let output =
+            std::env::temp_dir().join(format!("tsume-manifest-{}.sfen", std::process::id()));
+        let path = Manifest::path_for(&output);
+        // A manifest of an earlier version
+        fs::write(&path, r#"{"seed": 42}"#).unwrap();
+
+        let earlier = Manifest::read_all(&output).unwrap();
+        assert_eq!(earlier.len(), 1);
+        assert_eq!(earlier[0].seed, 42);
+
+        let mut run = Manifest::new(7);
+        run.finish(RunStats::default());
+        run.write(&output, &earlier).unwrap();
+        let runs = Manifest::read_all(&output).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[1].seed, 7);
+        assert_eq!(runs[1].tool_version, env!("CARGO_PKG_VERSION"));
+        assert!(runs[1].stats.is_some());
+        assert!(runs[1].to_string().contains("seed: 7"));
+    }
+
+    #[test]
+    fn test_missing_manifest_has_no_runs() {
+        let output = Path::new("/nonexistent/tsume-generator/results.sfen");
+        assert!(Manifest::read_all(output).unwrap().is_empty());
+    }
+}
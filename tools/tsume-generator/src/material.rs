@@ -0,0 +1,195 @@
+//! Material counting.
+
+use clap::ValueEnum;
+use shogi::wildcatshogi::Position;
+
+/// A kind of piece besides the king, for `--require-piece`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PieceType {
+    Rook,
+    Bishop,
+    Gold,
+    /// A pawn, promoted or not
+    Pawn,
+}
+
+impl PieceType {
+    /// SFEN letter of the piece, Black's case.
+    pub fn letter(self) -> char {
+        match self {
+            PieceType::Rook => 'R',
+            PieceType::Bishop => 'B',
+            PieceType::Gold => 'G',
+            PieceType::Pawn => 'P',
+        }
+    }
+}
+
+/// Value of a piece letter (either case) in centipawns; the king counts 0.
+///
+/// Promoted pieces are written with a leading `+` in SFEN and valued by
+/// [`promoted_value`].
+pub fn piece_value(piece: char) -> i32 {
+    match piece.to_ascii_uppercase() {
+        'P' => 100,
+        'G' => 300,
+        'B' => 400,
+        'R' => 500,
+        _ => 0,
+    }
+}
+
+/// Value of a promoted piece; a pawn promotes to a gold-moving tokin.
+pub fn promoted_value(piece: char) -> i32 {
+    match piece.to_ascii_uppercase() {
+        'P' => piece_value('G'),
+        other => piece_value(other),
+    }
+}
+
+/// `(black, white)` totals of the pieces on the board and in hand.
+///
+/// Pieces in hand count at their unpromoted value, as they are dropped.
+pub fn count_material(position: &Position) -> (i32, i32) {
+    count_sfen_material(&position.to_sfen())
+}
+
+/// `(black, white)` numbers of pieces in hand.
+pub fn hand_sizes(position: &Position) -> (u32, u32) {
+    let sfen = position.to_sfen();
+    let hand = sfen.split_whitespace().nth(2).unwrap_or("-");
+    let (mut black, mut white) = (0, 0);
+    let mut count = 0;
+    for c in hand.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            count = count * 10 + digit;
+        } else if c.is_ascii_alphabetic() {
+            if c.is_ascii_uppercase() {
+                black += count.max(1);
+            } else {
+                white += count.max(1);
+            }
+            count = 0;
+        }
+    }
+    (black, white)
+}
+
+/// `(black, white)` numbers of pieces on the board, kings included.
+pub fn board_piece_counts(position: &Position) -> (u32, u32) {
+    let sfen = position.to_sfen();
+    let board = sfen.split_whitespace().next().unwrap_or_default();
+    let black = board.chars().filter(char::is_ascii_uppercase).count() as u32;
+    let white = board.chars().filter(char::is_ascii_lowercase).count() as u32;
+    (black, white)
+}
+
+/// Whether Black has a `piece` on the board or in hand.
+pub fn black_has_piece(position: &Position, piece: PieceType) -> bool {
+    let sfen = position.to_sfen();
+    let mut fields = sfen.split_whitespace();
+    let board = fields.next().unwrap_or_default();
+    let hand = fields.nth(1).unwrap_or("-");
+    board.contains(piece.letter()) || hand.contains(piece.letter())
+}
+
+fn count_sfen_material(sfen: &str) -> (i32, i32) {
+    let mut fields = sfen.split_whitespace();
+    let board = fields.next().unwrap_or_default();
+    let hand = fields.nth(1).unwrap_or("-");
+    let (mut black, mut white) = (0, 0);
+    let mut add = |piece: char, value: i32| {
+        if piece.is_ascii_uppercase() {
+            black += value;
+        } else {
+            white += value;
+        }
+    };
+
+    let mut promoted = false;
+    for c in board.chars() {
+        match c {
+            '+' => promoted = true,
+            c if c.is_ascii_alphabetic() => {
+                add(
+                    c,
+                    if promoted {
+                        promoted_value(c)
+                    } else {
+                        piece_value(c)
+                    },
+                );
+                promoted = false;
+            }
+            _ => {}
+        }
+    }
+
+    let mut count = 0;
+    for c in hand.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            count = count * 10 + digit as i32;
+        } else if c.is_ascii_alphabetic() {
+            add(c, count.max(1) * piece_value(c));
+            count = 0;
+        }
+    }
+    (black, white)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn material(sfen: &str) -> (i32, i32) {
+        count_material(&Position::from_sfen(sfen).unwrap())
+    }
+
+    #[test]
+    fn test_starting_position_is_equal() {
+        let (black, white) = count_material(&Position::startpos());
+        assert_eq!(black, white);
+        assert_eq!(black, 500 + 400 + 2 * 100);
+    }
+
+    #[test]
+    fn test_captured_rook_moves_to_hand() {
+        let (black, white) = material("bk1/p1p/3/P1P/RKB b R 1");
+        let (start_black, start_white) = count_material(&Position::startpos());
+        assert_eq!(black, start_black + 500);
+        assert_eq!(white, start_white - 500);
+        assert_eq!(black + white, start_black + start_white);
+    }
+
+    #[test]
+    fn test_all_pieces_captured_by_one_side() {
+        let (black, white) = material("1k1/3/3/3/1K1 b 2R2B4P 1");
+        assert_eq!(white, 0);
+        assert_eq!(black, 2 * 500 + 2 * 400 + 4 * 100);
+    }
+
+    #[test]
+    fn test_hand_sizes() {
+        assert_eq!(hand_sizes(&Position::startpos()), (0, 0));
+        let position = Position::from_sfen("1k1/3/3/3/1K1 b 2RB2pg 1").unwrap();
+        assert_eq!(hand_sizes(&position), (3, 3));
+    }
+
+    #[test]
+    fn test_board_pieces() {
+        assert_eq!(board_piece_counts(&Position::startpos()), (5, 5));
+        let position = Position::from_sfen("1k1/1P1/3/3/1K1 b Bg 1").unwrap();
+        assert_eq!(board_piece_counts(&position), (2, 1));
+
+        assert!(black_has_piece(&position, PieceType::Pawn));
+        assert!(black_has_piece(&position, PieceType::Bishop));
+        // White's gold is not Black's
+        assert!(!black_has_piece(&position, PieceType::Gold));
+        assert!(!black_has_piece(&position, PieceType::Rook));
+    }
+
+    #[test]
+    fn test_promoted_pawn_on_board() {
+        assert_eq!(count_sfen_material("1k1/1+P1/3/3/1K1 b - 1"), (300, 0));
+    }
+}
@@ -0,0 +1,239 @@
+//! Merging the output files of several runs or shards (`merge`) into one,
+//! each position kept once.
+//!
+//! Files are streamed a puzzle at a time; only the duplicate keys of the
+//! puzzles written are held in memory.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::manifest::Manifest;
+use crate::output::{OutputFormat, duplicate_key};
+use crate::resume::{StoredPuzzle, detect_file_format, read_puzzles};
+
+/// What became of the puzzles of one input file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergedFile {
+    pub path: PathBuf,
+    /// Puzzles written to the merged file
+    pub puzzles: usize,
+    /// Puzzles left out as already found earlier in the same file
+    pub duplicates: usize,
+    /// Puzzles left out as already found in an earlier file
+    pub cross_file_duplicates: usize,
+    /// Lines holding no readable puzzle, left out
+    pub unreadable: usize,
+}
+
+/// Where merged puzzles are written, as stored in the inputs.
+enum Sink {
+    Lines(BufWriter<File>),
+    Csv {
+        writer: Box<csv::Writer<File>>,
+        headers: Option<csv::StringRecord>,
+    },
+}
+
+impl Sink {
+    fn write(&mut self, puzzle: StoredPuzzle) -> io::Result<()> {
+        match (self, puzzle) {
+            (Sink::Lines(out), StoredPuzzle::Line(line)) => writeln!(out, "{}", line),
+            (
+                Sink::Csv { writer, headers },
+                StoredPuzzle::Row {
+                    headers: theirs,
+                    row,
+                },
+            ) => {
+                match headers {
+                    Some(ours) if ours != theirs => {
+                        let message = "CSV columns differ from the first file's";
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                    }
+                    Some(_) => {}
+                    None => {
+                        writer.write_record(theirs)?;
+                        *headers = Some(theirs.clone());
+                    }
+                }
+                writer.write_record(row).map_err(io::Error::from)
+            }
+            _ => unreachable!("a puzzle is stored as the output format stores it"),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Lines(out) => out.flush(),
+            Sink::Csv { writer, .. } => writer.flush(),
+        }
+    }
+}
+
+/// Write every puzzle of `inputs` to `output` in input order, leaving out
+/// positions already written and unreadable lines, and the runs of their
+/// manifests to the manifest of `output`.
+///
+/// All inputs must be in the format of the first; the error names the file
+/// that is not, or could not be read.
+pub fn merge_outputs(inputs: &[PathBuf], output: &Path) -> Result<Vec<MergedFile>, String> {
+    if let Some(input) = inputs.iter().find(|input| *input == output) {
+        return Err(format!(
+            "{} is both an input and the output",
+            input.display()
+        ));
+    }
+    let mut format = None;
+    for input in inputs {
+        format = detect_file_format(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+        if format.is_some() {
+            break;
+        }
+    }
+    // Inputs without a puzzle merge into an empty file of any format
+    let format = format.unwrap_or(OutputFormat::Plain);
+    let file = File::create(output).map_err(|e| format!("{}: {}", output.display(), e))?;
+    let mut sink = match format {
+        OutputFormat::Csv | OutputFormat::LichessCsv => Sink::Csv {
+            writer: Box::new(csv::Writer::from_writer(file)),
+            headers: None,
+        },
+        _ => Sink::Lines(BufWriter::new(file)),
+    };
+
+    // File each written position was first found in
+    let mut first_found: HashMap<String, usize> = HashMap::new();
+    let mut merged = Vec::with_capacity(inputs.len());
+    for (index, input) in inputs.iter().enumerate() {
+        let mut report = MergedFile {
+            path: input.clone(),
+            ..MergedFile::default()
+        };
+        let mut written = Ok(());
+        let reading = File::open(input).map_err(Into::into).and_then(|file| {
+            read_puzzles(file, format, |_, stored, sfen| {
+                let Ok(sfen) = sfen else {
+                    report.unreadable += 1;
+                    return;
+                };
+                match first_found.get(&duplicate_key(&sfen)) {
+                    Some(&found) if found == index => report.duplicates += 1,
+                    Some(_) => report.cross_file_duplicates += 1,
+                    None if written.is_ok() => {
+                        first_found.insert(duplicate_key(&sfen), index);
+                        report.puzzles += 1;
+                        written = sink.write(stored);
+                    }
+                    None => {}
+                }
+            })
+        });
+        reading.map_err(|e| format!("{}: {}", input.display(), e))?;
+        written.map_err(|e| format!("{}: {}", output.display(), e))?;
+        merged.push(report);
+    }
+    sink.flush()
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+
+    let mut runs = Vec::new();
+    for input in inputs {
+        let manifest = Manifest::read_all(input)
+            .map_err(|e| format!("cannot read manifest of {}: {}", input.display(), e))?;
+        runs.extend(manifest);
+    }
+    if !runs.is_empty() {
+        Manifest::write_runs(output, &runs.iter().collect::<Vec<_>>())
+            .map_err(|e| format!("cannot write manifest: {}", e))?;
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::output::{OutputWriter, PuzzleRecord};
+
+    fn temp(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tsume-merge-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_merge_plain_files() {
+        let (first, second, output) = (temp("a.sfen"), temp("b.sfen"), temp("out.sfen"));
+        fs::write(&first, "1k1/3/R2/3/K1B b GP 1\n1k1/3/R2/3/K1B b PG 7\n").unwrap();
+        // The first puzzle of `first` as played, with White to move
+        fs::write(
+            &second,
+            "# shard 1\nb1k/3/2r/3/1K1 w gp 3\tw\nnot a position\nk2/PBR/3/p1p/rbK b - 1\n",
+        )
+        .unwrap();
+        let mut manifest = Manifest::new(7);
+        manifest.args = vec!["shard".to_string()];
+        manifest.write(&second, &[]).unwrap();
+
+        let merged = merge_outputs(&[first.clone(), second.clone()], &output);
+        let text = fs::read_to_string(&output).unwrap();
+        let runs = Manifest::read_all(&output).unwrap();
+        for path in [&first, &second, &output] {
+            let _ = fs::remove_file(path);
+            let _ = fs::remove_file(Manifest::path_for(path));
+        }
+
+        let merged = merged.unwrap();
+        assert_eq!(text, "1k1/3/R2/3/K1B b GP 1\nk2/PBR/3/p1p/rbK b - 1\n");
+        assert_eq!((merged[0].puzzles, merged[0].duplicates), (1, 1));
+        assert_eq!(
+            (
+                merged[1].puzzles,
+                merged[1].cross_file_duplicates,
+                merged[1].unreadable
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].seed, 7);
+    }
+
+    #[test]
+    fn test_merge_csv_files() {
+        let (first, second, output) = (temp("a.csv"), temp("b.csv"), temp("out.csv"));
+        for (path, sfen) in [
+            (&first, "1k1/3/R2/3/K1B b G 1"),
+            (&second, "k2/PBR/3/p1p/rbK b - 1"),
+        ] {
+            let mut writer = OutputWriter::create(OutputFormat::Csv, path).unwrap();
+            writer
+                .write(&PuzzleRecord::new(sfen.to_string(), 3))
+                .unwrap();
+        }
+        let merged = merge_outputs(&[first.clone(), second.clone()], &output);
+        let text = fs::read_to_string(&output).unwrap();
+        let missing = merge_outputs(&[first.clone(), temp("missing.jsonl")], &temp("none.csv"));
+        for path in [&first, &second, &output, &temp("none.csv")] {
+            let _ = fs::remove_file(path);
+        }
+
+        assert_eq!(
+            merged
+                .unwrap()
+                .iter()
+                .map(|file| file.puzzles)
+                .sum::<usize>(),
+            2
+        );
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("sfen,"));
+        assert!(missing.unwrap_err().contains("missing.jsonl"));
+    }
+
+    #[test]
+    fn test_output_among_inputs() {
+        let path = temp("same.sfen");
+        assert!(merge_outputs(std::slice::from_ref(&path), &path).is_err());
+    }
+}
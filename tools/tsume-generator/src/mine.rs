@@ -0,0 +1,244 @@
+//! Recorded games mined for puzzles (`mine --games <file>`) instead of
+//! simulated ones.
+//!
+//! A collection has one game per line, as a USI `position` argument with moves
+//! in library convention, optionally after other tokens. The games file of
+//! an earlier run is a collection:
+//!
+//! ```text
+//! # club games
+//! black-wins startpos moves 2e2d 3b3c 1d1c 1b1c
+//! 5f0c6b1e2a9d4c37 white-wins sfen bkr/p1p/3/P1P/RKB w - 1 moves 3b3c 2e2d
+//! sfen 1k1/3/1R1/3/K1B b G 1 moves G*2b
+//! ```
+//!
+//! The winner is read from a `black-wins` or `white-wins` token before the
+//! position. Without one, a game that ends with the side to move mated was
+//! won by the side that moved last, and any other game is skipped.
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::games::Winner;
+use crate::generator::{Candidate, GenerationMode};
+use crate::movegen::legal_moves;
+use crate::simulator::Tsume;
+use crate::{ensure_black_to_move, position_only_sfen};
+
+/// A recorded game whose winner is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinedGame {
+    /// Line of the collection the game was read from, from 1
+    pub line: usize,
+    pub start_sfen: String,
+    /// Moves in library convention, each legal where it was played
+    pub moves: Vec<String>,
+    pub winner: Winner,
+}
+
+impl MinedGame {
+    /// Every position of the game with the winner to move, the last one
+    /// included, turned so Black is to move, as a candidate puzzle.
+    ///
+    /// The game's last ply stands in for the ply of the mate, which a
+    /// resigned game never played.
+    pub fn candidates(&self) -> Vec<Candidate> {
+        let Ok(mut position) = Position::from_sfen(&self.start_sfen) else {
+            return Vec::new();
+        };
+        let mut candidates = Vec::new();
+        for ply in 0..=self.moves.len() {
+            let sfen = position_only_sfen(&position.to_sfen());
+            if side_to_move(&sfen) == self.winner {
+                candidates.push(Candidate {
+                    tsume: Tsume {
+                        sfen: ensure_black_to_move(&sfen),
+                        game_sfen: sfen,
+                        ply,
+                        mate_ply: self.moves.len(),
+                    },
+                    seed_position: None,
+                    start_sfen: self.start_sfen.clone(),
+                    moves: self.moves.clone(),
+                    search_ms: Vec::new(),
+                    mode: GenerationMode::Mined,
+                });
+            }
+            let Some(mv) = self.moves.get(ply).and_then(|mv| Move::from_sfen(mv)) else {
+                break;
+            };
+            // Every move was replayed when the collection was read
+            if position.make_move(mv).is_err() {
+                break;
+            }
+        }
+        candidates
+    }
+}
+
+/// The games of a collection, handed out one at a time to any number of workers.
+#[derive(Debug, Default)]
+pub struct GameCollection {
+    games: Vec<MinedGame>,
+    /// Games without a known winner, left out
+    pub skipped: usize,
+    next: AtomicUsize,
+}
+
+impl GameCollection {
+    /// Read a collection; every game must replay in the library, and the
+    /// error names the first line that does not.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read games {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut collection = GameCollection::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_game(index + 1, line).map_err(|e| format!("line {}: {}", index + 1, e))? {
+                Some(game) => collection.games.push(game),
+                None => collection.skipped += 1,
+            }
+        }
+        if collection.games.is_empty() {
+            return Err("no games with a result".to_string());
+        }
+        Ok(collection)
+    }
+
+    /// Games with a known winner.
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+
+    /// The next game no worker has taken yet.
+    pub fn next_game(&self) -> Option<&MinedGame> {
+        self.games.get(self.next.fetch_add(1, Ordering::SeqCst))
+    }
+
+    /// Whether every game has been taken.
+    pub fn is_exhausted(&self) -> bool {
+        self.next.load(Ordering::SeqCst) >= self.games.len()
+    }
+}
+
+/// One game, or `None` if its winner is unknown.
+fn parse_game(line: usize, text: &str) -> Result<Option<MinedGame>, String> {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let start = tokens
+        .iter()
+        .position(|token| *token == "sfen" || *token == "startpos")
+        .ok_or("no `sfen` or `startpos`")?;
+    let mut winner = tokens[..start].iter().find_map(|token| match *token {
+        "black-wins" => Some(Winner::Black),
+        "white-wins" => Some(Winner::White),
+        _ => None,
+    });
+
+    let rest = &tokens[start + 1..];
+    let (start_sfen, rest) = match tokens[start] {
+        "startpos" => (Position::startpos().to_sfen(), rest),
+        _ if rest.len() >= 4 => (rest[..4].join(" "), &rest[4..]),
+        _ => return Err("incomplete SFEN".to_string()),
+    };
+    let mut position = Position::from_sfen(&start_sfen)
+        .map_err(|e| format!("invalid SFEN {:?}: {}", start_sfen, e))?;
+    let moves = match rest.split_first() {
+        None => &[][..],
+        Some((&"moves", moves)) => moves,
+        Some((token, _)) => return Err(format!("expected `moves`, found {:?}", token)),
+    };
+    for (ply, mv) in moves.iter().enumerate() {
+        let legal = Move::from_sfen(mv).is_some_and(|mv| position.make_move(mv).is_ok());
+        if !legal {
+            return Err(format!("ply {}: {} is not a legal move", ply + 1, mv));
+        }
+    }
+
+    // A game ending in mate was won by the side that moved last
+    if winner.is_none() && !moves.is_empty() && legal_moves(&position).is_empty() {
+        let mated = side_to_move(&position.to_sfen());
+        winner = Some(match mated {
+            Winner::Black => Winner::White,
+            Winner::White => Winner::Black,
+        });
+    }
+    Ok(winner.map(|winner| MinedGame {
+        line,
+        start_sfen: position_only_sfen(&start_sfen),
+        moves: moves.iter().map(|mv| mv.to_string()).collect(),
+        winner,
+    }))
+}
+
+fn side_to_move(sfen: &str) -> Winner {
+    match sfen.split_whitespace().nth(1) {
+        Some("w") => Winner::White,
+        _ => Winner::Black,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_collection() {
+        let text = "# club games\n\
+                    black-wins startpos moves 2e2d 3b3c\n\
+                    \n\
+                    5f0c6b1e2a9d4c37 white-wins sfen bkr/p1p/3/P1P/RKB w - 1 moves 3b3c\n\
+                    sfen 1k1/3/1R1/3/K1B b G 1 moves G*2b\n\
+                    startpos moves 2e2d\n";
+        let collection = GameCollection::parse(text).unwrap();
+        assert_eq!(collection.len(), 3);
+        assert_eq!(collection.skipped, 1);
+
+        let first = collection.next_game().unwrap();
+        assert_eq!((first.line, first.winner), (2, Winner::Black));
+        assert_eq!(first.moves, ["2e2d", "3b3c"]);
+        assert_eq!(collection.next_game().unwrap().winner, Winner::White);
+        // Read from the mate that ends it
+        let mated = collection.next_game().unwrap();
+        assert_eq!((mated.line, mated.winner), (5, Winner::Black));
+        assert!(collection.is_exhausted());
+        assert_eq!(collection.next_game(), None);
+    }
+
+    #[test]
+    fn test_bad_games() {
+        let err = GameCollection::parse("startpos\nstartpos moves 2e2a\n").unwrap_err();
+        assert!(err.starts_with("line 2: ply 1:"), "{}", err);
+        let err = GameCollection::parse("black-wins 2e2d 3b3c\n").unwrap_err();
+        assert!(err.starts_with("line 1:"), "{}", err);
+        assert!(GameCollection::parse("startpos moves 2e2d\n").is_err());
+    }
+
+    #[test]
+    fn test_candidates_have_the_winner_to_move() {
+        let collection =
+            GameCollection::parse("white-wins startpos moves 2e2d 3b3c 1d1c\n").unwrap();
+        let candidates = collection.next_game().unwrap().candidates();
+        assert_eq!(candidates.len(), 2);
+        for (candidate, ply) in candidates.iter().zip([1, 3]) {
+            assert_eq!(candidate.tsume.ply, ply);
+            assert_eq!(candidate.tsume.mate_ply, 3);
+            assert!(candidate.tsume.game_sfen.contains(" w "));
+            assert!(candidate.validate().is_ok());
+            assert_eq!(candidate.mode, GenerationMode::Mined);
+        }
+    }
+}
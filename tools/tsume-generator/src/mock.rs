@@ -0,0 +1,231 @@
+//! Scripted engine for testing engine-driven code without Fairy-Stockfish.
+
+use std::collections::VecDeque;
+
+use crate::engine::{EngineFailure, EngineInterface, PvInfo, Score, SearchResult, Strength};
+
+/// Answers each search with the next scripted result.
+///
+/// Once the script runs out the engine reports [`EngineFailure::Exited`], as
+/// a real engine that died would.
+#[derive(Debug, Clone, Default)]
+pub struct MockEngine {
+    pub script: VecDeque<(Vec<PvInfo>, SearchResult)>,
+    /// Every position set, as `start_sfen` followed by the moves
+    pub positions: Vec<String>,
+    /// Every search time set
+    pub search_ms: Vec<u64>,
+    /// Every strength limit set
+    pub strengths: Vec<Option<Strength>>,
+    last_score: Option<Score>,
+    exhausted: bool,
+}
+
+impl MockEngine {
+    pub fn new(script: impl IntoIterator<Item = (Vec<PvInfo>, SearchResult)>) -> Self {
+        MockEngine {
+            script: script.into_iter().collect(),
+            ..MockEngine::default()
+        }
+    }
+
+    /// A search whose only candidate is `mv` (Fairy-Stockfish convention) scored `score`.
+    pub fn answer(mv: &str, score: Score) -> (Vec<PvInfo>, SearchResult) {
+        let pv = PvInfo {
+            multipv: 1,
+            score,
+            moves: vec![mv.to_string()],
+        };
+        (vec![pv], SearchResult::Move(mv.to_string()))
+    }
+}
+
+impl EngineInterface for MockEngine {
+    fn set_position(&mut self, start_sfen: &str, move_history: &[String]) -> Option<()> {
+        let mut position = start_sfen.to_string();
+        for mv in move_history {
+            position.push(' ');
+            position.push_str(mv);
+        }
+        self.positions.push(position);
+        Some(())
+    }
+
+    fn search(&mut self) -> Option<(Vec<PvInfo>, SearchResult)> {
+        let Some((pv_infos, result)) = self.script.pop_front() else {
+            self.exhausted = true;
+            return None;
+        };
+        self.last_score = pv_infos
+            .iter()
+            .find(|pv| pv.multipv == 1)
+            .map(|pv| pv.score);
+        Some((pv_infos, result))
+    }
+
+    fn set_search_ms(&mut self, ms: u64) {
+        self.search_ms.push(ms);
+    }
+
+    fn set_strength(&mut self, strength: Option<Strength>) -> Option<()> {
+        self.strengths.push(strength);
+        Some(())
+    }
+
+    fn last_score(&self) -> Option<Score> {
+        self.last_score
+    }
+
+    fn failure(&self) -> Option<EngineFailure> {
+        self.exhausted.then_some(EngineFailure::Exited)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::audit_puzzle;
+    use crate::ensure_black_to_move;
+    use crate::output::PuzzleRecord;
+    use crate::simulator::{GameResult, GameSimulator, SearchBudget, SimulationError};
+
+    /// Position after Black's 2e2d, White to move
+    const GAME_SFEN: &str = "bkr/p1p/3/PKP/R1B w - 2";
+    /// [`GAME_SFEN`] turned round, colors swapped, so Black is to move
+    const MIRRORED_SFEN: &str = "b1r/pkp/3/P1P/RKB b - 1";
+
+    #[test]
+    fn test_scripted_game_ends_in_mate() {
+        let mut engine = MockEngine::new([
+            MockEngine::answer("2e2d", Score::Cp(0)),
+            MockEngine::answer("2a2b", Score::Cp(-50)),
+            (Vec::new(), SearchResult::Checkmate),
+        ]);
+        let result = GameSimulator::new(&mut engine).record_scores(true).run();
+        let GameResult::Checkmate(tsume) = result.result else {
+            panic!("the script ends in mate");
+        };
+        assert_eq!(tsume.mate_ply, 2);
+        assert_eq!(result.moves, ["2e2d", "2a2b"]);
+        assert_eq!(
+            result.scores,
+            [Some(Score::Cp(0)), Some(Score::Cp(-50)), None]
+        );
+        // White searched after Black's move, in Fairy-Stockfish convention
+        assert_eq!(engine.positions[1], "bkr/p1p/3/P1P/RKB b - 1 2e2d");
+    }
+
+    #[test]
+    fn test_search_budget_grows_in_the_endgame() {
+        let script = || {
+            [
+                MockEngine::answer("2e2d", Score::Cp(0)),
+                MockEngine::answer("2a2b", Score::Cp(-50)),
+                (Vec::new(), SearchResult::Checkmate),
+            ]
+        };
+        let budget = SearchBudget {
+            search_ms: 10,
+            endgame_ms: Some(80),
+            endgame_ply: 1,
+            ..SearchBudget::default()
+        };
+        let mut engine = MockEngine::new(script());
+        let result = GameSimulator::new(&mut engine).search_budget(budget).run();
+        assert_eq!(result.search_ms, [10, 80, 80]);
+        assert_eq!(engine.search_ms, result.search_ms);
+
+        // Material decides too: White has lost no more than Black
+        let budget = SearchBudget {
+            endgame_ply: 100,
+            endgame_material: 1100,
+            ..budget
+        };
+        let mut engine = MockEngine::new(script());
+        let result = GameSimulator::new(&mut engine).search_budget(budget).run();
+        assert_eq!(result.search_ms, [10, 10, 10]);
+        let budget = SearchBudget {
+            endgame_material: 1101,
+            ..budget
+        };
+        let mut engine = MockEngine::new(script());
+        let result = GameSimulator::new(&mut engine).search_budget(budget).run();
+        assert_eq!(result.search_ms, [80, 80, 80]);
+
+        // Without a budget the engine's time is left alone
+        let mut engine = MockEngine::new(script());
+        let result = GameSimulator::new(&mut engine).run();
+        assert!(result.search_ms.is_empty());
+        assert!(engine.search_ms.is_empty());
+    }
+
+    #[test]
+    fn test_black_mate_keeps_the_position() {
+        let mut engine = MockEngine::new([
+            MockEngine::answer("2e2d", Score::Mate(1)),
+            (Vec::new(), SearchResult::Checkmate),
+        ]);
+        let result = GameSimulator::new(&mut engine).run();
+        let GameResult::Checkmate(tsume) = result.result else {
+            panic!("the script ends in mate");
+        };
+        // Black gave the mate, so the puzzle is the game position unchanged
+        assert_eq!(tsume.game_sfen, "bkr/p1p/3/P1P/RKB b - 1");
+        assert_eq!(tsume.sfen, tsume.game_sfen);
+        assert_eq!(
+            audit_puzzle(&PuzzleRecord::new(tsume.sfen, tsume.ply)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_white_mate_mirrors_the_position() {
+        let mut engine = MockEngine::new([
+            MockEngine::answer("2e2d", Score::Cp(0)),
+            MockEngine::answer("2a2b", Score::Mate(1)),
+            (Vec::new(), SearchResult::Checkmate),
+        ]);
+        let result = GameSimulator::new(&mut engine).run();
+        let GameResult::Checkmate(tsume) = result.result else {
+            panic!("the script ends in mate");
+        };
+        // White gave the mate from the position after Black's move
+        assert_eq!(tsume.game_sfen, GAME_SFEN);
+        assert_eq!(tsume.sfen, MIRRORED_SFEN);
+        assert_eq!(tsume.sfen, ensure_black_to_move(&tsume.game_sfen));
+        assert_eq!(
+            audit_puzzle(&PuzzleRecord::new(tsume.sfen, tsume.ply)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_mate_before_the_first_move_is_an_error() {
+        let mut engine = MockEngine::new([(Vec::new(), SearchResult::Checkmate)]);
+        let result = GameSimulator::new(&mut engine).run();
+        let GameResult::Error(SimulationError::MatedAtStart { sfen }) = result.result else {
+            panic!("a game mated at once has no puzzle");
+        };
+        assert_eq!(sfen, "bkr/p1p/3/P1P/RKB b - 1");
+        assert_eq!(result.plies, 0);
+
+        // The engine resigning at once ends the same way
+        let mut engine = MockEngine::new([(Vec::new(), SearchResult::Resign)]);
+        let result = GameSimulator::new(&mut engine).run();
+        assert!(matches!(
+            result.result,
+            GameResult::Error(SimulationError::MatedAtStart { .. })
+        ));
+    }
+
+    #[test]
+    fn test_exhausted_script_is_an_engine_failure() {
+        let mut engine = MockEngine::new([MockEngine::answer("2e2d", Score::Cp(0))]);
+        let result = GameSimulator::new(&mut engine).run();
+        assert!(matches!(
+            result.result,
+            GameResult::Error(SimulationError::Engine)
+        ));
+        assert_eq!(result.plies, 1);
+    }
+}
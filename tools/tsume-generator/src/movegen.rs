@@ -0,0 +1,180 @@
+//! Legal move generation by trial.
+//!
+//! The board is small enough (3x5) that trying every conceivable move and
+//! keeping those the library accepts is cheap, and it reuses the library's
+//! rules instead of duplicating them.
+
+use std::cell::RefCell;
+
+use shogi::Color;
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::variant::Variant;
+
+/// Variant of the library's `Position` type
+const VARIANT: Variant = Variant::Wildcatshogi;
+
+/// All legal moves for the side to move, in library coordinates.
+pub fn legal_moves(position: &Position) -> Vec<Move> {
+    candidate_moves(position)
+        .into_iter()
+        .filter_map(|text| Move::from_sfen(&text))
+        .filter(|mv| position.clone().make_move(*mv).is_ok())
+        .collect()
+}
+
+thread_local! {
+    /// Mobility of both sides in the last position asked about
+    static MOBILITY_CACHE: RefCell<(String, [Option<u32>; 2])> =
+        const { RefCell::new((String::new(), [None, None])) };
+}
+
+/// Legal moves, board moves and drops, `color` would have if it were its turn.
+///
+/// The last position's result is cached per thread, so evaluating both sides
+/// of one position, or one side repeatedly, generates its moves only once.
+pub fn mobility(position: &Position, color: Color) -> u32 {
+    let sfen = position.to_sfen();
+    let index = match color {
+        Color::Black => 0,
+        Color::White => 1,
+    };
+    let cached = MOBILITY_CACHE
+        .with_borrow(|(key, counts)| (*key == sfen).then_some(counts[index]).flatten());
+    if let Some(count) = cached {
+        return count;
+    }
+
+    let mut fields: Vec<&str> = sfen.split_whitespace().collect();
+    if fields.len() > 1 {
+        fields[1] = if color == Color::Black { "b" } else { "w" };
+    }
+    let count =
+        Position::from_sfen(&fields.join(" ")).map_or(0, |turn| legal_moves(&turn).len() as u32);
+    MOBILITY_CACHE.with_borrow_mut(|(key, counts)| {
+        if *key != sfen {
+            *key = sfen;
+            *counts = [None, None];
+        }
+        counts[index] = Some(count);
+    });
+    count
+}
+
+/// Whether the king of the side to move is attacked.
+///
+/// Tried by handing that side a gold: every piece steps a single square, so
+/// no drop can block an attack, and a king out of check may drop the gold on
+/// any empty square.
+pub fn in_check(position: &Position) -> bool {
+    let sfen = position.to_sfen();
+    let mut fields: Vec<&str> = sfen.split_whitespace().collect();
+    let gold = if fields.get(1) == Some(&"w") {
+        "g"
+    } else {
+        "G"
+    };
+    let hand = match fields.get(2) {
+        Some(&"-") | None => gold.to_string(),
+        Some(hand) => format!("{}{}", hand, gold),
+    };
+    if fields.len() > 2 {
+        fields[2] = &hand;
+    }
+    let Ok(holding) = Position::from_sfen(&fields.join(" ")) else {
+        return false;
+    };
+    !legal_moves(&holding)
+        .iter()
+        .any(|mv| mv.to_string().starts_with("G*"))
+}
+
+/// Move strings covering every normal move and every drop of a piece in hand.
+fn candidate_moves(position: &Position) -> Vec<String> {
+    let ranks: Vec<char> = (b'a'..)
+        .take(VARIANT.ranks() as usize)
+        .map(char::from)
+        .collect();
+    let squares: Vec<String> = (1..=VARIANT.files())
+        .flat_map(|file| ranks.iter().map(move |rank| format!("{}{}", file, rank)))
+        .collect();
+
+    let mut moves = Vec::new();
+    for from in &squares {
+        for to in squares.iter().filter(|to| *to != from) {
+            moves.push(format!("{}{}", from, to));
+            moves.push(format!("{}{}+", from, to));
+        }
+    }
+    for piece in hand_pieces(&position.to_sfen()) {
+        moves.extend(squares.iter().map(|to| format!("{}*{}", piece, to)));
+    }
+    moves
+}
+
+/// Distinct piece letters (uppercase) in the hand of the side to move.
+fn hand_pieces(sfen: &str) -> Vec<char> {
+    let mut fields = sfen.split_whitespace().skip(1);
+    let black = fields.next() != Some("w");
+    let hand = fields.next().unwrap_or("-");
+
+    let mut pieces: Vec<char> = hand
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic() && c.is_ascii_uppercase() == black)
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    pieces.sort_unstable();
+    pieces.dedup();
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starting_position_moves() {
+        let mut moves: Vec<String> = legal_moves(&Position::startpos())
+            .iter()
+            .map(|mv| mv.to_string())
+            .collect();
+        moves.sort();
+        // Both pawns, the king and the bishop can step forward; the rook is boxed in
+        assert_eq!(moves, vec!["1d1c", "2e2d", "3d3c", "3e2d"]);
+    }
+
+    #[test]
+    fn test_mobility() {
+        let start = Position::startpos();
+        assert_eq!(mobility(&start, Color::Black), 4);
+        assert_eq!(mobility(&start, Color::White), 4);
+        // Served from the cache the second time
+        assert_eq!(mobility(&start, Color::Black), 4);
+
+        // White's bare king in the corner against king and gold
+        let cornered = Position::from_sfen("k2/1G1/3/3/2K b - 1").unwrap();
+        assert!(mobility(&cornered, Color::White) < mobility(&cornered, Color::Black));
+        // Drops count
+        let holding = Position::from_sfen("k2/1G1/3/3/2K b P 1").unwrap();
+        assert!(mobility(&holding, Color::Black) > mobility(&cornered, Color::Black));
+    }
+
+    #[test]
+    fn test_in_check() {
+        // Mated by the gold drop, and stalemated by the rook
+        assert!(in_check(
+            &Position::from_sfen("2k/RG1/3/3/K1B w - 4").unwrap()
+        ));
+        assert!(!in_check(
+            &Position::from_sfen("k1K/3/R2/3/3 w - 2").unwrap()
+        ));
+        assert!(!in_check(&Position::startpos()));
+    }
+
+    #[test]
+    fn test_hand_pieces_of_side_to_move() {
+        assert_eq!(hand_pieces("k2/3/3/3/2K b 2PRb 1"), vec!['P', 'R']);
+        assert_eq!(hand_pieces("k2/3/3/3/2K w 2PRb 1"), vec!['B']);
+        assert!(hand_pieces("k2/3/3/3/2K b - 1").is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+//! Opening diversity guard (`--max-opening-reuse`): how often each opening
+//! line has been played, so long runs do not keep replaying a few of them.
+//!
+//! The counts are kept next to the output as `<output>.openings`, one line per
+//! opening with its count and the line as a USI `position` argument:
+//!
+//! ```text
+//! 3 sfen bkr/p1p/3/P1P/RKB b - 1 moves 2e2d 3b3c 1d1c 1b1c
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use shogi::wildcatshogi::Move;
+
+/// Plies of a game that make up its opening
+pub const OPENING_PLIES: usize = 6;
+
+/// Games played so far per opening line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpeningBook {
+    max_reuse: usize,
+    counts: HashMap<String, usize>,
+}
+
+impl OpeningBook {
+    /// An empty book letting each opening be played again `max_reuse` times.
+    pub fn new(max_reuse: usize) -> Self {
+        OpeningBook {
+            max_reuse,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Counts file path for an output file: `<output>.openings`.
+    pub fn path_for(output: &Path) -> PathBuf {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".openings");
+        PathBuf::from(path)
+    }
+
+    /// The book kept for `output`, or an empty one if there is none.
+    pub fn load(output: &Path, max_reuse: usize) -> io::Result<Self> {
+        let text = match fs::read_to_string(Self::path_for(output)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new(max_reuse)),
+            Err(e) => return Err(e),
+        };
+        let mut book = Self::new(max_reuse);
+        for (number, line) in text.lines().enumerate() {
+            let parsed = line
+                .split_once(' ')
+                .and_then(|(count, opening)| Some((count.parse().ok()?, opening)));
+            let Some((count, opening)) = parsed else {
+                let message = format!("line {}: expected a count and an opening", number + 1);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            };
+            book.counts.insert(opening.to_string(), count);
+        }
+        Ok(book)
+    }
+
+    /// Write the book next to `output`, most played openings first.
+    pub fn save(&self, output: &Path) -> io::Result<()> {
+        let mut openings: Vec<_> = self.counts.iter().collect();
+        openings.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let text: String = openings
+            .into_iter()
+            .map(|(opening, count)| format!("{} {}\n", count, opening))
+            .collect();
+        fs::write(Self::path_for(output), text)
+    }
+
+    /// Count the opening `moves` played from `start_sfen`, unless it has
+    /// already been played again `max_reuse` times; returns whether the game
+    /// may go on.
+    pub fn admit(&mut self, start_sfen: &str, moves: &[Move]) -> bool {
+        let count = self
+            .counts
+            .entry(opening_key(start_sfen, moves))
+            .or_default();
+        if *count > self.max_reuse {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+
+    /// Distinct openings played.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+fn opening_key(start_sfen: &str, moves: &[Move]) -> String {
+    let moves: Vec<String> = moves.iter().map(Move::to_string).collect();
+    format!("sfen {} moves {}", start_sfen, moves.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "bkr/p1p/3/P1P/RKB b - 1";
+
+    fn moves(texts: &[&str]) -> Vec<Move> {
+        texts.iter().map(|t| Move::from_sfen(t).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_reuse_limit() {
+        let mut book = OpeningBook::new(1);
+        let line = moves(&["2e2d", "3b3c"]);
+        assert!(book.admit(START, &line));
+        assert!(book.admit(START, &line));
+        assert!(!book.admit(START, &line));
+
+        // The same moves from another start are another opening
+        assert!(book.admit("bkr/p1p/3/P1P/RKB b P 1", &line));
+        assert!(book.admit(START, &moves(&["2e2d", "1b1c"])));
+        assert_eq!(book.len(), 3);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let output = std::env::temp_dir().join(format!("tsume-openings-{}", std::process::id()));
+        assert!(OpeningBook::load(&output, 0).unwrap().is_empty());
+
+        let mut book = OpeningBook::new(0);
+        let line = moves(&["2e2d", "3b3c"]);
+        assert!(book.admit(START, &line));
+        book.save(&output).unwrap();
+        let text = fs::read_to_string(OpeningBook::path_for(&output)).unwrap();
+        assert_eq!(text, format!("1 sfen {} moves 2e2d 3b3c\n", START));
+
+        let mut loaded = OpeningBook::load(&output, 0).unwrap();
+        fs::remove_file(OpeningBook::path_for(&output)).unwrap();
+        assert_eq!(loaded, book);
+        assert!(!loaded.admit(START, &line));
+    }
+}
@@ -0,0 +1,779 @@
+//! Puzzle records and output formats.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::diagram;
+use crate::generator::GenerationMode;
+use crate::rotate_move;
+use crate::sfen::{canonicalize, normalize_tsume_sfen, reflect_files};
+use crate::themes::Theme;
+
+/// Output file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One SFEN per line
+    #[value(alias = "sfen")]
+    Plain,
+    /// One JSON object per line
+    #[value(alias = "json-lines")]
+    Jsonl,
+    /// Spreadsheet/Anki-friendly CSV with a header row
+    Csv,
+    /// CSV in the columns of the Lichess puzzle database, see [`LICHESS_HEADER`]
+    LichessCsv,
+}
+
+/// Columns of the Lichess puzzle database export
+pub const LICHESS_HEADER: [&str; 10] = [
+    "PuzzleId",
+    "FEN",
+    "Moves",
+    "Rating",
+    "RatingDeviation",
+    "Popularity",
+    "NbPlays",
+    "Themes",
+    "GameUrl",
+    "OpeningTags",
+];
+/// Pseudo-rating of difficulty 0
+const LICHESS_BASE_RATING: f64 = 600.0;
+/// Pseudo-rating added per point of difficulty
+const LICHESS_RATING_PER_LEVEL: f64 = 150.0;
+/// Rating of puzzles that were not rated
+const LICHESS_UNRATED: u32 = 1500;
+/// Rating deviation of every puzzle: nobody has played them yet, so their
+/// ratings are as uncertain as Lichess allows
+const LICHESS_RATING_DEVIATION: u32 = 500;
+
+/// A generated puzzle with its metadata.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PuzzleRecord {
+    /// Puzzle position, Black to move
+    pub sfen: String,
+    /// Stable fingerprint of the position
+    pub id: String,
+    /// SHA-256 of `sfen` as 64 hex digits, to detect records edited or damaged since
+    #[serde(default)]
+    pub sha256: String,
+    /// Number of plies played in the source game before the puzzle position
+    pub ply_of_game: usize,
+    /// Seconds since the Unix epoch
+    pub generated_at: u64,
+    /// Ply of the source game on which the mate was played
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mate_ply: Option<usize>,
+    /// Seed position the source game started from, if not the initial position
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed_position: Option<String>,
+    /// Verified mate distance in plies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mate_in: Option<i32>,
+    /// Engine's mating line in USI notation (Fairy-Stockfish coordinates)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solution: Option<Vec<String>>,
+    /// Estimated difficulty; higher is harder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<f64>,
+    /// Elo-like rating from strength-limited solve probes (`--rate-puzzles`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u32>,
+    /// Themes of the verified solution
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub themes: Vec<Theme>,
+    /// How the source game was played; records from before modes read as `weak`
+    #[serde(default)]
+    pub mode: GenerationMode,
+    /// Side that mates, `b` or `w`, when the position is written as played
+    /// (`--no-mirror`) instead of turned so Black mates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attacker: Option<String>,
+    /// Rendered board, hands and side to move (`--with-diagram`); filled in
+    /// when the record is written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagram: Option<String>,
+}
+
+impl PuzzleRecord {
+    pub fn new(sfen: String, ply_of_game: usize) -> Self {
+        let generated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        PuzzleRecord {
+            id: fingerprint(&sfen),
+            sha256: sfen_sha256(&sfen),
+            sfen,
+            ply_of_game,
+            generated_at,
+            mate_ply: None,
+            seed_position: None,
+            mate_in: None,
+            solution: None,
+            difficulty: None,
+            rating: None,
+            themes: Vec::new(),
+            mode: GenerationMode::Weak,
+            attacker: None,
+            diagram: None,
+        }
+    }
+
+    /// Write the puzzle as it occurred in `game_sfen`, its position before
+    /// being turned so Black mates (`--no-mirror`).
+    ///
+    /// After a White win the solution is turned back with the board. The
+    /// fingerprint does not change, so the puzzle stays a duplicate of itself
+    /// in either orientation.
+    pub fn unmirror(&mut self, game_sfen: &str) {
+        let attacker = game_sfen.split_whitespace().nth(1).unwrap_or("b");
+        if attacker == "w"
+            && let Some(solution) = &mut self.solution
+        {
+            *solution = solution.iter().map(|mv| rotate_move(mv)).collect();
+        }
+        self.sfen = game_sfen.to_string();
+        self.sha256 = sfen_sha256(&self.sfen);
+        self.attacker = Some(attacker.to_string());
+    }
+}
+
+/// SHA-256 of an SFEN string as 64 lowercase hex digits.
+pub fn sfen_sha256(sfen: &str) -> String {
+    Sha256::digest(sfen.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Result of checking the hashes of a JSON Lines puzzle file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Puzzle lines checked
+    pub total: usize,
+    /// Line numbers, from 1, whose record does not parse or whose SFEN does not match its hash
+    pub corrupted: Vec<usize>,
+}
+
+/// Recompute the hash of every record of a JSON Lines puzzle file.
+///
+/// Records written before hashes were added have none and count as corrupted.
+/// Blank lines are skipped.
+pub fn verify_puzzle_file_integrity(path: &Path) -> Result<IntegrityReport, io::Error> {
+    let mut report = IntegrityReport {
+        total: 0,
+        corrupted: Vec::new(),
+    };
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        report.total += 1;
+        let intact = serde_json::from_str::<PuzzleRecord>(&line)
+            .is_ok_and(|record| record.sha256 == sfen_sha256(&record.sfen));
+        if !intact {
+            report.corrupted.push(index + 1);
+        }
+    }
+    Ok(report)
+}
+
+/// Stable 64-bit FNV-1a fingerprint of a position, ignoring the move counter and hand order.
+///
+/// A position with White to move has the fingerprint of its mirror with
+/// Black to move, so a puzzle is the same whichever side it is written for.
+/// This is the published `id`; see [`duplicate_key`] for finding duplicates.
+pub fn fingerprint(sfen: &str) -> String {
+    format!("{:016x}", position_hash(&normalize_tsume_sfen(sfen)))
+}
+
+/// Key of the duplicate check, `--append` and `merge`: a [`fingerprint`]
+/// that is also the same for a position reflected left to right.
+///
+/// Every piece moves alike on either wing, so a reflection is the same
+/// puzzle. The fingerprint of the smaller of the two SFENs is taken, so the
+/// key of a puzzle is its `id` or the `id` of its reflection.
+pub fn duplicate_key(sfen: &str) -> String {
+    let normalized = normalize_tsume_sfen(sfen);
+    let reflected = reflect_files(&normalized);
+    fingerprint(&normalized.min(reflected))
+}
+
+/// Stable 64-bit FNV-1a hash of a position's canonical form.
+///
+/// The move counter and the order of the hand are ignored.
+pub fn position_hash(sfen: &str) -> u64 {
+    // Without the counter, so fingerprints match those of earlier versions
+    let canonical = canonicalize(sfen);
+    let position = canonical
+        .rsplit_once(' ')
+        .map_or(canonical.as_str(), |(p, _)| p);
+    fnv1a(position.as_bytes())
+}
+
+/// 64-bit FNV-1a hash of `bytes`, stable across runs and platforms.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// `--output` value that writes puzzles to stdout
+pub const STDOUT: &str = "-";
+
+/// Where a run writes its puzzles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// Standard output, for piping into other tools
+    Stdout,
+    /// A file: the output file, or one shard of it
+    File(PathBuf),
+}
+
+impl OutputTarget {
+    /// The target of `--output`: stdout for [`STDOUT`], else the file or,
+    /// with `shards` above 1, shard `index` of it (see [`shard_path`]).
+    pub fn new(output: &Path, shards: usize, index: usize) -> Self {
+        if output == Path::new(STDOUT) {
+            OutputTarget::Stdout
+        } else if shards > 1 {
+            OutputTarget::File(shard_path(output, index))
+        } else {
+            OutputTarget::File(output.to_path_buf())
+        }
+    }
+
+    /// The file written, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            OutputTarget::Stdout => None,
+            OutputTarget::File(path) => Some(path),
+        }
+    }
+
+    /// Open a writer; with `append`, a file is added to instead of overwritten.
+    pub fn open(
+        &self,
+        format: OutputFormat,
+        append: bool,
+        diagram: bool,
+    ) -> io::Result<Box<dyn PuzzleSink>> {
+        Ok(match self {
+            OutputTarget::Stdout => {
+                Box::new(OutputWriter::new(format, io::stdout()).diagram(diagram))
+            }
+            OutputTarget::File(path) if append => {
+                Box::new(OutputWriter::append(format, path)?.diagram(diagram))
+            }
+            OutputTarget::File(path) => {
+                Box::new(OutputWriter::create(format, path)?.diagram(diagram))
+            }
+        })
+    }
+}
+
+impl fmt::Display for OutputTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputTarget::Stdout => write!(f, "stdout"),
+            OutputTarget::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// File of shard `index` of `output`: the index as four digits before the
+/// extension, e.g. `results.0003.sfen`.
+pub fn shard_path(output: &Path, index: usize) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(extension) => format!("{}.{:04}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}.{:04}", stem, index),
+    };
+    output.with_file_name(name)
+}
+
+/// A destination of puzzle records, whatever the backend.
+pub trait PuzzleSink: Send {
+    fn write(&mut self, record: &PuzzleRecord) -> io::Result<()>;
+
+    /// Push buffered records through to the backend.
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+impl<W: Write + Send> PuzzleSink for OutputWriter<W> {
+    fn write(&mut self, record: &PuzzleRecord) -> io::Result<()> {
+        OutputWriter::write(self, record)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Lines(_, out) => out.flush(),
+            Sink::Csv(_, out) => out.flush(),
+        }
+    }
+}
+
+/// Destination of the records, by format family.
+enum Sink<W: Write> {
+    Lines(OutputFormat, W),
+    Csv(OutputFormat, Box<csv::Writer<W>>),
+}
+
+/// Writes puzzle records in the selected format.
+pub struct OutputWriter<W: Write = File> {
+    sink: Sink<W>,
+    /// Include the rendered board with every record
+    diagram: bool,
+    header_written: bool,
+}
+
+impl OutputWriter<File> {
+    pub fn create(format: OutputFormat, path: &Path) -> io::Result<Self> {
+        Ok(Self::new(format, File::create(path)?))
+    }
+
+    /// Add records to the end of `path`, creating it if missing.
+    ///
+    /// A CSV header is only written if the file was empty.
+    pub fn append(format: OutputFormat, path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut writer = Self::new(format, file);
+        writer.header_written = path.metadata()?.len() > 0;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> OutputWriter<W> {
+    pub fn new(format: OutputFormat, out: W) -> Self {
+        let sink = match format {
+            OutputFormat::Plain | OutputFormat::Jsonl => Sink::Lines(format, out),
+            OutputFormat::Csv | OutputFormat::LichessCsv => {
+                Sink::Csv(format, Box::new(csv::Writer::from_writer(out)))
+            }
+        };
+        OutputWriter {
+            sink,
+            diagram: false,
+            header_written: false,
+        }
+    }
+
+    /// Add the rendered board to every record: a `diagram` field in JSON
+    /// Lines, a `diagram` column in CSV, or `# ` comment lines after the
+    /// position in plain output, which readers of the file skip. Lichess CSV
+    /// has fixed columns and is left as it is.
+    pub fn diagram(mut self, enabled: bool) -> Self {
+        self.diagram = enabled;
+        self
+    }
+
+    pub fn write(&mut self, record: &PuzzleRecord) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Lines(OutputFormat::Jsonl, out) if self.diagram => {
+                let record = PuzzleRecord {
+                    diagram: Some(diagram::render_puzzle(&record.sfen, record.mate_in)),
+                    ..record.clone()
+                };
+                serde_json::to_writer(&mut *out, &record)?;
+                writeln!(out)
+            }
+            Sink::Lines(OutputFormat::Jsonl, out) => {
+                serde_json::to_writer(&mut *out, record)?;
+                writeln!(out)
+            }
+            Sink::Lines(_, out) => {
+                match &record.attacker {
+                    Some(attacker) => writeln!(out, "{}\t{}", record.sfen, attacker)?,
+                    None => writeln!(out, "{}", record.sfen)?,
+                }
+                if self.diagram {
+                    for line in diagram::render_puzzle(&record.sfen, record.mate_in).lines() {
+                        writeln!(out, "# {}", line)?;
+                    }
+                }
+                Ok(())
+            }
+            Sink::Csv(OutputFormat::LichessCsv, out) => {
+                if !self.header_written {
+                    out.write_record(LICHESS_HEADER)?;
+                    self.header_written = true;
+                }
+                out.write_record(lichess_row(record))?;
+                out.flush()
+            }
+            Sink::Csv(_, out) => {
+                if !self.header_written {
+                    let mut header = vec!["sfen", "mate_in", "solution", "difficulty"];
+                    if self.diagram {
+                        header.push("diagram");
+                    }
+                    // Every record of a run has an attacker or none does
+                    if record.attacker.is_some() {
+                        header.push("attacker");
+                    }
+                    out.write_record(&header)?;
+                    self.header_written = true;
+                }
+
+                let mut row = vec![
+                    record.sfen.clone(),
+                    record.mate_in.map(|m| m.to_string()).unwrap_or_default(),
+                    record
+                        .solution
+                        .as_ref()
+                        .map(|moves| moves.join(" "))
+                        .unwrap_or_default(),
+                    record
+                        .difficulty
+                        .map(|d| format!("{:.1}", d))
+                        .unwrap_or_default(),
+                ];
+                if self.diagram {
+                    row.push(diagram::render(&record.sfen));
+                }
+                if let Some(attacker) = &record.attacker {
+                    row.push(attacker.clone());
+                }
+                out.write_record(&row)?;
+                // Flush every row so an interrupted run leaves complete lines
+                out.flush()
+            }
+        }
+    }
+}
+
+/// A record as a row of [`LICHESS_HEADER`].
+///
+/// The moves are the solution as the engine gave it: USI notation in
+/// Fairy-Stockfish coordinates, file 1 on the right, drops as `G*2b`. Lichess
+/// puzzles start with the opponent's last move; these start with the
+/// solver's first. The difficulty becomes a pseudo-rating on a linear scale.
+fn lichess_row(record: &PuzzleRecord) -> [String; 10] {
+    let rating = record.difficulty.map_or(LICHESS_UNRATED, |difficulty| {
+        (LICHESS_BASE_RATING + difficulty * LICHESS_RATING_PER_LEVEL).round() as u32
+    });
+    let themes: Vec<String> = record.themes.iter().map(Theme::to_string).collect();
+    [
+        record.id.clone(),
+        record.sfen.clone(),
+        record.solution.as_deref().unwrap_or_default().join(" "),
+        rating.to_string(),
+        LICHESS_RATING_DEVIATION.to_string(),
+        // Nobody has voted on or played them yet
+        "0".to_string(),
+        "0".to_string(),
+        themes.join(" "),
+        String::new(),
+        String::new(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_record() -> PuzzleRecord {
+        let mut record = PuzzleRecord::new("k2/PBR/3/p1p/rbK b - 1".to_string(), 17);
+        record.mate_in = Some(1);
+        record.solution = Some(vec!["2b2a".to_string()]);
+        record.difficulty = Some(2.5);
+        record
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_move_counter() {
+        assert_eq!(
+            fingerprint("k2/PBR/3/p1p/rbK b - 1"),
+            fingerprint("k2/PBR/3/p1p/rbK b - 37")
+        );
+        assert_eq!(
+            fingerprint("k2/3/3/3/2K b BP 1"),
+            fingerprint("k2/3/3/3/2K b PB 1")
+        );
+        assert_ne!(
+            fingerprint("k2/PBR/3/p1p/rbK b - 1"),
+            fingerprint("rkb/1p1/3/P1P/BKR b - 1")
+        );
+    }
+
+    #[test]
+    fn test_duplicate_key_ignores_reflection() {
+        let key = duplicate_key("1k1/3/R2/3/K1B b G 1");
+        assert_eq!(duplicate_key("1k1/3/2R/3/B1K b G 1"), key);
+        // Reflected and written for White
+        assert_eq!(duplicate_key("k1b/3/r2/3/1K1 w g 4"), key);
+        assert_ne!(duplicate_key("1k1/3/R2/3/B1K b G 1"), key);
+
+        // The published id stays that of the position as given
+        assert_ne!(
+            fingerprint("1k1/3/R2/3/K1B b G 1"),
+            fingerprint("1k1/3/2R/3/B1K b G 1")
+        );
+        let ids = [
+            fingerprint("1k1/3/R2/3/K1B b G 1"),
+            fingerprint("1k1/3/2R/3/B1K b G 1"),
+        ];
+        assert!(ids.contains(&key));
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        // Reference values of the 64-bit FNV-1a specification
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_write_plain() {
+        let mut buffer = Vec::new();
+        OutputWriter::new(OutputFormat::Plain, &mut buffer)
+            .write(&sample_record())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "k2/PBR/3/p1p/rbK b - 1\n"
+        );
+    }
+
+    #[test]
+    fn test_write_diagram() {
+        let record = sample_record();
+        let mut buffer = Vec::new();
+        OutputWriter::new(OutputFormat::Plain, &mut buffer)
+            .diagram(true)
+            .write(&record)
+            .unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], record.sfen);
+        assert!(lines[1..].iter().all(|line| line.starts_with("# ")));
+        assert_eq!(lines.last(), Some(&"# Black to move, mate in 1"));
+
+        let mut buffer = Vec::new();
+        OutputWriter::new(OutputFormat::Jsonl, &mut buffer)
+            .diagram(true)
+            .write(&record)
+            .unwrap();
+        let parsed: PuzzleRecord = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(
+            parsed.diagram,
+            Some(diagram::render_puzzle(&record.sfen, Some(1)))
+        );
+        assert_eq!(
+            PuzzleRecord {
+                diagram: None,
+                ..parsed
+            },
+            record
+        );
+    }
+
+    #[test]
+    fn test_unmirror_writes_the_game_orientation() {
+        let mut record = sample_record();
+        let id = record.id.clone();
+        // The puzzle as White had it in the game
+        record.unmirror("kBR/P1P/3/rbp/2K w - 12");
+        assert_eq!(record.sfen, "kBR/P1P/3/rbp/2K w - 12");
+        assert_eq!(record.solution, Some(vec!["2d2e".to_string()]));
+        assert_eq!(record.attacker.as_deref(), Some("w"));
+        assert_eq!(record.sha256, sfen_sha256(&record.sfen));
+        assert_eq!(record.id, id);
+        assert_eq!(fingerprint(&record.sfen), id);
+
+        let mut buffer = Vec::new();
+        OutputWriter::new(OutputFormat::Plain, &mut buffer)
+            .write(&record)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "kBR/P1P/3/rbp/2K w - 12\tw\n"
+        );
+
+        let mut record = sample_record();
+        record.unmirror("k2/PBR/3/p1p/rbK b - 9");
+        assert_eq!(record.solution, sample_record().solution);
+        assert_eq!(record.attacker.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_write_jsonl_round_trip() {
+        let mut record = sample_record();
+        record.rating = Some(1400);
+        let mut buffer = Vec::new();
+        OutputWriter::new(OutputFormat::Jsonl, &mut buffer)
+            .write(&record)
+            .unwrap();
+
+        let text = String::from_utf8(buffer).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        let parsed: PuzzleRecord = serde_json::from_str(text.trim_end()).unwrap();
+        assert_eq!(parsed, record);
+    }
+
+    #[test]
+    fn test_write_csv_round_trip() {
+        let first = sample_record();
+        let second = PuzzleRecord::new("rkb/1p1/3/P1P/BKR b - 1".to_string(), 9);
+
+        let mut buffer = Vec::new();
+        {
+            let mut writer = OutputWriter::new(OutputFormat::Csv, &mut buffer).diagram(true);
+            writer.write(&first).unwrap();
+            writer.write(&second).unwrap();
+        }
+
+        let mut reader = csv::Reader::from_reader(buffer.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["sfen", "mate_in", "solution", "difficulty", "diagram"]
+        );
+        let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(&rows[0][0], first.sfen);
+        assert_eq!(&rows[0][1], "1");
+        assert_eq!(&rows[0][2], "2b2a");
+        assert_eq!(&rows[0][3], "2.5");
+        assert_eq!(&rows[0][4], diagram::render(&first.sfen));
+        assert_eq!(&rows[1][0], second.sfen);
+        assert_eq!(&rows[1][1], "");
+        assert_eq!(&rows[1][3], "");
+    }
+
+    #[test]
+    fn test_write_lichess_csv() {
+        let mut first = sample_record();
+        first.themes = vec![Theme::DropMate, Theme::EdgeMate];
+        let second = PuzzleRecord::new("rkb/1p1/3/P1P/BKR b - 1".to_string(), 9);
+
+        let mut buffer = Vec::new();
+        {
+            let mut writer = OutputWriter::new(OutputFormat::LichessCsv, &mut buffer);
+            writer.write(&first).unwrap();
+            writer.write(&second).unwrap();
+        }
+
+        let mut reader = csv::Reader::from_reader(buffer.as_slice());
+        assert_eq!(reader.headers().unwrap(), LICHESS_HEADER.to_vec());
+        let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            rows[0].iter().collect::<Vec<_>>(),
+            [
+                first.id.as_str(),
+                "k2/PBR/3/p1p/rbK b - 1",
+                "2b2a",
+                "975",
+                "500",
+                "0",
+                "0",
+                "drop_mate edge_mate",
+                "",
+                ""
+            ]
+        );
+        // Unverified and unrated
+        assert_eq!(&rows[1][2], "");
+        assert_eq!(&rows[1][3], "1500");
+        assert_eq!(&rows[1][7], "");
+    }
+
+    #[test]
+    fn test_append_csv_writes_header_once() {
+        let path =
+            std::env::temp_dir().join(format!("tsume-output-append-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        for _ in 0..2 {
+            OutputWriter::append(OutputFormat::Csv, &path)
+                .unwrap()
+                .write(&sample_record())
+                .unwrap();
+        }
+
+        let rows: Vec<csv::StringRecord> = csv::Reader::from_path(&path)
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(&rows[1][0], sample_record().sfen);
+    }
+
+    #[test]
+    fn test_output_target() {
+        assert_eq!(
+            OutputTarget::new(Path::new("-"), 4, 3),
+            OutputTarget::Stdout
+        );
+        let target = OutputTarget::new(Path::new("out/results.sfen"), 8, 3);
+        assert_eq!(target.path(), Some(Path::new("out/results.0003.sfen")));
+        let target = OutputTarget::new(Path::new("results.sfen"), 1, 0);
+        assert_eq!(target.path(), Some(Path::new("results.sfen")));
+        assert_eq!(
+            shard_path(Path::new("results"), 12),
+            Path::new("results.0012")
+        );
+    }
+
+    #[test]
+    fn test_sfen_sha256() {
+        assert_eq!(
+            sfen_sha256(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(sample_record().sha256, sfen_sha256(&sample_record().sfen));
+    }
+
+    #[test]
+    fn test_integrity_report_finds_edited_sfen() {
+        let path = std::env::temp_dir().join(format!(
+            "tsume-output-integrity-{}.jsonl",
+            std::process::id()
+        ));
+        {
+            let mut writer = OutputWriter::create(OutputFormat::Jsonl, &path).unwrap();
+            for ply in [3, 5, 7] {
+                writer
+                    .write(&PuzzleRecord::new(
+                        "k2/PBR/3/p1p/rbK b - 1".to_string(),
+                        ply,
+                    ))
+                    .unwrap();
+            }
+        }
+        let clean = verify_puzzle_file_integrity(&path).unwrap();
+        assert_eq!(clean.total, 3);
+        assert!(clean.corrupted.is_empty());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        lines[1] = lines[1].replace("rbK b", "r1K b");
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let report = verify_puzzle_file_integrity(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.total, 3);
+        assert_eq!(report.corrupted, [2]);
+    }
+
+    #[test]
+    fn test_jsonl_omits_missing_optional_fields() {
+        let record = PuzzleRecord::new("k2/PBR/3/p1p/rbK b - 1".to_string(), 3);
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(!json.contains("mate_in"));
+        assert!(!json.contains("solution"));
+    }
+}
@@ -0,0 +1,337 @@
+//! PGN-like text export of played games, for reading and annotating by hand.
+//!
+//! A game is written as a `[SFEN "..."]` header followed by numbered moves in
+//! piece notation, each optionally followed by `{score=+50}` and a
+//! `{comment}`:
+//!
+//! ```text
+//! [SFEN "bkr/p1p/3/P1P/RKB b - 1"]
+//!
+//! 1. B2d {score=+50} 1... P1c {score=-30} 2. Px1c {the only defence}
+//! ```
+
+use std::fmt;
+
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::engine::Score;
+use crate::games::Game;
+use crate::movegen::legal_moves;
+use crate::training::{WHITE_OFFSET, encode_board};
+use crate::variant::Variant;
+
+/// Board the squares are laid out on
+const VARIANT: Variant = Variant::Wildcatshogi;
+/// Letters of the piece codes of [`encode_board`], indexed by code
+const PIECE_LETTERS: [&str; 7] = ["", "K", "R", "B", "G", "P", "+P"];
+
+/// What [`Game::to_pgn_like`] writes besides the moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PgnLikeConfig {
+    pub include_scores: bool,
+    pub include_comments: bool,
+    /// Move numbers per line; 0 writes every move on one line
+    pub moves_per_line: usize,
+}
+
+impl Default for PgnLikeConfig {
+    fn default() -> Self {
+        PgnLikeConfig {
+            include_scores: true,
+            include_comments: true,
+            moves_per_line: 8,
+        }
+    }
+}
+
+/// Why PGN-like text could not be read back into a game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The `[SFEN "..."]` header is malformed or holds an invalid position
+    BadHeader(String),
+    /// A `{` with no matching `}`
+    UnclosedBrace,
+    /// A `{score=...}` whose value is neither centipawns nor `#` and plies
+    BadScore(String),
+    /// The move token at `ply` (from 0) matches no legal move
+    UnknownMove { ply: usize, token: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::BadHeader(header) => write!(f, "bad header: {}", header),
+            ParseError::UnclosedBrace => write!(f, "unclosed {{"),
+            ParseError::BadScore(score) => write!(f, "bad score: {}", score),
+            ParseError::UnknownMove { ply, token } => {
+                write!(f, "ply {}: {} is not a legal move", ply + 1, token)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Game {
+    /// The game as a `[SFEN]` header and numbered moves in piece notation.
+    ///
+    /// Moves the library rejects are written in USI notation, as is every
+    /// move after them, since there is no board left to name their pieces.
+    pub fn to_pgn_like(&self, config: PgnLikeConfig) -> String {
+        let mut text = format!("[SFEN \"{}\"]\n\n", self.start_sfen);
+        let mut position = Position::from_sfen(&self.start_sfen).ok();
+        let black_started = self.start_sfen.split_whitespace().nth(1) != Some("w");
+        let mut numbers = 0;
+        let mut line_start = true;
+
+        for (ply, mv) in self.moves.iter().enumerate() {
+            let black = black_started == ply.is_multiple_of(2);
+            let number = (ply + usize::from(!black_started)) / 2 + 1;
+            if black || ply == 0 {
+                if black && config.moves_per_line > 0 && numbers == config.moves_per_line {
+                    text.push('\n');
+                    numbers = 0;
+                    line_start = true;
+                }
+                numbers += 1;
+            }
+            if !line_start {
+                text.push(' ');
+            }
+            line_start = false;
+            text.push_str(&format!("{}{} ", number, if black { "." } else { "..." }));
+
+            let parsed = Move::from_sfen(mv);
+            match (position.as_mut(), parsed) {
+                (Some(board), Some(parsed)) => {
+                    let name = to_algebraic(board, parsed);
+                    if board.make_move(parsed).is_ok() {
+                        text.push_str(&name);
+                    } else {
+                        text.push_str(mv);
+                        position = None;
+                    }
+                }
+                _ => {
+                    text.push_str(mv);
+                    position = None;
+                }
+            }
+
+            if let Some(Some(score)) = self.scores.get(ply).filter(|_| config.include_scores) {
+                text.push_str(&format!(" {{score={}}}", format_score(*score)));
+            }
+            if let Some(Some(comment)) = self.comments.get(ply).filter(|_| config.include_comments)
+            {
+                text.push_str(&format!(" {{{}}}", comment));
+            }
+        }
+        text.push('\n');
+        text
+    }
+}
+
+/// Read back text written by [`Game::to_pgn_like`].
+///
+/// Without a header the game starts from the starting position. Moves may be
+/// in piece notation or in USI notation, library convention.
+pub fn pgn_like_to_game(input: &str) -> Result<Game, ParseError> {
+    let mut body = input.trim_start();
+    let mut start_sfen = Position::startpos().to_sfen();
+    if let Some(rest) = body.strip_prefix('[') {
+        let (header, after) = rest
+            .split_once(']')
+            .ok_or_else(|| ParseError::BadHeader(rest.to_string()))?;
+        start_sfen = header
+            .strip_prefix("SFEN ")
+            .map(|value| value.trim().trim_matches('"').to_string())
+            .ok_or_else(|| ParseError::BadHeader(header.to_string()))?;
+        body = after;
+    }
+    let mut position =
+        Position::from_sfen(&start_sfen).map_err(|_| ParseError::BadHeader(start_sfen.clone()))?;
+
+    let mut game = Game {
+        start_sfen,
+        ..Game::default()
+    };
+    let mut rest = body;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(inner) = rest.strip_prefix('{') {
+            let (annotation, after) = inner.split_once('}').ok_or(ParseError::UnclosedBrace)?;
+            rest = after;
+            let Some(ply) = game.moves.len().checked_sub(1) else {
+                continue;
+            };
+            match annotation.strip_prefix("score=") {
+                Some(score) => {
+                    let score = parse_score(score)
+                        .ok_or_else(|| ParseError::BadScore(score.to_string()))?;
+                    game.scores[ply] = Some(score);
+                }
+                None => game.comments[ply] = Some(annotation.to_string()),
+            }
+            continue;
+        }
+
+        let end = rest.find(|c: char| c.is_whitespace() || c == '{');
+        let (token, after) = rest.split_at(end.unwrap_or(rest.len()));
+        rest = after;
+        // Move numbers: `1.` and `1...`
+        if token
+            .trim_end_matches('.')
+            .chars()
+            .all(|c| c.is_ascii_digit())
+        {
+            continue;
+        }
+        let ply = game.moves.len();
+        let unknown = || ParseError::UnknownMove {
+            ply,
+            token: token.to_string(),
+        };
+        let mv = legal_moves(&position)
+            .into_iter()
+            .find(|mv| mv.to_string() == token || to_algebraic(&position, *mv) == token)
+            .ok_or_else(unknown)?;
+        position.make_move(mv).map_err(|_| unknown())?;
+        game.moves.push(mv.to_string());
+        game.scores.push(None);
+        game.comments.push(None);
+    }
+    Ok(game)
+}
+
+/// `mv` in piece notation from `position`: the piece letter, `x` for a
+/// capture, the destination and `+` for a promotion; drops keep their USI
+/// form, e.g. `G*2b`.
+///
+/// The origin square goes between the letter and the rest if another piece
+/// of the same kind could reach the destination too, e.g. `G1bx2b`. The
+/// library's `Move` has no notion of pieces, so this reads them from the SFEN.
+pub fn to_algebraic(position: &Position, mv: Move) -> String {
+    let text = mv.to_string();
+    if text.contains('*') {
+        return text;
+    }
+    let board = position
+        .to_sfen()
+        .split_whitespace()
+        .next()
+        .and_then(encode_board);
+    let Some(board) = board else {
+        return text;
+    };
+    let letter = |square: &str| {
+        square_index(square)
+            .and_then(|index| PIECE_LETTERS.get((board[index] % WHITE_OFFSET) as usize))
+            .copied()
+            .unwrap_or("")
+    };
+    let (from, to) = (text.get(0..2).unwrap_or(""), text.get(2..4).unwrap_or(""));
+    let piece = letter(from);
+    let capture = if letter(to).is_empty() { "" } else { "x" };
+    let promotion = if text.ends_with('+') { "+" } else { "" };
+
+    let ambiguous = legal_moves(position).into_iter().any(|other| {
+        let other = other.to_string();
+        other != text
+            && !other.contains('*')
+            && other.get(2..4) == Some(to)
+            && other.ends_with('+') == text.ends_with('+')
+            && other.get(0..2).map(letter) == Some(piece)
+    });
+    let origin = if ambiguous { from } else { "" };
+    format!("{}{}{}{}{}", piece, origin, capture, to, promotion)
+}
+
+/// Index in [`encode_board`] order of a square such as `2b`.
+fn square_index(square: &str) -> Option<usize> {
+    let mut chars = square.chars();
+    let file = chars.next()?.to_digit(10)? as usize;
+    let rank = (chars.next()? as u8).checked_sub(b'a')? as usize;
+    let files = VARIANT.files() as usize;
+    (file >= 1 && file <= files && rank < VARIANT.ranks() as usize).then(|| rank * files + file - 1)
+}
+
+/// `+50`, `-30` or `#+3` for a mate in 3 plies.
+fn format_score(score: Score) -> String {
+    match score {
+        Score::Cp(cp) => format!("{:+}", cp),
+        Score::Mate(plies) => format!("#{:+}", plies),
+    }
+}
+
+fn parse_score(text: &str) -> Option<Score> {
+    match text.strip_prefix('#') {
+        Some(plies) => plies.parse().ok().map(Score::Mate),
+        None => text.parse().ok().map(Score::Cp),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "bkr/p1p/3/P1P/RKB b - 1";
+
+    fn game() -> Game {
+        Game {
+            start_sfen: START.to_string(),
+            moves: ["3e2d", "1b1c", "1d1c", "2a2b", "2d3c"]
+                .iter()
+                .map(|mv| mv.to_string())
+                .collect(),
+            scores: vec![
+                Some(Score::Cp(50)),
+                Some(Score::Cp(-30)),
+                Some(Score::Cp(120)),
+                None,
+                Some(Score::Mate(3)),
+            ],
+            comments: vec![None, Some("the only defence".to_string())],
+            ..Game::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let game = game();
+        let text = game.to_pgn_like(PgnLikeConfig::default());
+        let parsed = pgn_like_to_game(&text).unwrap();
+        assert_eq!(parsed.start_sfen, game.start_sfen);
+        assert_eq!(parsed.moves, game.moves);
+        assert_eq!(parsed.scores, game.scores);
+        assert_eq!(parsed.comments[1], game.comments[1]);
+    }
+
+    #[test]
+    fn test_layout() {
+        let text = game().to_pgn_like(PgnLikeConfig {
+            include_scores: true,
+            include_comments: false,
+            moves_per_line: 2,
+        });
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], format!("[SFEN \"{}\"]", START));
+        assert!(lines[2].starts_with("1. B2d {score=+50} 1... "));
+        assert!(lines[3].starts_with("3. "));
+        assert!(lines[3].ends_with("{score=#+3}"));
+        assert!(!text.contains("defence"));
+    }
+
+    #[test]
+    fn test_unknown_move() {
+        assert_eq!(
+            pgn_like_to_game("1. K1a"),
+            Err(ParseError::UnknownMove {
+                ply: 0,
+                token: "K1a".to_string()
+            })
+        );
+    }
+}
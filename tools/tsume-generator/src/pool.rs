@@ -0,0 +1,167 @@
+//! Shared pool of engine processes.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+
+use crate::engine::{Engine, EngineConfig, EngineError};
+
+/// A fixed set of engines handed out to whichever thread needs one.
+pub struct EnginePool {
+    engines: Mutex<Engines>,
+    /// Signalled whenever an engine is returned or the pool shrinks
+    returned: Condvar,
+    config: EngineConfig,
+}
+
+/// The idle engines and the pool size, under one lock so that a waiter
+/// cannot miss the pool shrinking between checking the size and waiting.
+struct Engines {
+    idle: Vec<Engine>,
+    /// Engines owned by the pool, idle or checked out
+    size: usize,
+}
+
+/// An engine checked out of a pool; returned when dropped.
+pub struct EngineGuard<'a> {
+    pool: &'a EnginePool,
+    engine: Option<Engine>,
+}
+
+impl EnginePool {
+    pub fn new(count: usize, config: &EngineConfig) -> Result<Self, EngineError> {
+        let engines = (0..count)
+            .map(|_| Engine::spawn(config))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(EnginePool {
+            engines: Mutex::new(Engines {
+                idle: engines,
+                size: count,
+            }),
+            returned: Condvar::new(),
+            config: config.clone(),
+        })
+    }
+
+    /// Take an engine, waiting until one is free.
+    ///
+    /// Returns `None` once the pool has no engines left at all.
+    pub fn acquire(&self) -> Option<EngineGuard<'_>> {
+        let mut engines = self.engines.lock().unwrap();
+        loop {
+            if let Some(engine) = engines.idle.pop() {
+                return Some(EngineGuard {
+                    pool: self,
+                    engine: Some(engine),
+                });
+            }
+            if engines.size == 0 {
+                return None;
+            }
+            engines = self.returned.wait(engines).unwrap();
+        }
+    }
+
+    /// Engines currently idle in the pool.
+    pub fn available_count(&self) -> usize {
+        self.engines.lock().unwrap().idle.len()
+    }
+
+    /// Take back an engine, replacing it first if it hung or crashed.
+    fn release(&self, mut engine: Engine) {
+        if engine.failure().is_some() {
+            engine.quit();
+            match Engine::spawn(&self.config) {
+                Ok(fresh) => engine = fresh,
+                Err(e) => {
+                    eprintln!("Dropping engine from pool: {}", e);
+                    let mut engines = self.engines.lock().unwrap();
+                    engines.size -= 1;
+                    self.returned.notify_all();
+                    return;
+                }
+            }
+        }
+        self.engines.lock().unwrap().idle.push(engine);
+        self.returned.notify_one();
+    }
+}
+
+impl Drop for EnginePool {
+    fn drop(&mut self) {
+        for engine in self.engines.get_mut().unwrap().idle.iter_mut() {
+            engine.quit();
+        }
+    }
+}
+
+impl Deref for EngineGuard<'_> {
+    type Target = Engine;
+
+    fn deref(&self) -> &Engine {
+        self.engine.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for EngineGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Engine {
+        self.engine.as_mut().unwrap()
+    }
+}
+
+impl Drop for EngineGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(engine) = self.engine.take() {
+            self.pool.release(engine);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use super::*;
+    use crate::variant::Variant;
+
+    fn candidates_config() -> EngineConfig {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        EngineConfig {
+            engine: fixtures.join("candidates-engine.sh"),
+            variants_ini: fixtures.join("variants.ini"),
+            variant: Variant::Wildcatshogi,
+            ..EngineConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_threads_share_engines_without_overlap() {
+        let pool = EnginePool::new(2, &candidates_config()).unwrap();
+        assert_eq!(pool.available_count(), 2);
+
+        let in_use = AtomicUsize::new(0);
+        let max_in_use = AtomicUsize::new(0);
+        let completed = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut engine = pool.acquire().unwrap();
+                    let now = in_use.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_use.fetch_max(now, Ordering::SeqCst);
+
+                    engine.set_sfen("bkr/p1p/3/P1P/RKB b - 1").unwrap();
+                    let (pv_infos, _) = engine.search_with_time(10).unwrap();
+                    assert_eq!(pv_infos.len(), 3);
+
+                    in_use.fetch_sub(1, Ordering::SeqCst);
+                    completed.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(completed.load(Ordering::SeqCst), 4);
+        assert!(max_in_use.load(Ordering::SeqCst) <= 2);
+        assert_eq!(pool.available_count(), 2);
+    }
+}
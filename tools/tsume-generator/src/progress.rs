@@ -0,0 +1,71 @@
+//! Progress lines on stderr while workers get through a run.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Least time between two progress lines
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Counts the items finished by every worker of a run and prints how far
+/// the run got, at most once per [`REPORT_INTERVAL`].
+pub struct ProgressReporter {
+    /// What is counted, e.g. "puzzles found"
+    noun: &'static str,
+    total: usize,
+    done: AtomicUsize,
+    started: Instant,
+    /// When the last line was printed, or the run started
+    reported: Mutex<Instant>,
+}
+
+impl ProgressReporter {
+    pub fn new(noun: &'static str, total: usize) -> Self {
+        let started = Instant::now();
+        ProgressReporter {
+            noun,
+            total,
+            done: AtomicUsize::new(0),
+            started,
+            reported: Mutex::new(started),
+        }
+    }
+
+    /// Count one finished item, printing a progress line if one is due.
+    pub fn record(&self) {
+        let done = self.done.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut reported = self.reported.lock().unwrap();
+        if reported.elapsed() < REPORT_INTERVAL {
+            return;
+        }
+        *reported = Instant::now();
+        eprintln!(
+            "{}",
+            progress_line(self.noun, done, self.total, self.started.elapsed())
+        );
+    }
+}
+
+/// `Progress: 12/40 puzzles found (0.5/s)`
+fn progress_line(noun: &str, done: usize, total: usize, elapsed: Duration) -> String {
+    let rate = done as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    format!("Progress: {}/{} {} ({:.1}/s)", done, total, noun, rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_line() {
+        assert_eq!(
+            progress_line("puzzles verified", 12, 40, Duration::from_secs(24)),
+            "Progress: 12/40 puzzles verified (0.5/s)"
+        );
+
+        let reporter = ProgressReporter::new("puzzles found", 2);
+        reporter.record();
+        reporter.record();
+        assert_eq!(reporter.done.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,125 @@
+//! Producer-consumer plumbing between generation workers and the output file.
+
+use std::io;
+use std::sync::mpsc::{self, Receiver, SendError, SyncSender};
+use std::thread;
+
+use crate::output::{OutputFormat, OutputTarget, PuzzleRecord};
+
+/// Sending half handed to worker threads; clone it once per worker.
+#[derive(Clone)]
+pub struct TsumeWorkQueue {
+    tx: SyncSender<PuzzleRecord>,
+}
+
+/// Receiving half that writes every submitted puzzle to the output.
+pub struct TsumeCollector {
+    rx: Receiver<PuzzleRecord>,
+    format: OutputFormat,
+    diagram: bool,
+    append: bool,
+}
+
+impl TsumeWorkQueue {
+    /// Create a queue holding at most `buffer` unwritten puzzles.
+    pub fn new(buffer: usize) -> (TsumeWorkQueue, TsumeCollector) {
+        let (tx, rx) = mpsc::sync_channel(buffer);
+        let collector = TsumeCollector {
+            rx,
+            format: OutputFormat::Plain,
+            diagram: false,
+            append: false,
+        };
+        (TsumeWorkQueue { tx }, collector)
+    }
+
+    /// Hand a finished puzzle to the collector, blocking while the buffer is full.
+    ///
+    /// Fails only if the collector has stopped, e.g. after a write error.
+    pub fn submit(&self, record: PuzzleRecord) -> Result<(), Box<SendError<PuzzleRecord>>> {
+        self.tx.send(record).map_err(Box::new)
+    }
+}
+
+impl TsumeCollector {
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Add the rendered board to every puzzle, see [`OutputWriter::diagram`].
+    pub fn diagram(mut self, enabled: bool) -> Self {
+        self.diagram = enabled;
+        self
+    }
+
+    /// Add to the end of the output file instead of overwriting it.
+    pub fn append(mut self, enabled: bool) -> Self {
+        self.append = enabled;
+        self
+    }
+
+    /// Write puzzles to `target` on a dedicated thread until every queue handle is dropped.
+    ///
+    /// The thread returns the number of puzzles written.
+    pub fn run_to(self, target: OutputTarget) -> thread::JoinHandle<io::Result<usize>> {
+        thread::spawn(move || {
+            let mut sink = target.open(self.format, self.append, self.diagram)?;
+            let mut written = 0;
+            for record in self.rx {
+                sink.write(&record)?;
+                // Flush every record so an interrupted run, or a reader at the
+                // other end of a pipe, sees complete lines
+                sink.flush()?;
+                written += 1;
+            }
+            Ok(written)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collector_receives_every_submission() {
+        let path = std::env::temp_dir().join(format!("tsume-queue-{}.jsonl", std::process::id()));
+        let (queue, collector) = TsumeWorkQueue::new(2);
+        let handle = collector
+            .format(OutputFormat::Jsonl)
+            .run_to(OutputTarget::File(path.clone()));
+
+        let workers: Vec<_> = (0..3)
+            .map(|worker| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 0..5 {
+                        let sfen =
+                            format!("k2/3/3/{}/2K b - 1", ["3", "P2", "1P1", "2P", "PP1"][i]);
+                        queue.submit(PuzzleRecord::new(sfen, worker)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(queue);
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(handle.join().unwrap().unwrap(), 15);
+        let text = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<PuzzleRecord> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 15);
+        for worker in 0..3 {
+            assert_eq!(
+                records.iter().filter(|r| r.ply_of_game == worker).count(),
+                5
+            );
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,232 @@
+//! Difficulty estimates and ratings for verified puzzles.
+
+use crate::engine::{EngineInterface, Score, SearchResult, Strength};
+use crate::output::PuzzleRecord;
+use crate::verify::{VerifyResult, verify_puzzle};
+
+/// Search budgets tried in turn until the engine sees the mate
+pub const PROBE_BUDGETS_MS: [u64; 3] = [10, 50, 250];
+
+/// One step of [`RATING_LADDER`]: a strength-limited search, and the rating
+/// of a puzzle first solved there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RatingRung {
+    /// `UCI_Elo` of the search
+    pub elo: u32,
+    pub search_ms: u64,
+    pub rating: u32,
+}
+
+/// Searches of [`estimate_rating`], weakest first:
+///
+/// | `UCI_Elo` | Search | Rating |
+/// |-----------|--------|--------|
+/// | 800       | 10 ms  | 800    |
+/// | 1200      | 20 ms  | 1100   |
+/// | 1600      | 50 ms  | 1400   |
+/// | 2000      | 100 ms | 1700   |
+/// | 2400      | 250 ms | 2000   |
+///
+/// A puzzle none of them solves is rated [`UNSOLVED_RATING`].
+pub const RATING_LADDER: [RatingRung; 5] = [
+    RatingRung {
+        elo: 800,
+        search_ms: 10,
+        rating: 800,
+    },
+    RatingRung {
+        elo: 1200,
+        search_ms: 20,
+        rating: 1100,
+    },
+    RatingRung {
+        elo: 1600,
+        search_ms: 50,
+        rating: 1400,
+    },
+    RatingRung {
+        elo: 2000,
+        search_ms: 100,
+        rating: 1700,
+    },
+    RatingRung {
+        elo: 2400,
+        search_ms: 250,
+        rating: 2000,
+    },
+];
+/// Rating of a puzzle no rung of [`RATING_LADDER`] solves
+pub const UNSOLVED_RATING: u32 = 2300;
+
+/// How hard a puzzle was for the engine to solve.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PuzzleRating {
+    /// Smallest probe budget at which the engine found the mate, if any
+    pub found_at_ms: Option<u64>,
+    /// Mate distance in plies from the longest probe, if it found one
+    pub mate_in: Option<i32>,
+    /// Candidate first moves that do not mate, from the longest probe
+    pub non_mating_candidates: usize,
+    /// Combined estimate; higher is harder
+    pub difficulty: f64,
+}
+
+/// Probe `sfen` with searches of increasing length and rate its difficulty.
+///
+/// Each probe budget the engine needs beyond the first adds 1, each move of
+/// the attacker adds 1 and each plausible non-mating candidate adds 0.5.
+/// A mate never found at any budget counts as one level beyond the last.
+/// Returns `None` if the engine fails during probing.
+pub fn rate_puzzle<E: EngineInterface>(engine: &mut E, sfen: &str) -> Option<PuzzleRating> {
+    let mut found_level = None;
+    let mut last = None;
+    for (level, &budget) in PROBE_BUDGETS_MS.iter().enumerate() {
+        let verification = verify_puzzle(engine, sfen, budget);
+        if let VerifyResult::Timeout(_) = verification.result {
+            return None;
+        }
+        if found_level.is_none() && matches!(verification.result, VerifyResult::Matein(_)) {
+            found_level = Some(level);
+        }
+        last = Some(verification);
+    }
+    let last = last?;
+
+    let mate_in = match last.result {
+        VerifyResult::Matein(plies) => Some(plies),
+        _ => None,
+    };
+    let non_mating_candidates = last
+        .pv_infos
+        .iter()
+        .filter(|pv| !pv.moves.is_empty())
+        .filter(|pv| !matches!(pv.score, Score::Mate(plies) if plies > 0))
+        .count();
+
+    let level = found_level.unwrap_or(PROBE_BUDGETS_MS.len());
+    let attacker_moves = mate_in.map_or(0, |plies| (plies + 1) / 2);
+    let difficulty = level as f64 + attacker_moves as f64 + 0.5 * non_mating_candidates as f64;
+
+    Some(PuzzleRating {
+        found_at_ms: found_level.map(|level| PROBE_BUDGETS_MS[level]),
+        mate_in,
+        non_mating_candidates,
+        difficulty,
+    })
+}
+
+/// Rate `puzzle` by the weakest rung of [`RATING_LADDER`] whose
+/// strength-limited search plays the key move.
+///
+/// Slower than [`rate_puzzle`]: every rung is a search of its own. A rated
+/// puzzle leaves the engine at full strength. Returns `None` without a
+/// verified solution or if the engine fails.
+pub fn estimate_rating<E: EngineInterface>(engine: &mut E, puzzle: &PuzzleRecord) -> Option<u32> {
+    let key = puzzle.solution.as_ref()?.first()?;
+    let mut rating = UNSOLVED_RATING;
+    for rung in RATING_LADDER {
+        engine.set_strength(Some(Strength::Elo(rung.elo)))?;
+        // A limited engine picks its weaker move among the lines it searches
+        engine.set_multipv(1)?;
+        engine.set_search_ms(rung.search_ms);
+        engine.set_position(&puzzle.sfen, &[])?;
+        // Solutions and engine moves are both in Fairy-Stockfish coordinates
+        if engine.get_engine_move()? == SearchResult::Move(key.clone()) {
+            rating = rung.rating;
+            break;
+        }
+    }
+    engine.set_strength(None)?;
+    Some(rating)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEngine;
+
+    fn puzzle() -> PuzzleRecord {
+        let mut record = PuzzleRecord::new("1k1/3/R2/3/K1B b G 1".to_string(), 0);
+        record.solution = Some(vec!["G*2b".to_string()]);
+        record
+    }
+
+    #[test]
+    fn test_rating_is_the_weakest_rung_that_solves() {
+        let mut engine = MockEngine::new([
+            MockEngine::answer("3c3a", Score::Cp(200)),
+            MockEngine::answer("G*1b", Score::Cp(300)),
+            MockEngine::answer("G*2b", Score::Mate(1)),
+        ]);
+        assert_eq!(estimate_rating(&mut engine, &puzzle()), Some(1400));
+        assert_eq!(engine.search_ms, [10, 20, 50]);
+        assert_eq!(
+            engine.strengths,
+            [
+                Some(Strength::Elo(800)),
+                Some(Strength::Elo(1200)),
+                Some(Strength::Elo(1600)),
+                None
+            ]
+        );
+        assert!(engine.positions.iter().all(|p| p == "1k1/3/R2/3/K1B b G 1"));
+    }
+
+    #[test]
+    fn test_unsolved_and_unrated() {
+        let miss = || MockEngine::answer("3c3a", Score::Cp(200));
+        let mut engine = MockEngine::new(std::iter::repeat_with(miss).take(RATING_LADDER.len()));
+        assert_eq!(
+            estimate_rating(&mut engine, &puzzle()),
+            Some(UNSOLVED_RATING)
+        );
+
+        // The script runs out on the second rung
+        let mut engine = MockEngine::new([miss()]);
+        assert_eq!(estimate_rating(&mut engine, &puzzle()), None);
+        let unverified = PuzzleRecord::new("1k1/3/R2/3/K1B b G 1".to_string(), 0);
+        assert_eq!(estimate_rating(&mut engine, &unverified), None);
+    }
+
+    #[cfg(unix)]
+    fn fixture_engine(name: &str) -> crate::engine::Engine {
+        use std::path::Path;
+
+        use crate::engine::{Engine, EngineConfig};
+        use crate::variant::Variant;
+
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        Engine::spawn(&EngineConfig {
+            engine: fixtures.join(name),
+            variants_ini: fixtures.join("variants.ini"),
+            variant: Variant::Wildcatshogi,
+            ..EngineConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rate_puzzle_found_on_second_probe() {
+        let mut engine = fixture_engine("slow-mate-engine.sh");
+        let rating = rate_puzzle(&mut engine, "1k1/3/R2/3/K1B b G 1").unwrap();
+        assert_eq!(rating.found_at_ms, Some(50));
+        assert_eq!(rating.mate_in, Some(3));
+        assert_eq!(rating.non_mating_candidates, 2);
+        // One extra probe, two attacker moves, two alternatives
+        assert_eq!(rating.difficulty, 1.0 + 2.0 + 1.0);
+        engine.quit();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rate_puzzle_found_immediately_is_easier() {
+        let mut engine = fixture_engine("mate-engine.sh");
+        let rating = rate_puzzle(&mut engine, "1k1/3/R2/3/K1B b G 1").unwrap();
+        assert_eq!(rating.found_at_ms, Some(10));
+        assert_eq!(rating.difficulty, 2.0);
+
+        engine.quit();
+        assert_eq!(rate_puzzle(&mut engine, "1k1/3/R2/3/K1B b G 1"), None);
+    }
+}
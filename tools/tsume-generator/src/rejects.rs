@@ -0,0 +1,119 @@
+//! Machine-readable log of rejected candidates and failed games.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::generator::Rejection;
+
+/// Stage at which a game or candidate was given up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectStage {
+    /// The engine failed or played a move the library could not replay, or the
+    /// game started in a mated position
+    SimulationError,
+    /// The game reached its ply limit or was interrupted without a mate
+    NoMate,
+    /// The game was abandoned after a position came up a third time
+    Repetition,
+    /// The game was abandoned without a capture, drop or pawn move for too long
+    Stuck,
+    /// The game was abandoned for an opening played too often (`--max-opening-reuse`)
+    OpeningReuse,
+    /// The mate came before `--min-game-plies`
+    TooShort,
+    /// The puzzle position failed to parse or had White to move
+    BadSfen,
+    /// The position was already found in this run
+    Duplicate,
+    /// The engine found no forced mate, or its solution did not replay
+    VerificationFailed,
+    /// The engine failed while verifying
+    EngineFailed,
+    /// The `--strictly-forced` walk ran out of time
+    OutOfTime,
+    /// Mate length outside `--min-mate`/`--max-mate`
+    MateLength,
+    MultipleSolutions,
+    /// The defender escaped in the defense check
+    Defended,
+    /// Difficulty outside `--min-difficulty`/`--max-difficulty`
+    Difficulty,
+    /// A puzzle filter refused it
+    Filtered,
+}
+
+impl From<Rejection> for RejectStage {
+    fn from(rejection: Rejection) -> Self {
+        match rejection {
+            Rejection::Unverified => RejectStage::VerificationFailed,
+            Rejection::EngineFailed => RejectStage::EngineFailed,
+            Rejection::OutOfTime => RejectStage::OutOfTime,
+            Rejection::MateLength(_) => RejectStage::MateLength,
+            Rejection::MultipleSolutions => RejectStage::MultipleSolutions,
+            Rejection::Defended => RejectStage::Defended,
+            Rejection::Difficulty(_) => RejectStage::Difficulty,
+            Rejection::Filtered => RejectStage::Filtered,
+        }
+    }
+}
+
+/// One line of the `--reject-log` file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RejectRecord {
+    pub stage: RejectStage,
+    /// Position concerned: the puzzle position, or where the game failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sfen: Option<String>,
+    /// Engine move, in Fairy-Stockfish convention, that could not be replayed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engine_move: Option<String>,
+    /// Plies played in the game before the failure or the puzzle position
+    pub plies: usize,
+}
+
+impl RejectRecord {
+    pub fn new(stage: RejectStage, sfen: Option<String>, plies: usize) -> Self {
+        RejectRecord {
+            stage,
+            sfen,
+            engine_move: None,
+            plies,
+        }
+    }
+}
+
+impl fmt::Display for RejectRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_record_line() {
+        let record = RejectRecord::new(
+            Rejection::MateLength(7).into(),
+            Some("1k1/3/R2/3/K1B b G 1".to_string()),
+            12,
+        );
+        assert_eq!(
+            record.to_string(),
+            r#"{"stage":"mate_length","sfen":"1k1/3/R2/3/K1B b G 1","plies":12}"#
+        );
+
+        let record = RejectRecord {
+            engine_move: Some("1e2d".to_string()),
+            ..RejectRecord::new(RejectStage::SimulationError, None, 3)
+        };
+        assert_eq!(
+            record.to_string(),
+            r#"{"stage":"simulation_error","engine_move":"1e2d","plies":3}"#
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Reading existing output files, before `--append` adds to one or when
+//! `merge` combines several.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::ensure_black_to_move;
+use crate::output::{OutputFormat, PuzzleRecord, duplicate_key};
+use crate::sfen::validate_puzzle_sfen;
+
+/// Puzzles already in an output file.
+#[derive(Debug, Default)]
+pub struct ExistingOutput {
+    /// [`duplicate_key`]s of the readable puzzles, to seed the duplicate check
+    pub fingerprints: HashSet<String>,
+    /// Lines holding no readable puzzle, as `(line number, reason)`
+    pub malformed: Vec<(usize, String)>,
+}
+
+/// Reasons an output file cannot be appended to.
+#[derive(Debug)]
+pub enum ResumeError {
+    Io(io::Error),
+    /// The file is written in another format than the one requested
+    FormatMismatch {
+        requested: OutputFormat,
+        found: OutputFormat,
+    },
+}
+
+impl fmt::Display for ResumeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResumeError::Io(e) => write!(f, "{}", e),
+            ResumeError::FormatMismatch { requested, found } => write!(
+                f,
+                "file is in {:?} format, not the requested {:?}",
+                found, requested
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResumeError {}
+
+impl From<io::Error> for ResumeError {
+    fn from(e: io::Error) -> Self {
+        ResumeError::Io(e)
+    }
+}
+
+/// Read the puzzles of `path`, written in `format`; a missing file holds none.
+///
+/// Fails if the first puzzle line shows the file is in another format, so a
+/// run never interleaves two formats. Unreadable lines are only reported.
+pub fn read_existing_output(
+    path: &Path,
+    format: OutputFormat,
+) -> Result<ExistingOutput, ResumeError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ExistingOutput::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut existing = ExistingOutput::default();
+    read_puzzles(file, format, |number, _, sfen| match sfen {
+        Ok(sfen) => {
+            existing.fingerprints.insert(duplicate_key(&sfen));
+        }
+        Err(reason) => existing.malformed.push((number, reason)),
+    })?;
+    Ok(existing)
+}
+
+/// A puzzle as it is stored in an output file.
+#[derive(Debug, Clone, Copy)]
+pub enum StoredPuzzle<'a> {
+    /// Line of a plain or JSON Lines file
+    Line(&'a str),
+    /// Row of a CSV file, with the file's header row
+    Row {
+        headers: &'a csv::StringRecord,
+        row: &'a csv::StringRecord,
+    },
+}
+
+/// Read the puzzles of `file`, written in `format`, one at a time: `each`
+/// gets the line number, the puzzle as stored and its SFEN, or why it holds
+/// no readable puzzle.
+///
+/// Fails if the first puzzle line shows the file is in another format.
+pub fn read_puzzles(
+    file: impl Read,
+    format: OutputFormat,
+    mut each: impl FnMut(usize, StoredPuzzle, Result<String, String>),
+) -> Result<(), ResumeError> {
+    // Puzzles written as played (`--no-mirror`) may have White to move
+    let check = |sfen: Result<String, String>| {
+        sfen.and_then(|sfen| validate_puzzle_sfen(&ensure_black_to_move(&sfen)).map(|()| sfen))
+    };
+
+    if matches!(format, OutputFormat::Csv | OutputFormat::LichessCsv) {
+        // Quoted diagrams span several lines, so CSV goes through its own reader
+        let mut reader = csv::Reader::from_reader(file);
+        let headers = reader.headers().map_err(io::Error::from)?.clone();
+        let found = detect_format(&headers.iter().collect::<Vec<_>>().join(","));
+        if !headers.is_empty() && found != format {
+            return Err(ResumeError::FormatMismatch {
+                requested: format,
+                found,
+            });
+        }
+        let column = usize::from(format == OutputFormat::LichessCsv);
+        for row in reader.records() {
+            let row = row.map_err(io::Error::from)?;
+            let number = row.position().map_or(0, |p| p.line() as usize);
+            let sfen = row.get(column).map(str::to_string);
+            let stored = StoredPuzzle::Row {
+                headers: &headers,
+                row: &row,
+            };
+            each(
+                number,
+                stored,
+                check(sfen.ok_or_else(|| "empty row".to_string())),
+            );
+        }
+        return Ok(());
+    }
+
+    let mut checked = false;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !checked {
+            let found = detect_format(line);
+            if found != format {
+                return Err(ResumeError::FormatMismatch {
+                    requested: format,
+                    found,
+                });
+            }
+            checked = true;
+        }
+        let sfen = match format {
+            OutputFormat::Jsonl => serde_json::from_str::<PuzzleRecord>(line)
+                .map(|record| record.sfen)
+                .map_err(|e| e.to_string()),
+            // Without the attacker column of `--no-mirror`
+            _ => Ok(line.split('\t').next().unwrap_or_default().to_string()),
+        };
+        each(index + 1, StoredPuzzle::Line(line), check(sfen));
+    }
+    Ok(())
+}
+
+/// Format of the output file `path`, from its first puzzle line; `None` if
+/// it has none.
+pub fn detect_file_format(path: &Path) -> io::Result<Option<OutputFormat>> {
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            return Ok(Some(detect_format(line)));
+        }
+    }
+    Ok(None)
+}
+
+/// Format a file whose first puzzle line is `line` is written in.
+fn detect_format(line: &str) -> OutputFormat {
+    if line.starts_with('{') {
+        OutputFormat::Jsonl
+    } else if line == "sfen" || line.starts_with("sfen,") {
+        OutputFormat::Csv
+    } else if line.starts_with("PuzzleId,") {
+        OutputFormat::LichessCsv
+    } else {
+        OutputFormat::Plain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OutputWriter;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_plain_file_seeds_fingerprints() {
+        let existing =
+            read_existing_output(&fixture("existing.sfen"), OutputFormat::Plain).unwrap();
+        // The second puzzle is the first with another move counter and hand order
+        assert_eq!(existing.fingerprints.len(), 2);
+        assert!(
+            existing
+                .fingerprints
+                .contains(&duplicate_key("1k1/3/R2/3/K1B b GP 1"))
+        );
+        assert_eq!(existing.malformed.len(), 1);
+        assert_eq!(existing.malformed[0].0, 5);
+    }
+
+    #[test]
+    fn test_unmirrored_puzzles_match_their_mirror() {
+        let path = std::env::temp_dir().join(format!("tsume-resume-{}.sfen", std::process::id()));
+        std::fs::write(&path, "kBR/P1P/3/rbp/2K w - 12\tw\n").unwrap();
+        let existing = read_existing_output(&path, OutputFormat::Plain);
+        std::fs::remove_file(&path).unwrap();
+        let existing = existing.unwrap();
+        assert!(existing.malformed.is_empty());
+        assert!(
+            existing
+                .fingerprints
+                .contains(&duplicate_key("k2/PBR/3/p1p/rbK b - 1"))
+        );
+    }
+
+    #[test]
+    fn test_diagram_comments_are_skipped() {
+        let path = std::env::temp_dir().join(format!("tsume-diagram-{}.sfen", std::process::id()));
+        {
+            let mut writer = OutputWriter::create(OutputFormat::Plain, &path)
+                .unwrap()
+                .diagram(true);
+            writer
+                .write(&PuzzleRecord::new("1k1/3/R2/3/K1B b G 1".to_string(), 3))
+                .unwrap();
+        }
+        let existing = read_existing_output(&path, OutputFormat::Plain);
+        std::fs::remove_file(&path).unwrap();
+        let existing = existing.unwrap();
+        assert_eq!(existing.fingerprints.len(), 1);
+        assert!(existing.malformed.is_empty());
+    }
+
+    #[test]
+    fn test_jsonl_file_seeds_fingerprints() {
+        let existing =
+            read_existing_output(&fixture("existing.jsonl"), OutputFormat::Jsonl).unwrap();
+        assert_eq!(existing.fingerprints.len(), 2);
+        assert_eq!(
+            existing
+                .malformed
+                .iter()
+                .map(|(line, _)| *line)
+                .collect::<Vec<_>>(),
+            [3]
+        );
+    }
+
+    #[test]
+    fn test_format_mismatch() {
+        assert!(matches!(
+            read_existing_output(&fixture("existing.jsonl"), OutputFormat::Plain),
+            Err(ResumeError::FormatMismatch {
+                found: OutputFormat::Jsonl,
+                ..
+            })
+        ));
+        assert!(matches!(
+            read_existing_output(&fixture("existing.sfen"), OutputFormat::Csv),
+            Err(ResumeError::FormatMismatch {
+                found: OutputFormat::Plain,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_lichess_csv_file_seeds_fingerprints() {
+        let path = std::env::temp_dir().join(format!("tsume-resume-{}.csv", std::process::id()));
+        {
+            let mut writer = OutputWriter::create(OutputFormat::LichessCsv, &path).unwrap();
+            writer
+                .write(&PuzzleRecord::new("1k1/3/R2/3/K1B b GP 1".to_string(), 3))
+                .unwrap();
+        }
+        let existing = read_existing_output(&path, OutputFormat::LichessCsv);
+        let mismatch = read_existing_output(&path, OutputFormat::Csv);
+        std::fs::remove_file(&path).unwrap();
+        let existing = existing.unwrap();
+        assert!(
+            existing
+                .fingerprints
+                .contains(&duplicate_key("1k1/3/R2/3/K1B b GP 1"))
+        );
+        assert!(existing.malformed.is_empty());
+        assert!(matches!(
+            mismatch,
+            Err(ResumeError::FormatMismatch {
+                found: OutputFormat::LichessCsv,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_missing_file_is_empty() {
+        let existing = read_existing_output(&fixture("missing.sfen"), OutputFormat::Plain).unwrap();
+        assert!(existing.fingerprints.is_empty());
+        assert!(existing.malformed.is_empty());
+    }
+}
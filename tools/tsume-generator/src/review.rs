@@ -0,0 +1,185 @@
+//! Engine review of played games: the score of each move against the
+//! engine's best, with mistakes and blunders marked.
+
+use std::fmt;
+
+use crate::convert_move_files;
+use crate::engine::{EngineInterface, Score};
+use crate::games::Game;
+
+/// Centipawns lost from which a move is a mistake
+pub const MISTAKE_CP: i32 = 50;
+/// Centipawns lost from which a move is a blunder
+pub const BLUNDER_CP: i32 = 200;
+
+/// How bad a move was, by the centipawns it lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    /// [`MISTAKE_CP`] or more
+    Mistake,
+    /// [`BLUNDER_CP`] or more
+    Blunder,
+}
+
+impl Judgement {
+    fn of(loss: i32) -> Option<Judgement> {
+        if loss >= BLUNDER_CP {
+            Some(Judgement::Blunder)
+        } else if loss >= MISTAKE_CP {
+            Some(Judgement::Mistake)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Judgement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        })
+    }
+}
+
+/// One move of a reviewed game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnotatedMove {
+    /// The move played, in library convention
+    pub mv: String,
+    /// Score of the move played, from the side that played it
+    pub score: Option<Score>,
+    /// The engine's best move, in library convention, if not the move played
+    pub best_move: Option<String>,
+    /// Centipawns the move played scores below the best move
+    pub loss: Option<i32>,
+    pub judgement: Option<Judgement>,
+}
+
+/// A game with every move reviewed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnotatedGame {
+    pub moves: Vec<AnnotatedMove>,
+}
+
+/// Search every position of `game` and judge each move against the engine's best.
+///
+/// The score of a move is the negated score of the position it leads to, so
+/// each position is searched once and the engine's options, `MultiPV`
+/// included, are left as they are. A search that fails leaves the moves
+/// depending on it unscored.
+pub fn replay_and_annotate<E: EngineInterface>(engine: &mut E, game: &Game) -> AnnotatedGame {
+    // Engines take moves in Fairy-Stockfish convention
+    let history: Vec<String> = game.moves.iter().map(|mv| convert_move_files(mv)).collect();
+    let mut searches = (0..=history.len()).map(|ply| {
+        engine.set_position(&game.start_sfen, &history[..ply])?;
+        let (pv_infos, _) = engine.search()?;
+        let Some(pv) = pv_infos.into_iter().find(|pv| pv.multipv == 1) else {
+            // No line at all: the side to move is mated
+            return Some((Score::Mate(0), None));
+        };
+        let best = pv.moves.first().map(|mv| convert_move_files(mv));
+        Some((pv.score, best))
+    });
+
+    let mut before = searches.next().flatten();
+    let mut moves = Vec::with_capacity(game.moves.len());
+    for mv in &game.moves {
+        let after = searches.next().flatten();
+        let score = after.as_ref().map(|(score, _)| from_mover(*score));
+        let (best_score, best_move) = match before {
+            Some((best_score, best_move)) => (Some(best_score), best_move),
+            None => (None, None),
+        };
+        let loss = best_score
+            .zip(score)
+            .map(|(best, played)| best.rank().saturating_sub(played.rank()).max(0));
+        moves.push(AnnotatedMove {
+            mv: mv.clone(),
+            score,
+            best_move: best_move.filter(|best| best != mv),
+            loss,
+            judgement: loss.and_then(Judgement::of),
+        });
+        before = after;
+    }
+    AnnotatedGame { moves }
+}
+
+/// The score of a position, from the side to move, as the score of the move
+/// that led to it, from the side that played it.
+fn from_mover(score: Score) -> Score {
+    match score {
+        Score::Cp(cp) => Score::Cp(-cp),
+        // One ply further from the mate
+        Score::Mate(plies) if plies > 0 => Score::Mate(-plies - 1),
+        Score::Mate(plies) => Score::Mate(1 - plies),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shogi::wildcatshogi::{Move, Position};
+
+    use super::*;
+    use crate::engine::{PvInfo, SearchResult};
+    use crate::mock::MockEngine;
+    use crate::movegen::legal_moves;
+
+    const START: &str = "bkr/p1p/3/P1P/RKB b - 1";
+
+    fn game() -> Game {
+        Game {
+            start_sfen: START.to_string(),
+            moves: vec!["2e2d".to_string(), "2a2b".to_string()],
+            ..Game::default()
+        }
+    }
+
+    #[test]
+    fn test_blunder_is_marked() {
+        let mut engine = MockEngine::new([
+            // The engine prefers the bishop move, 3e2d in library convention
+            MockEngine::answer("1e2d", Score::Cp(30)),
+            MockEngine::answer("2a2b", Score::Cp(250)),
+            MockEngine::answer("2d2c", Score::Cp(-240)),
+        ]);
+        let annotated = replay_and_annotate(&mut engine, &game());
+        let [black, white] = &annotated.moves[..] else {
+            panic!("two moves were played");
+        };
+
+        assert_eq!(black.score, Some(Score::Cp(-250)));
+        assert_eq!(black.loss, Some(280));
+        assert_eq!(black.judgement, Some(Judgement::Blunder));
+        let best = black.best_move.as_deref().unwrap();
+        assert_eq!(best, "3e2d");
+        let start = Position::from_sfen(START).unwrap();
+        assert!(legal_moves(&start).contains(&Move::from_sfen(best).unwrap()));
+
+        // White played the engine's choice and lost little
+        assert_eq!(white.best_move, None);
+        assert_eq!(white.loss, Some(10));
+        assert_eq!(white.judgement, None);
+        assert_eq!(engine.positions[2], format!("{} 2e2d 2a2b", START));
+    }
+
+    #[test]
+    fn test_mate_scores_and_failed_search() {
+        let mut engine = MockEngine::new([
+            MockEngine::answer("2e2d", Score::Cp(0)),
+            MockEngine::answer("2a2b", Score::Mate(1)),
+            (Vec::<PvInfo>::new(), SearchResult::Checkmate),
+        ]);
+        let annotated = replay_and_annotate(&mut engine, &game());
+        // Black walked into a mate in 1
+        assert_eq!(annotated.moves[0].score, Some(Score::Mate(-2)));
+        assert_eq!(annotated.moves[0].judgement, Some(Judgement::Blunder));
+        assert_eq!(annotated.moves[1].score, Some(Score::Mate(1)));
+        assert_eq!(annotated.moves[1].loss, Some(0));
+
+        let mut engine = MockEngine::new([MockEngine::answer("2e2d", Score::Cp(0))]);
+        let annotated = replay_and_annotate(&mut engine, &game());
+        assert!(annotated.moves.iter().all(|mv| mv.score.is_none()));
+    }
+}
@@ -0,0 +1,51 @@
+//! Seed positions that games start from instead of the initial position.
+
+use std::fs;
+use std::path::Path;
+
+use shogi::wildcatshogi::Position;
+
+use crate::position_only_sfen;
+
+/// Read one SFEN per line, skipping blank lines and `#` comments.
+///
+/// Every position must parse; the error names the first line that does not.
+pub fn load_seed_positions(path: &Path) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read seeds file {}: {}", path.display(), e))?;
+    parse_seed_positions(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn parse_seed_positions(text: &str) -> Result<Vec<String>, String> {
+    let mut seeds = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let position = Position::from_sfen(line)
+            .map_err(|e| format!("line {}: invalid SFEN {:?}: {}", index + 1, line, e))?;
+        seeds.push(position_only_sfen(&position.to_sfen()));
+    }
+    if seeds.is_empty() {
+        return Err("no seed positions".to_string());
+    }
+    Ok(seeds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_seed_positions() {
+        let text = "# from club games\nbkr/p1p/3/P1P/RKB b - 1\n\nk2/PBR/3/p1p/rbK w - 12\n";
+        let seeds = parse_seed_positions(text).unwrap();
+        assert_eq!(seeds.len(), 2);
+        assert!(Position::from_sfen(&seeds[1]).is_ok());
+
+        let err = parse_seed_positions("bkr/p1p/3/P1P/RKB b - 1\nnot a position\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+        assert!(parse_seed_positions("# nothing here\n").is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! SFEN normalization for comparing positions.
+
+use shogi::wildcatshogi::Position;
+
+use crate::ensure_black_to_move;
+use crate::movegen::legal_moves;
+
+/// Hand pieces from most to least valuable, the usual SFEN order
+const HAND_ORDER: &str = "RBGSNLP";
+
+/// Canonical form of a position SFEN: single spaces, hand in canonical
+/// order and the move counter reset to 1.
+///
+/// Two SFENs of the same game state give the same string. A move list
+/// after the position is dropped.
+pub fn canonicalize(sfen: &str) -> String {
+    let mut fields = sfen
+        .split_whitespace()
+        .take_while(|field| *field != "moves");
+    let board = fields.next().unwrap_or_default();
+    let side = fields.next().unwrap_or("b");
+    let hand = fields.next().unwrap_or("-");
+    format!("{} {} {} 1", board, side, normalize_hand(hand))
+}
+
+/// Canonical form of a puzzle position: [`canonicalize`]d and turned so
+/// Black is to move, the form puzzles are stored in.
+///
+/// The hand is put in order again after the turn, which swaps its cases.
+pub fn normalize_tsume_sfen(sfen: &str) -> String {
+    canonicalize(&ensure_black_to_move(&canonicalize(sfen)))
+}
+
+/// `sfen` reflected left to right, each rank read from the other side; the
+/// side to move, hands and move counter are kept.
+///
+/// Shogi pieces move alike on either wing, so a puzzle and its reflection
+/// have the same solution, file for file reflected.
+pub fn reflect_files(sfen: &str) -> String {
+    let (board, rest) = sfen.split_once(' ').unwrap_or((sfen, ""));
+    let ranks: Vec<String> = board
+        .split('/')
+        .map(|rank| {
+            // One token per piece or run of empty squares, `+` kept with its piece
+            let mut squares: Vec<String> = Vec::new();
+            let mut promoted = false;
+            for c in rank.chars() {
+                if c == '+' {
+                    promoted = true;
+                    continue;
+                }
+                squares.push(if promoted {
+                    format!("+{}", c)
+                } else {
+                    c.to_string()
+                });
+                promoted = false;
+            }
+            squares.reverse();
+            squares.concat()
+        })
+        .collect();
+    let board = ranks.join("/");
+    if rest.is_empty() {
+        board
+    } else {
+        format!("{} {}", board, rest)
+    }
+}
+
+/// Whether `position`'s board is its own left-right reflection: file 2 on
+/// the axis, files 1 and 3 mirroring each other.
+///
+/// The library's `Position` belongs to the shogi-rs fork, so this is a
+/// function here rather than a method there.
+pub fn is_symmetric(position: &Position) -> bool {
+    let sfen = position.to_sfen();
+    let board = sfen.split_whitespace().next().unwrap_or_default();
+    reflect_files(board) == board
+}
+
+/// Check that `sfen` is a complete puzzle position: board, side to move,
+/// hand and move counter, parsed by the library, with Black to move.
+pub fn validate_puzzle_sfen(sfen: &str) -> Result<(), String> {
+    let fields: Vec<&str> = sfen.split_whitespace().collect();
+    if fields.len() != 4 {
+        return Err(format!("expected 4 fields, found {}", fields.len()));
+    }
+    if fields[1] != "b" {
+        return Err(format!("side to move is {}, not Black", fields[1]));
+    }
+    Position::from_sfen(sfen).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Whether the game is over in `sfen`: the side to move has no legal move,
+/// checkmated or not. An SFEN the library rejects is not terminal.
+///
+/// Puzzle positions are never terminal; the mate comes after their solution.
+pub fn is_terminal_position(sfen: &str) -> bool {
+    Position::from_sfen(sfen).is_ok_and(|position| legal_moves(&position).is_empty())
+}
+
+/// Hand section with Black's pieces before White's, each side ordered from
+/// most to least valuable and repeated pieces merged into one count.
+pub fn normalize_hand(hand: &str) -> String {
+    let mut counts: Vec<(char, u32)> = Vec::new();
+    let mut count = 0;
+    for c in hand.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            count = count * 10 + digit;
+        } else if c.is_ascii_alphabetic() {
+            match counts.iter_mut().find(|(piece, _)| *piece == c) {
+                Some((_, total)) => *total += count.max(1),
+                None => counts.push((c, count.max(1))),
+            }
+            count = 0;
+        }
+    }
+    if counts.is_empty() {
+        return "-".to_string();
+    }
+
+    let rank = |piece: char| {
+        let value = HAND_ORDER
+            .find(piece.to_ascii_uppercase())
+            .unwrap_or(HAND_ORDER.len());
+        (piece.is_ascii_lowercase(), value, piece)
+    };
+    counts.sort_by_key(|(piece, _)| rank(*piece));
+    counts
+        .iter()
+        .map(|(piece, count)| match count {
+            1 => piece.to_string(),
+            n => format!("{}{}", n, piece),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hand_order_does_not_matter() {
+        assert_eq!(
+            canonicalize("k2/3/3/3/2K b pP2Bb 7"),
+            canonicalize("k2/3/3/3/2K   b  2BPbp 1")
+        );
+        assert_eq!(normalize_hand("pP2Bb"), "2BPbp");
+        assert_eq!(normalize_hand("PP"), "2P");
+        assert_eq!(normalize_hand("-"), "-");
+    }
+
+    #[test]
+    fn test_canonicalize_is_idempotent_and_parses() {
+        let sfen = "bkr/p1p/3/P1P/RKB w pR 12 moves 1e2d";
+        let canonical = canonicalize(sfen);
+        assert_eq!(canonical, "bkr/p1p/3/P1P/RKB w Rp 1");
+        assert_eq!(canonicalize(&canonical), canonical);
+        assert!(Position::from_sfen(&canonical).is_ok());
+    }
+
+    #[test]
+    fn test_normalize_tsume_sfen() {
+        // White mates; turned round, the pawn in White's hand becomes Black's
+        let normalized = normalize_tsume_sfen("kBR/P1P/3/rbp/2K  w pB 12");
+        assert_eq!(normalized, "k2/PBR/3/p1p/rbK b Pb 1");
+        assert_eq!(normalize_tsume_sfen(&normalized), normalized);
+        assert!(validate_puzzle_sfen(&normalized).is_ok());
+
+        let black = "1k1/3/R2/3/K1B b gP2G 5 moves G*2b";
+        assert_eq!(normalize_tsume_sfen(black), "1k1/3/R2/3/K1B b 2GPg 1");
+    }
+
+    #[test]
+    fn test_reflect_files() {
+        assert_eq!(
+            reflect_files("k2/PBR/3/p1p/rbK b Pb 1"),
+            "2k/RBP/3/p1p/Kbr b Pb 1"
+        );
+        assert_eq!(reflect_files("1k+p/3/3/+R2/K1B"), "+pk1/3/3/2+R/B1K");
+        let sfen = "1k1/3/R2/3/K1B b G 1";
+        assert_eq!(reflect_files(&reflect_files(sfen)), sfen);
+    }
+
+    #[test]
+    fn test_is_symmetric() {
+        // Wild Cat Shogi's bishop and rook start on opposite wings
+        assert!(!is_symmetric(&Position::startpos()));
+        let kings = Position::from_sfen("1k1/3/3/3/1K1 b - 1").unwrap();
+        assert!(is_symmetric(&kings));
+        let pawns = Position::from_sfen("1k1/p1p/3/P1P/1K1 b - 1").unwrap();
+        assert!(is_symmetric(&pawns));
+        // One pawn stepped off its file
+        let stepped = Position::from_sfen("1k1/p1p/3/PP1/1K1 b - 1").unwrap();
+        assert!(!is_symmetric(&stepped));
+    }
+
+    #[test]
+    fn test_validate_puzzle_sfen() {
+        assert!(validate_puzzle_sfen("bkr/p1p/3/P1P/RKB b - 1").is_ok());
+        // A mirrored position that lost its hand field
+        assert!(validate_puzzle_sfen("bkr/p1p/3/P1P/RKB b 1").is_err());
+        assert!(validate_puzzle_sfen("bkr/p1p/3/P1P/RKB w - 1").is_err());
+        assert!(validate_puzzle_sfen("bkr/p1p/3/P1P/R1B b - 1").is_err());
+    }
+
+    #[test]
+    fn test_is_terminal_position() {
+        // The puzzle 1k1/3/1R1/3/K1B b G 1 after its solution G*2b
+        assert!(is_terminal_position("1k1/1G1/1R1/3/K1B w - 2"));
+        assert!(!is_terminal_position("1k1/3/1R1/3/K1B b G 1"));
+        assert!(!is_terminal_position("bkr/p1p/3/P1P/RKB b - 1"));
+        assert!(!is_terminal_position("not a position"));
+    }
+}
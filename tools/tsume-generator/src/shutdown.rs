@@ -0,0 +1,27 @@
+//! Graceful shutdown on Ctrl-C and SIGTERM.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Exit status of a run stopped by a signal (128 + SIGINT)
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Install the signal handler.
+///
+/// The first signal asks the generation loop to stop after abandoning the
+/// game in progress; a second one exits immediately.
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            eprintln!("Forced exit");
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        eprintln!("Interrupted: finishing up (press Ctrl-C again to force exit)");
+    })
+}
+
+/// Whether a shutdown has been requested.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
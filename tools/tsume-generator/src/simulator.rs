@@ -0,0 +1,803 @@
+//! Engine-driven game simulation.
+
+use std::collections::{HashMap, VecDeque};
+
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::{RngCore, SeedableRng};
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::engine::{Engine, EngineInterface, SEARCH_TIME_MS, Score, SearchResult, Strength};
+use crate::material::count_material;
+use crate::movegen::legal_moves;
+use crate::output::position_hash;
+use crate::{convert_move_files, ensure_black_to_move, position_only_sfen, shutdown};
+
+/// Default limit on plies per game before giving up
+pub const MAX_MOVES: usize = 300;
+/// Material, board and hand, below which a side is in the endgame: each
+/// side starts with 1100
+pub const ENDGAME_MATERIAL: i32 = 800;
+/// Ply from which a game is in the endgame whatever the material
+pub const ENDGAME_PLY: usize = 60;
+
+/// How a side picks its moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// The engine's best move
+    Best,
+    /// The lowest-scoring of the engine's MultiPV candidates
+    Worst,
+    /// A uniformly random legal move, without the engine
+    ///
+    /// The choice depends only on the seed and the position.
+    Random(u64),
+    /// The lowest-scoring of the engine's top N candidates
+    MultiPvWorst(usize),
+    /// The move a strength-limited engine plays
+    Weakened(Strength),
+    /// One of the engine's MultiPV candidates, sampled with weight
+    /// `exp(-score / temperature)` (centipawns)
+    ///
+    /// Sampling uses the simulator's random number generator; without one, or
+    /// at temperature 0, this plays like `Worst`.
+    SampledWorst(u32),
+}
+
+/// Pick a move for the side to move in `position`.
+///
+/// `Random` needs no engine; the other strategies search `position` with
+/// `engine` and return `None` without one. `None` is also returned when the
+/// side to move has no move.
+pub fn select_move<E: EngineInterface>(
+    engine: Option<&mut E>,
+    position: &Position,
+    strategy: &Strategy,
+) -> Option<Move> {
+    let sfen = position_only_sfen(&position.to_sfen());
+    if let Strategy::Random(seed) = strategy {
+        return random_move(position, &sfen, *seed);
+    }
+
+    let engine = engine?;
+    engine.set_position(&sfen, &[])?;
+    match engine_move(engine, *strategy, None)? {
+        SearchResult::Move(mv) => Move::from_sfen(&convert_move_files(&mv)),
+        SearchResult::Checkmate | SearchResult::Resign => None,
+    }
+}
+
+/// Uniformly random legal move, seeded by `seed` and the position.
+fn random_move(position: &Position, sfen: &str, seed: u64) -> Option<Move> {
+    let mut rng = StdRng::seed_from_u64(seed ^ position_hash(sfen));
+    legal_moves(position).choose(&mut rng).copied()
+}
+
+/// Search the engine's current position with an engine-backed strategy.
+fn engine_move<E: EngineInterface>(
+    engine: &mut E,
+    strategy: Strategy,
+    rng: Option<&mut dyn RngCore>,
+) -> Option<SearchResult> {
+    let strength = match strategy {
+        Strategy::Weakened(strength) => Some(strength),
+        _ => None,
+    };
+    engine.set_strength(strength)?;
+    // Only the worst-move strategies look beyond the first PV, and MultiPV slows the search
+    let multipv = match strategy {
+        Strategy::Best | Strategy::Weakened(_) => 1,
+        Strategy::MultiPvWorst(k) => k,
+        _ => engine.weak_multipv(),
+    };
+    engine.set_multipv(multipv)?;
+
+    match strategy {
+        Strategy::Best => engine.get_best_move(),
+        Strategy::Worst => engine.get_worst_move(),
+        Strategy::MultiPvWorst(k) => engine.get_worst_move_among(k),
+        Strategy::Weakened(_) => engine.get_engine_move(),
+        Strategy::SampledWorst(temperature) => match rng {
+            Some(mut rng) => engine.get_sampled_worst_move(temperature, &mut rng),
+            None => engine.get_worst_move(),
+        },
+        Strategy::Random(_) => unreachable!("random moves are chosen without the engine"),
+    }
+}
+
+/// Search time of each move of a game, longer once the game reaches the
+/// endgame, where short searches miss mates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchBudget {
+    /// Time of every move before the endgame
+    pub search_ms: u64,
+    /// Time of every move from the endgame on; `search_ms` throughout if `None`
+    pub endgame_ms: Option<u64>,
+    /// The endgame starts once either side's material is below this
+    pub endgame_material: i32,
+    /// ... or at this ply
+    pub endgame_ply: usize,
+}
+
+impl Default for SearchBudget {
+    fn default() -> Self {
+        SearchBudget {
+            search_ms: SEARCH_TIME_MS,
+            endgame_ms: None,
+            endgame_material: ENDGAME_MATERIAL,
+            endgame_ply: ENDGAME_PLY,
+        }
+    }
+}
+
+impl SearchBudget {
+    /// Search time of the move played at `ply` in `position`.
+    ///
+    /// The endgame never gets less than `search_ms`, which may have been
+    /// raised above it by escalation.
+    pub fn for_move(&self, position: &Position, ply: usize) -> u64 {
+        let Some(endgame_ms) = self.endgame_ms else {
+            return self.search_ms;
+        };
+        let (black, white) = count_material(position);
+        if ply >= self.endgame_ply || black.min(white) < self.endgame_material {
+            endgame_ms.max(self.search_ms)
+        } else {
+            self.search_ms
+        }
+    }
+}
+
+/// Candidate tsume extracted from a finished game
+pub struct Tsume {
+    /// Position before the mating move, Black to move
+    pub sfen: String,
+    /// The same position as it occurred in the game, before any flip
+    pub game_sfen: String,
+    /// Number of plies played before the puzzle position
+    pub ply: usize,
+    /// Ply of the game on which the mating move was played
+    pub mate_ply: usize,
+}
+
+/// Result of a single game simulation
+pub enum GameResult {
+    /// Game ended in checkmate
+    Checkmate(Tsume),
+    /// Game did not end within move limit
+    NoResult,
+    /// Error during simulation
+    Error(SimulationError),
+}
+
+/// Why a simulation stopped with [`GameResult::Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimulationError {
+    /// The engine hung, exited or refused the position
+    Engine,
+    /// The engine's move (Fairy-Stockfish convention) did not parse or was
+    /// illegal in the library at `sfen`
+    BadMove { sfen: String, engine_move: String },
+    /// The side to move at `sfen`, where the game started, was already mated
+    MatedAtStart { sfen: String },
+}
+
+/// A finished simulation with its game record.
+pub struct SimulationResult {
+    pub result: GameResult,
+    /// Position the game started from
+    pub start_sfen: String,
+    /// Moves played, in library convention
+    pub moves: Vec<String>,
+    /// Plies played before the game ended
+    pub plies: usize,
+    /// Score of the engine's principal variation before each search, if recorded
+    pub scores: Vec<Option<Score>>,
+    /// Search time given each move, with a [`SearchBudget`]
+    pub search_ms: Vec<u64>,
+    /// Why the game was abandoned as stuck, if it was
+    pub stuck: Option<Stuck>,
+}
+
+/// Why a game was abandoned without a result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stuck {
+    /// A position came up a third time, on its way to sennichite
+    Repetition,
+    /// Nothing irreversible happened for the configured number of plies
+    NoProgress,
+    /// The opening guard refused the game's opening line
+    OpeningReuse,
+}
+
+/// Decides from the start position and the opening moves whether a game may
+/// go on; see [`GameSimulator::opening_guard`].
+pub type OpeningGuard<'a> = &'a mut dyn FnMut(&str, &[Move]) -> bool;
+
+/// Plays one engine-driven game per `run` with configurable move selection.
+pub struct GameSimulator<'a, E: EngineInterface = Engine> {
+    engine: &'a mut E,
+    white_engine: Option<&'a mut E>,
+    black_strategy: Strategy,
+    white_strategy: Strategy,
+    max_moves: usize,
+    initial_position: Position,
+    record_scores: bool,
+    random_plies: usize,
+    plies_before_mate: usize,
+    max_quiet_plies: Option<usize>,
+    search_budget: Option<SearchBudget>,
+    rng: Option<&'a mut dyn RngCore>,
+    opening_guard: Option<(usize, OpeningGuard<'a>)>,
+}
+
+impl<'a, E: EngineInterface> GameSimulator<'a, E> {
+    /// Black plays best and White worst from the starting position.
+    pub fn new(engine: &'a mut E) -> Self {
+        GameSimulator {
+            engine,
+            white_engine: None,
+            black_strategy: Strategy::Best,
+            white_strategy: Strategy::Worst,
+            max_moves: MAX_MOVES,
+            initial_position: Position::startpos(),
+            record_scores: false,
+            random_plies: 0,
+            plies_before_mate: 1,
+            max_quiet_plies: None,
+            search_budget: None,
+            rng: None,
+            opening_guard: None,
+        }
+    }
+
+    /// Search White's moves on a second engine; `None` plays both sides on one.
+    ///
+    /// Each engine keeps its own options and only sees the positions it moves in.
+    pub fn white_engine(mut self, engine: Option<&'a mut E>) -> Self {
+        self.white_engine = engine;
+        self
+    }
+
+    pub fn black_strategy(mut self, strategy: Strategy) -> Self {
+        self.black_strategy = strategy;
+        self
+    }
+
+    pub fn white_strategy(mut self, strategy: Strategy) -> Self {
+        self.white_strategy = strategy;
+        self
+    }
+
+    pub fn max_moves(mut self, max_moves: usize) -> Self {
+        self.max_moves = max_moves;
+        self
+    }
+
+    pub fn initial_position(mut self, position: Position) -> Self {
+        self.initial_position = position;
+        self
+    }
+
+    /// Keep the engine's score before every move in the result.
+    pub fn record_scores(mut self, enabled: bool) -> Self {
+        self.record_scores = enabled;
+        self
+    }
+
+    /// Sample the first `plies` moves uniformly from the engine's MultiPV candidates.
+    pub fn random_opening(mut self, plies: usize, rng: &'a mut dyn RngCore) -> Self {
+        self.random_plies = plies;
+        self.rng = Some(rng);
+        self
+    }
+
+    /// Emit the position this many attacker moves before the mate (at least 1).
+    ///
+    /// The game is not checked for a forced mate from that position; verify it.
+    pub fn plies_before_mate(mut self, moves: usize) -> Self {
+        self.plies_before_mate = moves.max(1);
+        self
+    }
+
+    /// Abandon the game after this many plies without a capture, drop or pawn move.
+    pub fn max_quiet_plies(mut self, plies: Option<usize>) -> Self {
+        self.max_quiet_plies = plies;
+        self
+    }
+
+    /// Set the engine's search time before every move from `budget`;
+    /// without one the engines keep the time they have.
+    pub fn search_budget(mut self, budget: SearchBudget) -> Self {
+        self.search_budget = Some(budget);
+        self
+    }
+
+    /// Once `plies` moves are played, abandon the game as
+    /// [`Stuck::OpeningReuse`] unless `admit` accepts the start position and
+    /// those moves.
+    pub fn opening_guard(mut self, plies: usize, admit: OpeningGuard<'a>) -> Self {
+        self.opening_guard = Some((plies, admit));
+        self
+    }
+
+    pub fn run(&mut self) -> SimulationResult {
+        let mut position = self.initial_position.clone();
+        let start_sfen = position_only_sfen(&position.to_sfen());
+        // Moves in library form; each engine gets them in its own convention
+        let mut move_history: Vec<Move> = Vec::new();
+        let mut is_black_turn = start_sfen.split_whitespace().nth(1) != Some("w");
+        // Positions before the last moves, enough to step back to the puzzle position
+        let keep = 2 * self.plies_before_mate - 1;
+        let mut recent: VecDeque<(String, usize)> = VecDeque::with_capacity(keep + 1);
+        let mut scores = Vec::new();
+        let mut search_ms = Vec::new();
+        let mut occurrences: HashMap<u64, usize> = HashMap::new();
+        let mut quiet_plies = 0;
+        let mut stuck = None;
+
+        let result = 'game: {
+            for _move_num in 0..self.max_moves {
+                // Abandon the game rather than finish it once a shutdown is requested
+                if shutdown::requested() {
+                    break 'game GameResult::NoResult;
+                }
+                if let Some((plies, admit)) = self.opening_guard.as_mut()
+                    && move_history.len() == *plies
+                    && !admit(&start_sfen, &move_history)
+                {
+                    stuck = Some(Stuck::OpeningReuse);
+                    break 'game GameResult::NoResult;
+                }
+                let current_sfen = position_only_sfen(&position.to_sfen());
+
+                // Shuffling games would otherwise run to the move limit
+                let seen = occurrences.entry(position_hash(&current_sfen)).or_default();
+                *seen += 1;
+                if *seen >= 3 {
+                    stuck = Some(Stuck::Repetition);
+                } else if self.max_quiet_plies.is_some_and(|max| quiet_plies >= max) {
+                    stuck = Some(Stuck::NoProgress);
+                }
+                if stuck.is_some() {
+                    break 'game GameResult::NoResult;
+                }
+
+                let engine: &mut E = match self.white_engine.as_deref_mut() {
+                    Some(white_engine) if !is_black_turn => white_engine,
+                    _ => &mut *self.engine,
+                };
+                let engine_history: Vec<String> = move_history
+                    .iter()
+                    .map(|mv| convert_move_files(&mv.to_string()))
+                    .collect();
+                if engine.set_position(&start_sfen, &engine_history).is_none() {
+                    break 'game GameResult::Error(SimulationError::Engine);
+                }
+                if let Some(budget) = &self.search_budget {
+                    let ms = budget.for_move(&position, move_history.len());
+                    engine.set_search_ms(ms);
+                    search_ms.push(ms);
+                }
+
+                // Black (sente) plays best, White (gote) plays worst -> Black will checkmate White
+                let strategy = if is_black_turn {
+                    self.black_strategy
+                } else {
+                    self.white_strategy
+                };
+                let (result, score) = match (self.rng.as_mut(), strategy) {
+                    (Some(rng), _) if move_history.len() < self.random_plies => {
+                        let k = engine.weak_multipv();
+                        let result = engine
+                            .set_multipv(k)
+                            .and_then(|_| engine.get_random_move(rng));
+                        (result, engine.last_score())
+                    }
+                    (_, Strategy::Random(seed)) => {
+                        let mv = random_move(&position, &current_sfen, seed);
+                        let result =
+                            mv.map(|mv| SearchResult::Move(convert_move_files(&mv.to_string())));
+                        (result, None)
+                    }
+                    (rng, strategy) => {
+                        let rng = rng.map(|rng| &mut **rng as &mut dyn RngCore);
+                        let result = engine_move(engine, strategy, rng);
+                        (result, engine.last_score())
+                    }
+                };
+                if self.record_scores {
+                    scores.push(score);
+                }
+
+                let result = match result {
+                    Some(r) => r,
+                    // A hung or dead engine also returns nothing; that is not a checkmate
+                    None if engine.failure().is_some() => {
+                        break 'game GameResult::Error(SimulationError::Engine);
+                    }
+                    None => {
+                        // No legal moves = loss in shogi (no stalemate)
+                        break 'game mated(&recent, keep, move_history.len(), &current_sfen);
+                    }
+                };
+
+                match result {
+                    SearchResult::Move(chosen_move) => {
+                        // Save position BEFORE this move (for tsume: position before checkmate)
+                        recent.push_back((current_sfen.clone(), move_history.len()));
+                        if recent.len() > keep {
+                            recent.pop_front();
+                        }
+
+                        let converted_move = convert_move_files(&chosen_move);
+                        let Some(played) = Move::from_sfen(&converted_move)
+                            .filter(|mv| position.make_move(*mv).is_ok())
+                        else {
+                            break 'game GameResult::Error(SimulationError::BadMove {
+                                sfen: current_sfen,
+                                engine_move: chosen_move,
+                            });
+                        };
+                        if is_irreversible(&current_sfen, &position.to_sfen()) {
+                            quiet_plies = 0;
+                        } else {
+                            quiet_plies += 1;
+                        }
+
+                        move_history.push(played);
+                        is_black_turn = !is_black_turn;
+                    }
+                    SearchResult::Checkmate => {
+                        break 'game mated(&recent, keep, move_history.len(), &current_sfen);
+                    }
+                    SearchResult::Resign => {
+                        // Should not reach here - get_best_move/get_worst_move return None instead
+                        unreachable!(
+                            "Resign should be handled by returning None from move functions"
+                        );
+                    }
+                }
+            }
+
+            GameResult::NoResult
+        };
+
+        SimulationResult {
+            result,
+            plies: move_history.len(),
+            moves: move_history.iter().map(Move::to_string).collect(),
+            start_sfen,
+            scores,
+            search_ms,
+            stuck,
+        }
+    }
+}
+
+/// Whether the move from `before` to `after` was a capture, a drop or a pawn move.
+///
+/// Captures and drops change the hands; pawn moves and promotions change
+/// where the pawns stand.
+fn is_irreversible(before: &str, after: &str) -> bool {
+    fn field(sfen: &str, index: usize) -> &str {
+        sfen.split_whitespace().nth(index).unwrap_or_default()
+    }
+    field(before, 2) != field(after, 2)
+        || pawn_squares(field(before, 0)) != pawn_squares(field(after, 0))
+}
+
+/// Board indices of unpromoted pawns of either side.
+fn pawn_squares(board: &str) -> Vec<(usize, char)> {
+    let mut squares = Vec::new();
+    let mut index = 0;
+    let mut promoted = false;
+    for c in board.chars().filter(|c| *c != '/') {
+        match c {
+            '+' => promoted = true,
+            c if c.is_ascii_digit() => index += c.to_digit(10).unwrap_or(0) as usize,
+            c => {
+                if !promoted && c.eq_ignore_ascii_case(&'p') {
+                    squares.push((index, c));
+                }
+                promoted = false;
+                index += 1;
+            }
+        }
+    }
+    squares
+}
+
+/// Tsume from the oldest kept position, `keep` plies before the mate.
+///
+/// A game shorter than that has no such position and yields no result. A
+/// game mated before its first move, at `sfen`, is an error: its start
+/// position, from a seed or a variant's setup, is broken.
+fn mated(
+    recent: &VecDeque<(String, usize)>,
+    keep: usize,
+    mate_ply: usize,
+    sfen: &str,
+) -> GameResult {
+    if mate_ply == 0 {
+        return GameResult::Error(SimulationError::MatedAtStart {
+            sfen: sfen.to_string(),
+        });
+    }
+    match recent.front() {
+        Some((sfen, ply)) if recent.len() == keep => {
+            // If White wins (Black lost), flip the board so Black is the attacker
+            GameResult::Checkmate(Tsume {
+                sfen: ensure_black_to_move(sfen),
+                game_sfen: sfen.clone(),
+                ply: *ply,
+                mate_ply,
+            })
+        }
+        _ => GameResult::NoResult,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::variant::Variant;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    /// Mock engine whose White candidates include a blunder that ends the game.
+    fn blunder_engine() -> Engine {
+        Engine::spawn(&EngineConfig {
+            engine: fixture("blunder-engine.sh"),
+            variants_ini: fixture("variants.ini"),
+            variant: Variant::Wildcatshogi,
+            ..EngineConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Mock engine offering three candidates from every position.
+    fn candidates_engine() -> Engine {
+        Engine::spawn(&EngineConfig {
+            engine: fixture("candidates-engine.sh"),
+            variants_ini: fixture("variants.ini"),
+            variant: Variant::Wildcatshogi,
+            ..EngineConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Mock engine whose kings shuffle back and forth forever.
+    fn shuffle_engine() -> Engine {
+        Engine::spawn(&EngineConfig {
+            engine: fixture("shuffle-engine.sh"),
+            variants_ini: fixture("variants.ini"),
+            variant: Variant::Wildcatshogi,
+            ..EngineConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Library move for an engine (Fairy-Stockfish) move string.
+    fn engine_move_text(mv: &str) -> Option<Move> {
+        Move::from_sfen(&convert_move_files(mv))
+    }
+
+    #[test]
+    fn test_select_move_engine_strategies() {
+        let mut engine = candidates_engine();
+        let start = Position::startpos();
+        let mut select = |strategy| select_move(Some(&mut engine), &start, &strategy);
+        assert_eq!(select(Strategy::Best), engine_move_text("3d3c"));
+        assert_eq!(select(Strategy::Worst), engine_move_text("2e2d"));
+        assert_eq!(select(Strategy::MultiPvWorst(2)), engine_move_text("1d1c"));
+        engine.quit();
+
+        assert_eq!(
+            select_move(None::<&mut Engine>, &start, &Strategy::Best),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sampled_worst_move_temperature() {
+        let mut engine = candidates_engine();
+        engine
+            .set_sfen(Variant::Wildcatshogi.starting_sfen())
+            .unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(
+            engine.get_sampled_worst_move(0, &mut rng),
+            engine.get_worst_move()
+        );
+
+        let sampled: HashSet<_> = (0..30)
+            .filter_map(|_| engine.get_sampled_worst_move(300, &mut rng))
+            .collect();
+        assert!(sampled.len() > 1);
+        engine.quit();
+    }
+
+    #[test]
+    fn test_select_move_random_needs_no_engine() {
+        let start = Position::startpos();
+        let no_engine = || None::<&mut Engine>;
+        let mv = select_move(no_engine(), &start, &Strategy::Random(7)).unwrap();
+        assert!(crate::movegen::legal_moves(&start).contains(&mv));
+        assert_eq!(
+            select_move(no_engine(), &start, &Strategy::Random(7)),
+            Some(mv)
+        );
+    }
+
+    fn average_plies(simulator: &mut GameSimulator, games: usize) -> f64 {
+        let total: usize = (0..games).map(|_| simulator.run().plies).sum();
+        total as f64 / games as f64
+    }
+
+    #[test]
+    fn test_worst_white_shortens_games() {
+        let mut engine = blunder_engine();
+        let weak_white = average_plies(&mut GameSimulator::new(&mut engine), 3);
+        let strong_white = average_plies(
+            &mut GameSimulator::new(&mut engine).white_strategy(Strategy::Best),
+            3,
+        );
+        assert!(weak_white < strong_white);
+        engine.quit();
+    }
+
+    #[test]
+    fn test_checkmate_carries_mate_ply() {
+        let mut engine = blunder_engine();
+        let result = GameSimulator::new(&mut engine).run();
+        let GameResult::Checkmate(tsume) = result.result else {
+            panic!("weak White should get mated");
+        };
+        assert_eq!(tsume.mate_ply, result.plies);
+        assert_eq!(tsume.mate_ply, tsume.ply + 1);
+        assert_eq!(result.start_sfen, Variant::Wildcatshogi.starting_sfen());
+        // Black's king step and White's pawn push, in library convention
+        assert_eq!(result.moves, vec!["2e2d", "3b3c"]);
+        engine.quit();
+    }
+
+    #[test]
+    fn test_plies_before_mate_steps_back() {
+        let mut engine = blunder_engine();
+        let result = GameSimulator::new(&mut engine)
+            .white_strategy(Strategy::Best)
+            .plies_before_mate(2)
+            .run();
+        let GameResult::Checkmate(tsume) = result.result else {
+            panic!("the game should end in mate after six plies");
+        };
+        assert_eq!(tsume.ply + 3, tsume.mate_ply);
+        assert!(tsume.sfen.contains(" b "));
+
+        // Mated after two plies: too short to step back two attacker moves
+        let result = GameSimulator::new(&mut engine).plies_before_mate(2).run();
+        assert!(matches!(result.result, GameResult::NoResult));
+        engine.quit();
+    }
+
+    #[test]
+    fn test_multipv_per_side() {
+        let mut engine = Engine::spawn(&EngineConfig {
+            engine: fixture("multipv-engine.sh"),
+            variants_ini: fixture("variants.ini"),
+            variant: Variant::Wildcatshogi,
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        let position = Position::startpos();
+
+        let mv = select_move(Some(&mut engine), &position, &Strategy::Best).unwrap();
+        assert_eq!(mv.to_string(), "2e2d");
+        assert_eq!(engine.last_score(), Some(Score::Cp(900)));
+
+        engine.set_weak_multipv(2);
+        let mv = select_move(Some(&mut engine), &position, &Strategy::Worst).unwrap();
+        assert_eq!(mv, engine_move_text("3d3c").unwrap());
+        assert_eq!(engine.last_score(), Some(Score::Cp(1900)));
+        engine.quit();
+    }
+
+    #[test]
+    fn test_shuffling_game_is_abandoned_as_stuck() {
+        let mut engine = shuffle_engine();
+        // The start position comes up again after plies 4 and 8
+        let result = GameSimulator::new(&mut engine).max_moves(100).run();
+        assert!(matches!(result.result, GameResult::NoResult));
+        assert_eq!(result.stuck, Some(Stuck::Repetition));
+        assert_eq!(result.plies, 8);
+
+        let result = GameSimulator::new(&mut engine)
+            .max_quiet_plies(Some(3))
+            .run();
+        assert_eq!(result.stuck, Some(Stuck::NoProgress));
+        assert_eq!(result.plies, 3);
+
+        let result = GameSimulator::new(&mut engine).max_moves(5).run();
+        assert_eq!(result.stuck, None);
+        engine.quit();
+    }
+
+    #[test]
+    fn test_opening_guard() {
+        let mut engine = shuffle_engine();
+        let mut openings = Vec::new();
+        let mut admit = |start: &str, moves: &[Move]| {
+            openings.push(format!("{} {:?}", start, moves));
+            openings.len() < 2
+        };
+        let result = GameSimulator::new(&mut engine)
+            .max_moves(5)
+            .opening_guard(2, &mut admit)
+            .run();
+        assert_eq!(result.stuck, None);
+        assert_eq!(result.plies, 5);
+
+        let result = GameSimulator::new(&mut engine)
+            .max_moves(5)
+            .opening_guard(2, &mut admit)
+            .run();
+        assert!(matches!(result.result, GameResult::NoResult));
+        assert_eq!(result.stuck, Some(Stuck::OpeningReuse));
+        assert_eq!(result.plies, 2);
+        engine.quit();
+        // Both games played the same opening
+        assert_eq!(openings[0], openings[1]);
+    }
+
+    #[test]
+    fn test_white_moves_on_its_own_engine() {
+        let mut engine = blunder_engine();
+        let mut white_engine = shuffle_engine();
+        // White's engine offers no blunder, so the game runs until Black resigns
+        let result = GameSimulator::new(&mut engine)
+            .white_engine(Some(&mut white_engine))
+            .run();
+        assert_eq!(result.plies, 6);
+        assert_eq!(
+            result.moves[1],
+            engine_move_text("2a2b").unwrap().to_string()
+        );
+        engine.quit();
+        white_engine.quit();
+    }
+
+    #[test]
+    fn test_is_irreversible() {
+        let start = "bkr/p1p/3/P1P/RKB b - 1";
+        assert!(!is_irreversible(start, "bkr/p1p/3/PKP/R1B w - 2"));
+        assert!(is_irreversible(start, "bkr/p1p/P2/2P/RKB w - 2"));
+        assert!(is_irreversible(start, "bkr/p1p/3/P1P/RKB w P 2"));
+        assert!(is_irreversible(
+            "bkr/P1p/3/2P/RKB b - 1",
+            "b+Pr/2p/3/2P/RKB w - 2"
+        ));
+    }
+
+    #[test]
+    fn test_record_scores_and_max_moves() {
+        let mut engine = blunder_engine();
+        let result = GameSimulator::new(&mut engine)
+            .white_strategy(Strategy::Best)
+            .max_moves(3)
+            .record_scores(true)
+            .run();
+        assert!(matches!(result.result, GameResult::NoResult));
+        assert_eq!(result.plies, 3);
+        assert_eq!(result.scores, vec![Some(Score::Cp(0)); 3]);
+        engine.quit();
+    }
+}
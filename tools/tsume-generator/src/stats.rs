@@ -0,0 +1,474 @@
+//! Run statistics printed at the end of generation.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::generator::GenerationMode;
+use crate::output::{OutputTarget, position_hash};
+use crate::simulator::{GameResult, SimulationResult};
+use crate::themes::Theme;
+use crate::timings::Timings;
+
+/// Counters for accepted and rejected candidates over a whole run.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunStats {
+    /// How the games were played, which sets the expected yield
+    pub mode: GenerationMode,
+    pub accepted: usize,
+    /// Candidates the engine could not prove to be a forced mate
+    pub rejected_unverified: usize,
+    /// Verified candidates outside the `--min-mate`/`--max-mate` range
+    pub rejected_mate_length: usize,
+    /// Verified candidates with more than one mating first move
+    pub rejected_multiple_solutions: usize,
+    /// Verified candidates the defender escaped with a longer search
+    pub rejected_defended: usize,
+    /// Verified candidates whose `--strictly-forced` walk ran out of time
+    pub rejected_out_of_time: usize,
+    /// Rated candidates outside the `--min-difficulty`/`--max-difficulty` range
+    pub rejected_difficulty: usize,
+    /// Candidates refused by a puzzle filter, before or after verification
+    pub rejected_filtered: usize,
+    /// Candidates whose position failed to parse or had White to move
+    pub rejected_malformed: usize,
+    /// Candidates whose position was already written in this run
+    pub duplicates: usize,
+    /// Engines replaced after hanging or crashing
+    pub engine_restarts: usize,
+    /// Games abandoned on repetition or without progress
+    pub stuck_games: usize,
+    /// Stuck games abandoned on a repeated position
+    pub repetition_games: usize,
+    /// Games abandoned by the opening guard for an opening played too often
+    pub opening_reuse_games: usize,
+    /// Recorded games taken candidates from, when mining
+    pub games_mined: usize,
+    /// Raises of the move search time after games without a mate
+    pub escalations: usize,
+    /// Candidates found while the search time was raised
+    pub escalated_candidates: usize,
+    accepted_mates: BTreeMap<i32, usize>,
+    rejected_mates: BTreeMap<i32, usize>,
+    /// Accepted puzzles per theme tag
+    themes: BTreeMap<Theme, usize>,
+    /// Every game played, whatever its outcome
+    pub games: GameStats,
+    #[serde(skip)]
+    pub timings: Timings,
+}
+
+impl RunStats {
+    pub fn record_accepted(&mut self, mate: Option<i32>) {
+        self.accepted += 1;
+        if let Some(mate) = mate {
+            *self.accepted_mates.entry(mate).or_default() += 1;
+        }
+    }
+
+    pub fn record_themes(&mut self, themes: &[Theme]) {
+        for theme in themes {
+            *self.themes.entry(*theme).or_default() += 1;
+        }
+    }
+
+    pub fn record_unverified(&mut self) {
+        self.rejected_unverified += 1;
+    }
+
+    pub fn record_mate_length_rejected(&mut self, mate: i32) {
+        self.rejected_mate_length += 1;
+        *self.rejected_mates.entry(mate).or_default() += 1;
+    }
+
+    pub fn record_multiple_solutions(&mut self) {
+        self.rejected_multiple_solutions += 1;
+    }
+
+    pub fn record_defended(&mut self) {
+        self.rejected_defended += 1;
+    }
+
+    pub fn record_out_of_time(&mut self) {
+        self.rejected_out_of_time += 1;
+    }
+
+    pub fn record_difficulty_rejected(&mut self) {
+        self.rejected_difficulty += 1;
+    }
+
+    pub fn record_filtered(&mut self) {
+        self.rejected_filtered += 1;
+    }
+
+    pub fn record_malformed(&mut self) {
+        self.rejected_malformed += 1;
+    }
+
+    pub fn record_duplicate(&mut self) {
+        self.duplicates += 1;
+    }
+
+    /// Add the counters of another worker's run.
+    pub fn merge(&mut self, other: RunStats) {
+        self.accepted += other.accepted;
+        self.rejected_unverified += other.rejected_unverified;
+        self.rejected_mate_length += other.rejected_mate_length;
+        self.rejected_multiple_solutions += other.rejected_multiple_solutions;
+        self.rejected_defended += other.rejected_defended;
+        self.rejected_out_of_time += other.rejected_out_of_time;
+        self.rejected_difficulty += other.rejected_difficulty;
+        self.rejected_filtered += other.rejected_filtered;
+        self.rejected_malformed += other.rejected_malformed;
+        self.duplicates += other.duplicates;
+        self.engine_restarts += other.engine_restarts;
+        self.stuck_games += other.stuck_games;
+        self.repetition_games += other.repetition_games;
+        self.opening_reuse_games += other.opening_reuse_games;
+        self.games_mined += other.games_mined;
+        self.escalations += other.escalations;
+        self.escalated_candidates += other.escalated_candidates;
+        for (mate, count) in other.accepted_mates {
+            *self.accepted_mates.entry(mate).or_default() += count;
+        }
+        for (mate, count) in other.rejected_mates {
+            *self.rejected_mates.entry(mate).or_default() += count;
+        }
+        for (theme, count) in other.themes {
+            *self.themes.entry(theme).or_default() += count;
+        }
+        self.games.merge(other.games);
+        self.timings.merge(other.timings);
+    }
+
+    /// Candidates examined, whether accepted, rejected or duplicate.
+    pub fn candidates(&self) -> usize {
+        self.accepted
+            + self.rejected_unverified
+            + self.rejected_mate_length
+            + self.rejected_multiple_solutions
+            + self.rejected_defended
+            + self.rejected_out_of_time
+            + self.rejected_difficulty
+            + self.rejected_filtered
+            + self.rejected_malformed
+            + self.duplicates
+    }
+
+    /// Share of all candidates that were accepted.
+    pub fn acceptance_rate(&self) -> f64 {
+        let total = self.candidates();
+        if total == 0 {
+            return 0.0;
+        }
+        self.accepted as f64 / total as f64
+    }
+
+    /// Share of candidates surviving the duplicate check that were new positions.
+    pub fn unique_yield(&self) -> f64 {
+        let total = self.accepted + self.duplicates;
+        if total == 0 {
+            return 0.0;
+        }
+        self.accepted as f64 / total as f64
+    }
+
+    /// Accepted puzzles per game played, or mined, the figure that tells the
+    /// modes apart.
+    pub fn puzzles_per_game(&self) -> f64 {
+        let games = match self.mode {
+            GenerationMode::Mined => self.games_mined,
+            _ => self.games.total_games,
+        };
+        if games == 0 {
+            return 0.0;
+        }
+        self.accepted as f64 / games as f64
+    }
+
+    pub fn print_summary(&self, output: &OutputTarget) {
+        eprintln!("Done: {} -> {}", self.accepted, output);
+        match self.mode {
+            GenerationMode::Mined => eprintln!(
+                "  yield: {:.1} puzzles per 1000 games mined",
+                self.puzzles_per_game() * 1000.0
+            ),
+            _ => eprintln!(
+                "  yield: {:.1} puzzles per 100 games ({} mode)",
+                self.puzzles_per_game() * 100.0,
+                self.mode
+            ),
+        }
+        eprintln!(
+            "  acceptance rate: {:.1}% of candidates",
+            self.acceptance_rate() * 100.0
+        );
+        eprintln!("  rejected by verification: {}", self.rejected_unverified);
+        eprintln!("  rejected by mate length:  {}", self.rejected_mate_length);
+        eprintln!(
+            "  rejected for multiple solutions: {}",
+            self.rejected_multiple_solutions
+        );
+        eprintln!("  rejected by defense check: {}", self.rejected_defended);
+        eprintln!("  rejected out of time:     {}", self.rejected_out_of_time);
+        eprintln!("  rejected by difficulty:   {}", self.rejected_difficulty);
+        eprintln!("  rejected by filters:      {}", self.rejected_filtered);
+        eprintln!("  rejected as malformed:    {}", self.rejected_malformed);
+        eprintln!(
+            "  duplicates skipped: {} (unique yield {:.1}%)",
+            self.duplicates,
+            self.unique_yield() * 100.0
+        );
+        if self.stuck_games > 0 {
+            eprintln!(
+                "  games abandoned as stuck: {} ({} on repetition)",
+                self.stuck_games, self.repetition_games
+            );
+        }
+        if self.opening_reuse_games > 0 {
+            eprintln!(
+                "  games abandoned for a reused opening: {}",
+                self.opening_reuse_games
+            );
+        }
+        if self.escalations > 0 {
+            eprintln!(
+                "  search time raised: {} times ({} candidates found at a raised time)",
+                self.escalations, self.escalated_candidates
+            );
+        }
+        if self.engine_restarts > 0 {
+            eprintln!("  engine restarts: {}", self.engine_restarts);
+        }
+        if !self.accepted_mates.is_empty() {
+            eprintln!(
+                "  mate lengths (accepted): {}",
+                format_histogram(&self.accepted_mates)
+            );
+        }
+        if !self.rejected_mates.is_empty() {
+            eprintln!(
+                "  mate lengths (rejected): {}",
+                format_histogram(&self.rejected_mates)
+            );
+        }
+        if !self.themes.is_empty() {
+            eprintln!("  themes (accepted):");
+            for (theme, count) in &self.themes {
+                eprintln!("    {:<15} {}", theme.to_string(), count);
+            }
+        }
+    }
+}
+
+/// Counters over every simulated game, whether or not it yielded a puzzle.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameStats {
+    pub total_games: usize,
+    pub checkmates: usize,
+    pub no_result_games: usize,
+    pub errors: usize,
+    /// Plies played over all games
+    pub total_moves: u64,
+    /// Mates whose puzzle position an earlier game already produced
+    pub duplicate_positions: usize,
+    #[serde(skip)]
+    mate_positions: HashSet<u64>,
+}
+
+impl GameStats {
+    pub fn from_results(results: &[SimulationResult]) -> GameStats {
+        let mut stats = GameStats::default();
+        for result in results {
+            stats.record(result);
+        }
+        stats
+    }
+
+    pub fn record(&mut self, game: &SimulationResult) {
+        self.total_games += 1;
+        self.total_moves += game.plies as u64;
+        match &game.result {
+            GameResult::Checkmate(tsume) => {
+                self.checkmates += 1;
+                self.record_mate_position(position_hash(&tsume.sfen));
+            }
+            GameResult::NoResult => self.no_result_games += 1,
+            GameResult::Error(_) => self.errors += 1,
+        }
+    }
+
+    fn record_mate_position(&mut self, hash: u64) {
+        if !self.mate_positions.insert(hash) {
+            self.duplicate_positions += 1;
+        }
+    }
+
+    /// Mean plies per game.
+    pub fn average_game_length(&self) -> f64 {
+        if self.total_games == 0 {
+            return 0.0;
+        }
+        self.total_moves as f64 / self.total_games as f64
+    }
+
+    /// Add another worker's games; positions both found count as duplicates.
+    pub fn merge(&mut self, other: GameStats) {
+        self.total_games += other.total_games;
+        self.checkmates += other.checkmates;
+        self.no_result_games += other.no_result_games;
+        self.errors += other.errors;
+        self.total_moves += other.total_moves;
+        self.duplicate_positions += other.duplicate_positions;
+        for hash in other.mate_positions {
+            self.record_mate_position(hash);
+        }
+    }
+
+    pub fn print_summary(&self) {
+        eprintln!(
+            "Games: {} played, {} mates, {} without result, {} errors",
+            self.total_games, self.checkmates, self.no_result_games, self.errors
+        );
+        eprintln!(
+            "  average length: {:.1} plies; repeated mate positions: {}",
+            self.average_game_length(),
+            self.duplicate_positions
+        );
+    }
+}
+
+/// Format a histogram as space-separated `value:count` pairs.
+fn format_histogram(histogram: &BTreeMap<i32, usize>) -> String {
+    histogram
+        .iter()
+        .map(|(value, count)| format!("{}:{}", value, count))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{SimulationError, Tsume};
+
+    #[test]
+    fn test_mate_length_distribution() {
+        let mut stats = RunStats::default();
+        stats.record_accepted(Some(3));
+        stats.record_accepted(Some(3));
+        stats.record_accepted(Some(5));
+        stats.record_mate_length_rejected(1);
+        stats.record_unverified();
+
+        assert_eq!(stats.accepted, 3);
+        assert_eq!(stats.rejected_mate_length, 1);
+        assert_eq!(stats.rejected_unverified, 1);
+        assert_eq!(format_histogram(&stats.accepted_mates), "3:2 5:1");
+        assert_eq!(format_histogram(&stats.rejected_mates), "1:1");
+    }
+
+    #[test]
+    fn test_unique_yield() {
+        let mut stats = RunStats::default();
+        assert_eq!(stats.unique_yield(), 0.0);
+
+        stats.record_accepted(None);
+        stats.record_accepted(None);
+        stats.record_accepted(None);
+        stats.record_duplicate();
+        assert_eq!(stats.unique_yield(), 0.75);
+    }
+
+    #[test]
+    fn test_acceptance_rate() {
+        let mut stats = RunStats::default();
+        assert_eq!(stats.acceptance_rate(), 0.0);
+
+        stats.record_accepted(Some(1));
+        stats.record_unverified();
+        stats.record_mate_length_rejected(9);
+        stats.record_duplicate();
+        assert_eq!(stats.acceptance_rate(), 0.25);
+    }
+
+    #[test]
+    fn test_mined_yield() {
+        let mut stats = RunStats::default();
+        stats.record_accepted(None);
+        stats.games.total_games = 4;
+        assert_eq!(stats.puzzles_per_game(), 0.25);
+
+        // No game is played when mining
+        stats.mode = GenerationMode::Mined;
+        stats.games_mined = 8;
+        assert_eq!(stats.puzzles_per_game(), 0.125);
+    }
+
+    fn game(result: GameResult, plies: usize) -> SimulationResult {
+        SimulationResult {
+            result,
+            start_sfen: "bkr/p1p/3/P1P/RKB b - 1".to_string(),
+            moves: Vec::new(),
+            plies,
+            scores: Vec::new(),
+            search_ms: Vec::new(),
+            stuck: None,
+        }
+    }
+
+    fn mate(sfen: &str, ply: usize) -> GameResult {
+        GameResult::Checkmate(Tsume {
+            sfen: sfen.to_string(),
+            game_sfen: sfen.to_string(),
+            ply,
+            mate_ply: ply + 1,
+        })
+    }
+
+    #[test]
+    fn test_game_stats_from_results() {
+        let results = [
+            game(mate("1k1/3/R2/3/K1B b G 1", 11), 12),
+            game(GameResult::NoResult, 300),
+            game(GameResult::Error(SimulationError::Engine), 4),
+            // The same puzzle position again, at another move number
+            game(mate("1k1/3/R2/3/K1B b G 7", 5), 6),
+            game(mate("1k1/3/R2/3/K1B b P 1", 7), 8),
+        ];
+        let stats = GameStats::from_results(&results);
+        assert_eq!(stats.total_games, 5);
+        assert_eq!(stats.checkmates, 3);
+        assert_eq!(stats.no_result_games, 1);
+        assert_eq!(stats.errors, 1);
+        assert_eq!(stats.total_moves, 330);
+        assert_eq!(stats.duplicate_positions, 1);
+        assert_eq!(stats.average_game_length(), 66.0);
+        assert_eq!(GameStats::default().average_game_length(), 0.0);
+
+        let mut merged = GameStats::from_results(&results[..1]);
+        merged.merge(GameStats::from_results(&results[3..]));
+        assert_eq!(merged.checkmates, 3);
+        assert_eq!(merged.duplicate_positions, 1);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut first = RunStats::default();
+        first.record_accepted(Some(3));
+        first.record_duplicate();
+        let mut second = RunStats::default();
+        second.record_accepted(Some(3));
+        second.record_mate_length_rejected(7);
+        second.record_themes(&[Theme::DropMate, Theme::EdgeMate]);
+
+        first.merge(second);
+        assert_eq!(first.accepted, 2);
+        assert_eq!(first.duplicates, 1);
+        assert_eq!(format_histogram(&first.accepted_mates), "3:2");
+        assert_eq!(format_histogram(&first.rejected_mates), "7:1");
+        assert_eq!(first.themes.get(&Theme::EdgeMate), Some(&1));
+    }
+}
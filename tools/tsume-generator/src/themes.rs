@@ -0,0 +1,138 @@
+//! Theme tags of verified puzzles, classified from the solution line.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::training::{WHITE_OFFSET, encode_board};
+use crate::variant::Variant;
+
+/// Board the squares are laid out on
+const VARIANT: Variant = Variant::Wildcatshogi;
+/// Piece code of a king, see [`encode_board`]
+const KING: u8 = 1;
+
+/// A motif a solution shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    /// The mating move is a drop
+    DropMate,
+    /// Black puts a piece where the defender's reply in the solution takes it
+    Sacrifice,
+    /// The mating move promotes
+    PromotionMate,
+    /// The king is mated on the edge of the board
+    EdgeMate,
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Theme::DropMate => "drop_mate",
+            Theme::Sacrifice => "sacrifice",
+            Theme::PromotionMate => "promotion_mate",
+            Theme::EdgeMate => "edge_mate",
+        })
+    }
+}
+
+/// Themes of `solution` (library convention) played from `position`, Black to move.
+///
+/// The library has no static exchange evaluation, so a sacrifice is a Black
+/// move whose piece the solution's next defender move captures. There is no
+/// double check theme: every piece steps a single square, so there are no
+/// discovered checks and one move can only ever add one checker.
+pub fn classify(position: &Position, solution: &[Move]) -> Vec<Theme> {
+    let Some(mate) = solution.last().map(Move::to_string) else {
+        return Vec::new();
+    };
+    let mut themes = Vec::new();
+    if mate.contains('*') {
+        themes.push(Theme::DropMate);
+    }
+    if solution
+        .iter()
+        .step_by(2)
+        .zip(solution.iter().skip(1).step_by(2))
+        .any(|(attack, reply)| destination(&attack.to_string()) == destination(&reply.to_string()))
+    {
+        themes.push(Theme::Sacrifice);
+    }
+    if mate.ends_with('+') {
+        themes.push(Theme::PromotionMate);
+    }
+
+    let mut mated = position.clone();
+    if solution.iter().all(|mv| mated.make_move(*mv).is_ok()) && king_on_edge(&mated) {
+        themes.push(Theme::EdgeMate);
+    }
+    themes
+}
+
+/// Destination square of a move string, e.g. `2b` for `2c2b+` or `P*2b`.
+fn destination(mv: &str) -> &str {
+    mv.get(2..4).unwrap_or_default()
+}
+
+/// Whether the White king stands on the outer ring of squares.
+fn king_on_edge(position: &Position) -> bool {
+    let sfen = position.to_sfen();
+    let Some(board) = sfen.split_whitespace().next().and_then(encode_board) else {
+        return false;
+    };
+    let (files, ranks) = (VARIANT.files() as usize, VARIANT.ranks() as usize);
+    board
+        .iter()
+        .position(|&code| code == KING + WHITE_OFFSET)
+        .is_some_and(|index| {
+            let (row, col) = (index / files, index % files);
+            row == 0 || col == 0 || row == ranks - 1 || col == files - 1
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify_line(sfen: &str, moves: &[&str]) -> Vec<Theme> {
+        let position = Position::from_sfen(sfen).unwrap();
+        let moves: Vec<Move> = moves
+            .iter()
+            .map(|mv| Move::from_sfen(mv).unwrap())
+            .collect();
+        classify(&position, &moves)
+    }
+
+    #[test]
+    fn test_drop_mate_on_the_edge() {
+        // The gold drop mates the king on its back rank
+        assert_eq!(
+            classify_line("1k1/3/1R1/3/K1B b G 1", &["G*2b"]),
+            [Theme::DropMate, Theme::EdgeMate]
+        );
+    }
+
+    #[test]
+    fn test_sacrifice() {
+        // The rook steps next to the king, which takes it
+        let themes = classify_line("1k1/3/1R1/3/K1B b G 1", &["2c2b", "2a2b", "G*2c"]);
+        assert!(themes.contains(&Theme::Sacrifice));
+        let themes = classify_line("1k1/3/1R1/3/K1B b G 1", &["G*2b"]);
+        assert!(!themes.contains(&Theme::Sacrifice));
+    }
+
+    #[test]
+    fn test_promotion_mate() {
+        let themes = classify_line("1k1/P2/3/3/K1B b - 1", &["1b1a+"]);
+        assert!(themes.contains(&Theme::PromotionMate));
+    }
+
+    #[test]
+    fn test_king_in_the_centre() {
+        // Mated on 2b, which touches no edge of the 3x5 board
+        let themes = classify_line("3/1k1/3/1R1/K1B b G 1", &["G*2c"]);
+        assert_eq!(themes, [Theme::DropMate]);
+    }
+}
@@ -0,0 +1,199 @@
+//! Time spent in each stage of the pipeline, per puzzle and over a run.
+
+use std::io;
+use std::ops::AddAssign;
+use std::path::Path;
+use std::time::Duration;
+
+/// Time spent in each stage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageTimes {
+    /// Playing games
+    pub simulation: Duration,
+    /// Proving mates, with the defense check and filters
+    pub verification: Duration,
+    /// Rating difficulty
+    pub difficulty: Duration,
+    /// Handing puzzles to the output and writing their games
+    pub writing: Duration,
+}
+
+impl StageTimes {
+    fn stages(&self) -> [(&'static str, Duration); 4] {
+        [
+            ("simulation", self.simulation),
+            ("verification", self.verification),
+            ("difficulty", self.difficulty),
+            ("writing", self.writing),
+        ]
+    }
+}
+
+impl AddAssign for StageTimes {
+    fn add_assign(&mut self, other: StageTimes) {
+        self.simulation += other.simulation;
+        self.verification += other.verification;
+        self.difficulty += other.difficulty;
+        self.writing += other.writing;
+    }
+}
+
+/// Cost of one accepted puzzle, the candidates rejected before it included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PuzzleTiming {
+    pub id: String,
+    /// Wall-clock time from the previous puzzle of the worker to this one
+    pub wall: Duration,
+    pub stages: StageTimes,
+}
+
+/// Timings of one worker, or of a whole run once merged.
+///
+/// Each worker keeps its own and they are merged when the workers finish, so
+/// recording takes no lock.
+#[derive(Debug, Default)]
+pub struct Timings {
+    /// Every stage over the run, rejected candidates included
+    pub total: StageTimes,
+    puzzles: Vec<PuzzleTiming>,
+}
+
+impl Timings {
+    pub fn record_puzzle(&mut self, puzzle: PuzzleTiming) {
+        self.total += puzzle.stages;
+        self.puzzles.push(puzzle);
+    }
+
+    /// Add time that went into no accepted puzzle.
+    pub fn record_unused(&mut self, stages: StageTimes) {
+        self.total += stages;
+    }
+
+    pub fn puzzles(&self) -> &[PuzzleTiming] {
+        &self.puzzles
+    }
+
+    pub fn merge(&mut self, other: Timings) {
+        self.total += other.total;
+        self.puzzles.extend(other.puzzles);
+    }
+
+    pub fn print_summary(&self) {
+        eprintln!(
+            "Time: {}",
+            self.total
+                .stages()
+                .iter()
+                .map(|(stage, time)| format!("{} {:.1}s", stage, time.as_secs_f64()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if self.puzzles.is_empty() {
+            return;
+        }
+        let mut columns = vec![("wall-clock", self.column(|p| p.wall))];
+        for (i, (stage, _)) in self.total.stages().into_iter().enumerate() {
+            columns.push((stage, self.column(|p| p.stages.stages()[i].1)));
+        }
+        for (name, times) in columns {
+            let (mean, p50, p95) = summarize(&times);
+            eprintln!(
+                "  per puzzle {}: mean {} ms, p50 {} ms, p95 {} ms",
+                name,
+                mean.as_millis(),
+                p50.as_millis(),
+                p95.as_millis()
+            );
+        }
+    }
+
+    fn column(&self, time: impl Fn(&PuzzleTiming) -> Duration) -> Vec<Duration> {
+        self.puzzles.iter().map(time).collect()
+    }
+
+    /// Write one CSV row of milliseconds per accepted puzzle (`--timings`).
+    pub fn write_rows(&self, path: &Path) -> io::Result<()> {
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record([
+            "id",
+            "wall_ms",
+            "simulation_ms",
+            "verification_ms",
+            "difficulty_ms",
+            "writing_ms",
+        ])?;
+        for puzzle in &self.puzzles {
+            let mut row = vec![puzzle.id.clone(), puzzle.wall.as_millis().to_string()];
+            row.extend(
+                puzzle
+                    .stages
+                    .stages()
+                    .iter()
+                    .map(|(_, time)| time.as_millis().to_string()),
+            );
+            writer.write_record(&row)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Mean, median and 95th percentile (nearest rank) of non-empty `times`.
+fn summarize(times: &[Duration]) -> (Duration, Duration, Duration) {
+    let mut sorted = times.to_vec();
+    sorted.sort();
+    let percentile = |p: usize| sorted[(sorted.len() * p).div_ceil(100).max(1) - 1];
+    let mean = sorted.iter().sum::<Duration>() / sorted.len() as u32;
+    (mean, percentile(50), percentile(95))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puzzle(id: &str, wall_ms: u64) -> PuzzleTiming {
+        PuzzleTiming {
+            id: id.to_string(),
+            wall: Duration::from_millis(wall_ms),
+            stages: StageTimes {
+                simulation: Duration::from_millis(wall_ms / 2),
+                ..StageTimes::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        let times: Vec<Duration> = (1..=20).map(Duration::from_millis).collect();
+        let (mean, p50, p95) = summarize(&times);
+        assert_eq!(mean, Duration::from_micros(10_500));
+        assert_eq!(p50, Duration::from_millis(10));
+        assert_eq!(p95, Duration::from_millis(19));
+        let only = Duration::from_millis(7);
+        assert_eq!(summarize(&[only]), (only, only, only));
+    }
+
+    #[test]
+    fn test_merge_and_rows() {
+        let mut first = Timings::default();
+        first.record_puzzle(puzzle("a", 40));
+        first.record_unused(StageTimes {
+            verification: Duration::from_millis(5),
+            ..StageTimes::default()
+        });
+        let mut second = Timings::default();
+        second.record_puzzle(puzzle("b", 100));
+        first.merge(second);
+
+        assert_eq!(first.puzzles().len(), 2);
+        assert_eq!(first.total.simulation, Duration::from_millis(70));
+        assert_eq!(first.total.verification, Duration::from_millis(5));
+
+        let path = std::env::temp_dir().join(format!("tsume-timings-{}.csv", std::process::id()));
+        first.write_rows(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], "b,100,50,0,0,0");
+    }
+}
@@ -0,0 +1,457 @@
+//! Position encodings for machine learning: fixed-width binary training
+//! records and float feature vectors.
+//!
+//! A training file is a plain concatenation of [`RECORD_SIZE`]-byte records
+//! with no header, so it can be memory-mapped or read in any chunk of whole
+//! records.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use rand::Rng;
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::games::Game;
+use crate::movegen::legal_moves;
+
+/// Squares on the 3x5 board
+pub const SQUARES: usize = 15;
+
+/// Bytes per record: the board, both hands, the side to move and the move
+pub const RECORD_SIZE: usize = SQUARES + 4;
+
+/// Board piece codes, Black's pieces; White's are the same plus 8
+const PIECE_CODES: [(&str, u8); 6] = [("K", 1), ("R", 2), ("B", 3), ("G", 4), ("P", 5), ("+P", 6)];
+
+/// Code added to a Black piece code for the White piece
+pub(crate) const WHITE_OFFSET: u8 = 8;
+
+/// Hand piece letters in the order used by the hand byte and the feature vector
+const HAND_PIECES: &str = "PBRG";
+
+/// Most pieces of each [`HAND_PIECES`] kind one hand can hold: every pawn,
+/// bishop and rook in the game, and a gold for each pawn
+const HAND_LIMITS: [u32; 4] = [4, 2, 2, 4];
+
+/// Length of [`feature_vector`]: one plane of 15 squares per piece type and
+/// color, then the normalized hand counts of Black and of White
+pub const FEATURE_SIZE: usize = SQUARES * PIECE_CODES.len() * 2 + HAND_PIECES.len() * 2;
+
+/// One position of a game and the move played from it.
+///
+/// `board` holds a piece code per square in SFEN order (rank a first, files
+/// left to right); 0 is empty, 1-6 are Black's K, R, B, G, P and promoted P,
+/// 9-14 the same for White. A hand packs its piece counts into one byte:
+/// pawns in bits 0-2, bishops in bits 3-4, rooks in bits 5-6 and golds in
+/// bit 7. A single gold bit cannot hold the [`HAND_LIMITS`] golds, so a
+/// position with two or more golds in one hand has no record; every other
+/// count fits its width. `side_to_move` is 0 for Black and 1
+/// for White. `move_index` is the played move's index in
+/// [`legal_moves`] of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrainingRecord {
+    pub board: [u8; SQUARES],
+    pub hand_black: u8,
+    pub hand_white: u8,
+    pub side_to_move: u8,
+    pub move_index: u8,
+}
+
+impl TrainingRecord {
+    /// Record of `position` with the move at `move_index` among its legal moves.
+    ///
+    /// Returns `None` if the SFEN does not describe a 3x5 board or a hand
+    /// holds more pieces of a kind than its bits can count.
+    pub fn from_position(position: &Position, move_index: u8) -> Option<Self> {
+        let sfen = position.to_sfen();
+        let mut fields = sfen.split_whitespace();
+        let board = encode_board(fields.next()?)?;
+        let side_to_move = u8::from(fields.next()? == "w");
+        let (hand_black, hand_white) = encode_hands(fields.next().unwrap_or("-"))?;
+        Some(TrainingRecord {
+            board,
+            hand_black,
+            hand_white,
+            side_to_move,
+            move_index,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[..SQUARES].copy_from_slice(&self.board);
+        bytes[SQUARES] = self.hand_black;
+        bytes[SQUARES + 1] = self.hand_white;
+        bytes[SQUARES + 2] = self.side_to_move;
+        bytes[SQUARES + 3] = self.move_index;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Self {
+        let mut board = [0; SQUARES];
+        board.copy_from_slice(&bytes[..SQUARES]);
+        TrainingRecord {
+            board,
+            hand_black: bytes[SQUARES],
+            hand_white: bytes[SQUARES + 1],
+            side_to_move: bytes[SQUARES + 2],
+            move_index: bytes[SQUARES + 3],
+        }
+    }
+}
+
+/// Flat network input for `position`, [`FEATURE_SIZE`] long.
+///
+/// Planes come in [`PIECE_CODES`] order, Black's six then White's six, each
+/// with 1.0 on the squares (SFEN order) holding that piece. The hands follow
+/// as counts of P, B, R and G divided by [`HAND_LIMITS`], Black's then White's.
+pub fn feature_vector(position: &Position) -> Vec<f32> {
+    let sfen = position.to_sfen();
+    let mut fields = sfen.split_whitespace();
+    let board = fields.next().and_then(encode_board).unwrap_or([0; SQUARES]);
+    let hands = hand_counts(fields.nth(1).unwrap_or("-"));
+
+    let mut features = vec![0.0; FEATURE_SIZE];
+    for (square, code) in board.iter().enumerate().filter(|(_, code)| **code != 0) {
+        let color = usize::from(*code > WHITE_OFFSET);
+        let piece = (*code as usize - 1) % WHITE_OFFSET as usize;
+        features[(color * PIECE_CODES.len() + piece) * SQUARES + square] = 1.0;
+    }
+    let hand_start = SQUARES * PIECE_CODES.len() * 2;
+    for (color, counts) in hands.iter().enumerate() {
+        for (slot, (count, limit)) in counts.iter().zip(HAND_LIMITS).enumerate() {
+            let index = hand_start + color * HAND_PIECES.len() + slot;
+            features[index] = (*count).min(limit) as f32 / limit as f32;
+        }
+    }
+    features
+}
+
+/// Write `records` to `path` as raw bytes, returning the number of bytes written.
+pub fn write_training_data(records: &[TrainingRecord], path: &Path) -> io::Result<usize> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for record in records {
+        writer.write_all(&record.to_bytes())?;
+    }
+    writer.flush()?;
+    Ok(records.len() * RECORD_SIZE)
+}
+
+/// Read a file written by [`write_training_data`].
+pub fn read_training_data(path: &Path) -> io::Result<Vec<TrainingRecord>> {
+    let bytes = fs::read(path)?;
+    if bytes.len() % RECORD_SIZE != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} bytes is not a whole number of {}-byte records",
+                bytes.len(),
+                RECORD_SIZE
+            ),
+        ));
+    }
+    Ok(bytes
+        .chunks_exact(RECORD_SIZE)
+        .map(|chunk| TrainingRecord::from_bytes(chunk.try_into().expect("chunk of RECORD_SIZE")))
+        .collect())
+}
+
+/// One record per move of `game`, each with the position the move was played from.
+///
+/// Stops at the first move that does not parse or is not legal. A
+/// [`GameRecord`](crate::games::GameRecord) passes its `game`.
+pub fn game_to_training_records(game: &Game) -> Vec<TrainingRecord> {
+    let mut records = Vec::new();
+    let Ok(mut position) = Position::from_sfen(&game.start_sfen) else {
+        return records;
+    };
+    for text in &game.moves {
+        let Some(mv) = Move::from_sfen(text) else {
+            break;
+        };
+        let Some(index) = legal_moves(&position)
+            .iter()
+            .position(|legal| legal.to_string() == mv.to_string())
+        else {
+            break;
+        };
+        let Some(record) = u8::try_from(index)
+            .ok()
+            .and_then(|index| TrainingRecord::from_position(&position, index))
+        else {
+            break;
+        };
+        records.push(record);
+        if position.make_move(mv).is_err() {
+            break;
+        }
+    }
+    records
+}
+
+/// Positions at each end of a game that [`sample_position_from_game`] never
+/// picks: the first are shared by many games, the last are trivially won
+pub const SAMPLE_MARGIN: usize = 3;
+
+/// A position of `game` picked uniformly among its positions but the first
+/// and last [`SAMPLE_MARGIN`].
+///
+/// None if the game, up to its first move that is not legal, has no
+/// position left between the margins.
+pub fn sample_position_from_game(game: &Game, rng: &mut impl Rng) -> Option<Position> {
+    let mut positions = game.positions();
+    if positions.len() <= 2 * SAMPLE_MARGIN {
+        return None;
+    }
+    let index = rng.random_range(SAMPLE_MARGIN..positions.len() - SAMPLE_MARGIN);
+    Some(positions.swap_remove(index))
+}
+
+/// Piece codes of the squares of an SFEN board field.
+pub(crate) fn encode_board(board: &str) -> Option<[u8; SQUARES]> {
+    let mut squares = [0; SQUARES];
+    let mut index = 0;
+    let mut promoted = false;
+    for c in board.chars().filter(|c| *c != '/') {
+        if c == '+' {
+            promoted = true;
+            continue;
+        }
+        if let Some(empty) = c.to_digit(10) {
+            index += empty as usize;
+            continue;
+        }
+        let letter = c.to_ascii_uppercase().to_string();
+        let piece = if promoted {
+            format!("+{}", letter)
+        } else {
+            letter
+        };
+        let (_, code) = PIECE_CODES.iter().find(|(name, _)| *name == piece)?;
+        *squares.get_mut(index)? = if c.is_ascii_uppercase() {
+            *code
+        } else {
+            code + WHITE_OFFSET
+        };
+        promoted = false;
+        index += 1;
+    }
+    (index == SQUARES).then_some(squares)
+}
+
+/// Packed `(black, white)` hands of an SFEN hand field; none if one does not fit.
+fn encode_hands(hand: &str) -> Option<(u8, u8)> {
+    let [black, white] = hand_counts(hand);
+    Some((pack_hand(black)?, pack_hand(white)?))
+}
+
+/// Counts of each [`HAND_PIECES`] kind in Black's and White's hands.
+fn hand_counts(hand: &str) -> [[u32; 4]; 2] {
+    let mut counts = [[0u32; 4]; 2];
+    let mut count = 0;
+    for c in hand.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            count = count * 10 + digit;
+            continue;
+        }
+        let side = usize::from(c.is_ascii_lowercase());
+        if let Some(slot) = HAND_PIECES.find(c.to_ascii_uppercase()) {
+            counts[side][slot] += count.max(1);
+        }
+        count = 0;
+    }
+    counts
+}
+
+/// Bits of the hand byte per [`HAND_PIECES`] kind, lowest first
+const HAND_BITS: [u32; 4] = [3, 2, 2, 1];
+
+/// Pack pawn, bishop, rook and gold counts into a hand byte; none if a
+/// count does not fit its [`HAND_BITS`].
+fn pack_hand(counts: [u32; 4]) -> Option<u8> {
+    let mut byte = 0;
+    let mut shift = 0;
+    for (count, bits) in counts.into_iter().zip(HAND_BITS) {
+        if count >= 1 << bits {
+            return None;
+        }
+        byte |= count << shift;
+        shift += bits;
+    }
+    Some(byte as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use shogi::sfen::mirror_sfen;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_position() {
+        let position = Position::from_sfen("bk1/p1P/3/P2/RKB w 2PBr 1").unwrap();
+        let record = TrainingRecord::from_position(&position, 4).unwrap();
+        assert_eq!(
+            record.board,
+            [11, 9, 0, 13, 0, 5, 0, 0, 0, 5, 0, 0, 2, 1, 3]
+        );
+        assert_eq!(record.hand_black, 2 | 1 << 3);
+        assert_eq!(record.hand_white, 1 << 5);
+        assert_eq!(record.side_to_move, 1);
+        assert_eq!(record.move_index, 4);
+
+        assert_eq!(encode_board("bk1/p1+P/3/P2/RKB").unwrap()[5], 6);
+
+        // One gold fits the hand byte, two do not
+        let one = Position::from_sfen("k2/3/3/3/2K b G 1").unwrap();
+        assert_eq!(
+            TrainingRecord::from_position(&one, 0).unwrap().hand_black,
+            1 << 7
+        );
+        let two = Position::from_sfen("k2/3/3/3/2K b 2G 1").unwrap();
+        assert_eq!(TrainingRecord::from_position(&two, 0), None);
+        assert_eq!(pack_hand([4, 2, 2, 1]), Some(4 | 2 << 3 | 2 << 5 | 1 << 7));
+        assert_eq!(encode_board("bk1/p1P/3/P2/RK"), None);
+    }
+
+    #[test]
+    fn test_feature_vector_of_startpos() {
+        let features = feature_vector(&Position::startpos());
+        assert_eq!(features.len(), FEATURE_SIZE);
+        assert_eq!(FEATURE_SIZE, 188);
+        assert_eq!(features.iter().sum::<f32>(), 10.0);
+
+        // Black's rook, king and bishop stand on rank e, its pawns on rank d
+        let plane = |piece: usize| &features[piece * SQUARES..(piece + 1) * SQUARES];
+        assert_eq!(plane(0)[13], 1.0);
+        assert_eq!(plane(1)[12], 1.0);
+        assert_eq!(plane(2)[14], 1.0);
+        assert_eq!(plane(4)[9], 1.0);
+        assert_eq!(plane(4)[11], 1.0);
+        assert_eq!(plane(4).iter().sum::<f32>(), 2.0);
+    }
+
+    #[test]
+    fn test_feature_vector_of_mirrored_position() {
+        let sfen = "bk1/p1P/3/P2/RKB w 2PBr 1";
+        let features = feature_vector(&Position::from_sfen(sfen).unwrap());
+        let mirrored = feature_vector(&Position::from_sfen(&mirror_sfen(sfen)).unwrap());
+        assert_eq!(features[FEATURE_SIZE - 8], 0.5);
+
+        // Mirroring swaps the colors and turns each plane around
+        let planes = PIECE_CODES.len();
+        for color in 0..2 {
+            for piece in 0..planes {
+                for square in 0..SQUARES {
+                    let original = (color * planes + piece) * SQUARES + square;
+                    let swapped = ((1 - color) * planes + piece) * SQUARES + SQUARES - 1 - square;
+                    assert_eq!(features[original], mirrored[swapped]);
+                }
+            }
+        }
+        let hand_start = SQUARES * planes * 2;
+        let hand_size = HAND_PIECES.len();
+        assert_eq!(
+            features[hand_start..hand_start + hand_size],
+            mirrored[hand_start + hand_size..]
+        );
+        assert_eq!(
+            features[hand_start + hand_size..],
+            mirrored[hand_start..hand_start + hand_size]
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_training_data() {
+        let records: Vec<TrainingRecord> = (0..100u8)
+            .map(|i| TrainingRecord {
+                board: std::array::from_fn(|square| (i as usize + square) as u8 % 15),
+                hand_black: i,
+                hand_white: 255 - i,
+                side_to_move: i % 2,
+                move_index: i.wrapping_mul(7),
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("tsume-training-{}.bin", std::process::id()));
+
+        let written = write_training_data(&records, &path).unwrap();
+        assert_eq!(written, 100 * RECORD_SIZE);
+        let read = read_training_data(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(read, records);
+    }
+
+    #[test]
+    fn test_game_to_training_records() {
+        let game = Game {
+            start_sfen: "bkr/p1p/3/P1P/RKB b - 1".to_string(),
+            moves: vec!["2e2d".to_string(), "2a2b".to_string(), "9z9z".to_string()],
+            ..Game::default()
+        };
+        let records = game_to_training_records(&game);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].side_to_move, 0);
+        assert_eq!(records[1].side_to_move, 1);
+
+        let start = Position::startpos();
+        let played = legal_moves(&start)[records[0].move_index as usize];
+        assert_eq!(played.to_string(), "2e2d");
+    }
+
+    /// A game of `plies` moves from the starting position, each the first
+    /// legal move that leaves the opponent a move.
+    fn long_game(plies: usize) -> Game {
+        let mut position = Position::startpos();
+        let mut moves = Vec::new();
+        for _ in 0..plies {
+            let mv = legal_moves(&position)
+                .into_iter()
+                .find(|mv| {
+                    let mut next = position.clone();
+                    next.make_move(*mv).is_ok() && !legal_moves(&next).is_empty()
+                })
+                .unwrap();
+            position.make_move(mv).unwrap();
+            moves.push(mv.to_string());
+        }
+        Game {
+            start_sfen: Position::startpos().to_sfen(),
+            moves,
+            ..Game::default()
+        }
+    }
+
+    #[test]
+    fn test_sample_position_from_game() {
+        let game = long_game(20);
+        let sfens: Vec<String> = game.positions().iter().map(Position::to_sfen).collect();
+        assert_eq!(sfens.len(), 21);
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut counts = [0usize; 21];
+        for _ in 0..100 {
+            let sfen = sample_position_from_game(&game, &mut rng)
+                .unwrap()
+                .to_sfen();
+            assert!(Position::from_sfen(&sfen).is_ok());
+            let ply = sfens.iter().position(|known| *known == sfen).unwrap();
+            counts[ply] += 1;
+        }
+        assert!(counts[..SAMPLE_MARGIN].iter().all(|count| *count == 0));
+        assert!(counts[21 - SAMPLE_MARGIN..].iter().all(|count| *count == 0));
+
+        // Chi-squared over the 15 plies sampled from; 36.12 is the 0.1%
+        // critical value for 14 degrees of freedom
+        let expected = 100.0 / 15.0;
+        let chi_squared: f64 = counts[SAMPLE_MARGIN..21 - SAMPLE_MARGIN]
+            .iter()
+            .map(|count| (*count as f64 - expected).powi(2) / expected)
+            .sum();
+        assert!(chi_squared < 36.12, "chi-squared {}", chi_squared);
+
+        assert!(sample_position_from_game(&long_game(5), &mut rng).is_none());
+    }
+}
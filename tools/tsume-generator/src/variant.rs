@@ -0,0 +1,57 @@
+//! Shogi variants the generator can play and replay.
+
+use clap::ValueEnum;
+use shogi::wildcatshogi;
+
+/// A variant defined in variants.ini that the shogi library can also replay.
+///
+/// Every generated game is replayed through the library, so only variants
+/// with a `Position` implementation there can be listed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Variant {
+    /// Wild Cat Shogi (3x5)
+    #[default]
+    Wildcatshogi,
+}
+
+impl Variant {
+    /// Name of the variant in variants.ini, sent as `UCI_Variant`.
+    pub fn engine_name(self) -> &'static str {
+        match self {
+            Variant::Wildcatshogi => "wildcatshogi",
+        }
+    }
+
+    pub fn starting_sfen(self) -> &'static str {
+        match self {
+            Variant::Wildcatshogi => wildcatshogi::STARTING_SFEN,
+        }
+    }
+
+    /// Board width.
+    pub fn files(self) -> u32 {
+        match self {
+            Variant::Wildcatshogi => 3,
+        }
+    }
+
+    /// Board height.
+    pub fn ranks(self) -> u32 {
+        match self {
+            Variant::Wildcatshogi => 5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starting_sfen_matches_board_size() {
+        for variant in Variant::value_variants() {
+            let board = variant.starting_sfen().split_whitespace().next().unwrap();
+            assert_eq!(board.split('/').count() as u32, variant.ranks());
+        }
+    }
+}
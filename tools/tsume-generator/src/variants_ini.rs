@@ -0,0 +1,316 @@
+//! Reading the variant definitions in variants.ini before the engine loads them.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::variant::Variant;
+
+/// Fairy-Stockfish piece types that a variant section can assign a letter to.
+const PIECE_TYPES: &[&str] = &[
+    "pawn",
+    "knight",
+    "bishop",
+    "rook",
+    "queen",
+    "king",
+    "fers",
+    "alfil",
+    "wazir",
+    "commoner",
+    "shogiPawn",
+    "shogiKnight",
+    "lance",
+    "silver",
+    "gold",
+    "dragonHorse",
+    "bers",
+    "archbishop",
+    "chancellor",
+    "amazon",
+    "centaur",
+];
+
+/// The section of variants.ini defining one variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantConfig {
+    pub name: String,
+    width: u8,
+    height: u8,
+    /// `startFen` turned into a puzzle-style SFEN
+    starting_sfen: String,
+    /// Piece type names with their letter, in file order
+    pieces: Vec<(String, char)>,
+    /// Every other setting (promotion, drops, repetition rules, ...), in file order
+    pub rules: Vec<(String, String)>,
+}
+
+/// Reasons variants.ini does not define a usable variant.
+#[derive(Debug)]
+pub enum VariantConfigError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// No `[name]` section
+    MissingSection(String),
+    MissingKey(&'static str),
+    InvalidValue {
+        key: String,
+        value: String,
+    },
+    /// The section disagrees with what the generator plays
+    Mismatch {
+        what: &'static str,
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for VariantConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VariantConfigError::Io { path, source } => {
+                write!(f, "cannot read {}: {}", path.display(), source)
+            }
+            VariantConfigError::MissingSection(name) => {
+                write!(f, "variants file has no [{}] section", name)
+            }
+            VariantConfigError::MissingKey(key) => write!(f, "variant does not set {}", key),
+            VariantConfigError::InvalidValue { key, value } => {
+                write!(f, "invalid {} = {}", key, value)
+            }
+            VariantConfigError::Mismatch {
+                what,
+                expected,
+                found,
+            } => write!(f, "variant {} is {}, expected {}", what, found, expected),
+        }
+    }
+}
+
+impl std::error::Error for VariantConfigError {}
+
+impl VariantConfig {
+    pub fn board_width(&self) -> u8 {
+        self.width
+    }
+
+    pub fn board_height(&self) -> u8 {
+        self.height
+    }
+
+    /// Starting position as SFEN, e.g. `bkr/p1p/3/P1P/RKB b - 1`.
+    pub fn starting_sfen(&self) -> &str {
+        &self.starting_sfen
+    }
+
+    /// Fairy-Stockfish names of the piece types the variant uses.
+    pub fn piece_names(&self) -> Vec<String> {
+        self.pieces.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Value of a setting other than board size, start position and pieces.
+    pub fn rule(&self, key: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Check the engine will play the same board the library replays.
+    pub fn check(&self, variant: Variant) -> Result<(), VariantConfigError> {
+        let size = |width, height| format!("{}x{}", width, height);
+        if (self.width as u32, self.height as u32) != (variant.files(), variant.ranks()) {
+            return Err(VariantConfigError::Mismatch {
+                what: "board size",
+                expected: size(variant.files(), variant.ranks()),
+                found: size(self.width as u32, self.height as u32),
+            });
+        }
+        if self.starting_sfen != variant.starting_sfen() {
+            return Err(VariantConfigError::Mismatch {
+                what: "starting position",
+                expected: variant.starting_sfen().to_string(),
+                found: self.starting_sfen.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Read the section of `path` defining `variant`.
+pub fn parse_variants_ini(
+    path: &Path,
+    variant: Variant,
+) -> Result<VariantConfig, VariantConfigError> {
+    let text = fs::read_to_string(path).map_err(|source| VariantConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_variant_section(&text, variant.engine_name())
+}
+
+/// Parse the `[name]` section of variants.ini text.
+///
+/// A section inheriting another (`[name:parent]`) must still set the board
+/// size and start position itself.
+pub fn parse_variant_section(text: &str, name: &str) -> Result<VariantConfig, VariantConfigError> {
+    let mut entries = Vec::new();
+    // None before the section, true inside it, false after it
+    let mut inside = None;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let section = header.split(':').next().unwrap_or_default();
+            inside = match inside {
+                None if section == name => Some(true),
+                Some(true) => Some(false),
+                state => state,
+            };
+            continue;
+        }
+        if inside == Some(true)
+            && let Some((key, value)) = line.split_once('=')
+        {
+            entries.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    if inside.is_none() {
+        return Err(VariantConfigError::MissingSection(name.to_string()));
+    }
+    from_entries(name, entries)
+}
+
+fn from_entries(
+    name: &str,
+    entries: Vec<(String, String)>,
+) -> Result<VariantConfig, VariantConfigError> {
+    let get = |key: &'static str| {
+        entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+            .ok_or(VariantConfigError::MissingKey(key))
+    };
+    let invalid = |key: &str, value: &str| VariantConfigError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    };
+    let size = |key: &'static str| {
+        let value = get(key)?;
+        value
+            .parse::<u8>()
+            .ok()
+            .filter(|size| *size > 0)
+            .ok_or_else(|| invalid(key, value))
+    };
+    let width = size("maxFile")?;
+    let height = size("maxRank")?;
+    let start_fen = get("startFen")?;
+    let starting_sfen = fen_to_sfen(start_fen).ok_or_else(|| invalid("startFen", start_fen))?;
+
+    let mut pieces = Vec::new();
+    let mut rules = Vec::new();
+    for (key, value) in &entries {
+        let is_piece = PIECE_TYPES.contains(&key.as_str()) || key.starts_with("customPiece");
+        if is_piece {
+            let letter = value
+                .chars()
+                .next()
+                .filter(char::is_ascii_alphabetic)
+                .ok_or_else(|| invalid(key, value))?;
+            pieces.push((key.clone(), letter));
+        } else if !["maxFile", "maxRank", "startFen"].contains(&key.as_str()) {
+            rules.push((key.clone(), value.clone()));
+        }
+    }
+
+    Ok(VariantConfig {
+        name: name.to_string(),
+        width,
+        height,
+        starting_sfen,
+        pieces,
+        rules,
+    })
+}
+
+/// Turn a Fairy-Stockfish start FEN (`board[pocket] side ...`) into SFEN.
+fn fen_to_sfen(fen: &str) -> Option<String> {
+    let mut fields = fen.split_whitespace();
+    let board_and_pocket = fields.next()?;
+    let side = fields.next()?;
+    if side != "b" && side != "w" {
+        return None;
+    }
+    let (board, hand) = match board_and_pocket.split_once('[') {
+        Some((board, pocket)) => (board, pocket.strip_suffix(']')?),
+        None => (board_and_pocket, ""),
+    };
+    let hand = if hand.is_empty() { "-" } else { hand };
+    Some(format!("{} {} {} 1", board, side, hand))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WILDCAT: &str = "\
+# Wild Cat Shogi
+[shogi:fairy]
+maxFile = 9
+
+[wildcatshogi]
+variantTemplate = shogi
+maxFile = 3
+maxRank = 5
+shogiPawn = p
+gold = g
+wazir = r
+fers = b
+king = k
+startFen = bkr/p1p/3/P1P/RKB[] b - - 0 1
+promotionRank = 5
+promotedPieceType = p:g
+";
+
+    #[test]
+    fn test_parse_wildcat_section() {
+        let config = parse_variant_section(WILDCAT, "wildcatshogi").unwrap();
+        assert_eq!((config.board_width(), config.board_height()), (3, 5));
+        assert_eq!(config.starting_sfen(), "bkr/p1p/3/P1P/RKB b - 1");
+        assert_eq!(
+            config.piece_names(),
+            ["shogiPawn", "gold", "wazir", "fers", "king"]
+        );
+        assert_eq!(config.rule("promotedPieceType"), Some("p:g"));
+        assert!(config.check(Variant::Wildcatshogi).is_ok());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            parse_variant_section(WILDCAT, "minishogi"),
+            Err(VariantConfigError::MissingSection(_))
+        ));
+        // Inherits its size from [fairy], which this parser does not follow
+        assert!(matches!(
+            parse_variant_section(WILDCAT, "shogi"),
+            Err(VariantConfigError::MissingKey("maxRank"))
+        ));
+
+        let wide = WILDCAT.replace("maxFile = 3", "maxFile = 4");
+        let config = parse_variant_section(&wide, "wildcatshogi").unwrap();
+        assert!(matches!(
+            config.check(Variant::Wildcatshogi),
+            Err(VariantConfigError::Mismatch {
+                what: "board size",
+                ..
+            })
+        ));
+    }
+}
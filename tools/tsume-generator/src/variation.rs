@@ -0,0 +1,189 @@
+//! Typed principal variations.
+
+use serde::{Deserialize, Serialize};
+use shogi::wildcatshogi::{Move, Position};
+
+use crate::convert_move_files;
+use crate::engine::{PvInfo, Score};
+
+/// Why a move sequence could not be turned into positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    /// The move text at `index` could not be parsed
+    Unparsable { index: usize, mv: String },
+    /// The move at `index` is illegal in the position reached so far
+    Illegal { index: usize, mv: String },
+}
+
+/// A line of play with its evaluation and an optional comment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variation {
+    /// Moves in library convention, serialized as SFEN move strings
+    #[serde(with = "sfen_moves")]
+    pub moves: Vec<Move>,
+    pub score: Option<Score>,
+    pub annotation: Option<String>,
+}
+
+impl Variation {
+    /// Convert an engine PV to typed moves, checking each is legal from `start`.
+    pub fn from_pv_info(pv: &PvInfo, start: &Position) -> Result<Variation, MoveError> {
+        let moves = pv
+            .moves
+            .iter()
+            .enumerate()
+            .map(|(index, text)| {
+                Move::from_sfen(&convert_move_files(text)).ok_or_else(|| MoveError::Unparsable {
+                    index,
+                    mv: text.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let variation = Variation {
+            moves,
+            score: Some(pv.score),
+            annotation: None,
+        };
+        variation.apply(start)?;
+        Ok(variation)
+    }
+
+    /// Play the moves from `start`, returning the start and every position reached.
+    pub fn apply(&self, start: &Position) -> Result<Vec<Position>, MoveError> {
+        let mut positions = vec![start.clone()];
+        let mut position = start.clone();
+        for (index, mv) in self.moves.iter().enumerate() {
+            if position.make_move(*mv).is_err() {
+                return Err(MoveError::Illegal {
+                    index,
+                    mv: mv.to_string(),
+                });
+            }
+            positions.push(position.clone());
+        }
+        Ok(positions)
+    }
+}
+
+impl PvInfo {
+    /// This line as a [`Variation`] from `start`; see [`Variation::from_pv_info`].
+    ///
+    /// The error gives the index of the first move that does not parse or is
+    /// illegal; [`Variation::apply`] gives the positions along the line.
+    pub fn to_variation(&self, start: &Position) -> Result<Variation, MoveError> {
+        Variation::from_pv_info(self, start)
+    }
+}
+
+/// Serde adapter storing moves as SFEN strings.
+mod sfen_moves {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use shogi::wildcatshogi::Move;
+
+    pub fn serialize<S: Serializer>(moves: &[Move], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(moves.iter().map(|mv| mv.to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Move>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|text| {
+                Move::from_sfen(text)
+                    .ok_or_else(|| D::Error::custom(format!("invalid move: {}", text)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pv_info(moves: &[&str]) -> PvInfo {
+        PvInfo {
+            multipv: 1,
+            score: Score::Cp(40),
+            moves: moves.iter().map(|mv| mv.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_from_pv_info_and_apply() {
+        let start = Position::startpos();
+        let variation =
+            Variation::from_pv_info(&pv_info(&["1e2d", "3a2b", "3d3c"]), &start).unwrap();
+        assert_eq!(variation.moves.len(), 3);
+        assert_eq!(variation.score, Some(Score::Cp(40)));
+
+        let positions = variation.apply(&start).unwrap();
+        assert_eq!(positions.len(), 4);
+        for position in &positions {
+            assert!(Position::from_sfen(&position.to_sfen()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_from_pv_info_reports_failing_index() {
+        let start = Position::startpos();
+        let err = Variation::from_pv_info(&pv_info(&["1e2d", "1e1d"]), &start).unwrap_err();
+        assert!(matches!(err, MoveError::Illegal { index: 1, .. }));
+
+        let err = Variation::from_pv_info(&pv_info(&["1e2d", "zz"]), &start).unwrap_err();
+        assert_eq!(
+            err,
+            MoveError::Unparsable {
+                index: 1,
+                mv: "zz".to_string()
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_to_variation_of_an_engine_line() {
+        use std::path::Path;
+
+        use crate::engine::{Engine, EngineConfig};
+        use crate::movegen::legal_moves;
+
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let mut engine = Engine::spawn(&EngineConfig {
+            engine: fixtures.join("infinite-engine.sh"),
+            variants_ini: fixtures.join("variants.ini"),
+            ..EngineConfig::default()
+        })
+        .unwrap();
+        let start = Position::startpos();
+        engine.set_sfen(&start.to_sfen()).unwrap();
+        engine.go_infinite().unwrap();
+        let (pv_infos, _) = engine.stop().unwrap();
+        engine.quit();
+
+        let pv = &pv_infos[0];
+        let variation = pv.to_variation(&start).unwrap();
+        assert_eq!(variation.moves.len(), pv.moves.len());
+        assert_eq!(variation.score, Some(pv.score));
+        let positions = variation.apply(&start).unwrap();
+        for (before, mv) in positions.iter().zip(&variation.moves) {
+            assert!(legal_moves(before).contains(mv));
+        }
+        for position in &positions {
+            assert!(Position::from_sfen(&position.to_sfen()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_annotation_survives_json_round_trip() {
+        let start = Position::startpos();
+        let mut variation = Variation::from_pv_info(&pv_info(&["1e2d", "3a2b"]), &start).unwrap();
+        variation.annotation = Some("quiet developing move".to_string());
+
+        let json = serde_json::to_string(&variation).unwrap();
+        let parsed: Variation = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.annotation, variation.annotation);
+        assert_eq!(parsed.score, variation.score);
+        assert_eq!(parsed.moves, variation.moves);
+    }
+}
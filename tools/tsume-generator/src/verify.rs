@@ -0,0 +1,146 @@
+//! Proving that a candidate puzzle is a forced mate.
+
+use std::time::{Duration, Instant};
+
+use crate::engine::{EngineInterface, MULTIPV_K, PvInfo, Score};
+
+/// Verdict of a verification search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyResult {
+    /// The side to move forces mate in this many plies
+    Matein(i32),
+    /// The search finished without finding a mate for the side to move
+    NoMate,
+    /// The engine hung or exited before giving a verdict, after this long
+    Timeout(Duration),
+}
+
+/// A verdict together with the PVs it was read from.
+pub struct Verification {
+    pub result: VerifyResult,
+    /// Every PV of the search, empty unless the search finished
+    pub pv_infos: Vec<PvInfo>,
+}
+
+/// Search `sfen` for `time_ms` and report whether Black (the side to move) forces mate.
+pub fn verify_puzzle_with_engine<E: EngineInterface>(
+    engine: &mut E,
+    sfen: &str,
+    time_ms: u64,
+) -> VerifyResult {
+    verify_puzzle(engine, sfen, time_ms).result
+}
+
+/// Like [`verify_puzzle_with_engine`], also keeping the PVs for solution and uniqueness checks.
+pub fn verify_puzzle<E: EngineInterface>(engine: &mut E, sfen: &str, time_ms: u64) -> Verification {
+    let started = Instant::now();
+    let Some(pv_infos) = search_full_strength(engine, sfen, time_ms) else {
+        return Verification {
+            result: VerifyResult::Timeout(started.elapsed()),
+            pv_infos: Vec::new(),
+        };
+    };
+
+    let result = match pv_infos
+        .iter()
+        .find(|pv| pv.multipv == 1)
+        .map(|pv| pv.score)
+    {
+        Some(Score::Mate(plies)) if plies > 0 => VerifyResult::Matein(plies),
+        _ => VerifyResult::NoMate,
+    };
+    Verification { result, pv_infos }
+}
+
+/// Search the position after the attacker's key move, the defender to move,
+/// and report whether the defender is still mated: `Matein(n)` when the
+/// attacker mates `n` plies later whatever the defender plays.
+///
+/// Any mate counts here; a puzzle claiming mate in `m` is only confirmed by
+/// `Matein(m - 1)`, which the caller checks.
+pub fn verify_defense<E: EngineInterface>(
+    engine: &mut E,
+    sfen: &str,
+    time_ms: u64,
+) -> VerifyResult {
+    let started = Instant::now();
+    let Some(pv_infos) = search_full_strength(engine, sfen, time_ms) else {
+        return VerifyResult::Timeout(started.elapsed());
+    };
+    match pv_infos
+        .iter()
+        .find(|pv| pv.multipv == 1)
+        .map(|pv| pv.score)
+    {
+        Some(Score::Mate(plies)) if plies < 0 => VerifyResult::Matein(-plies),
+        _ => VerifyResult::NoMate,
+    }
+}
+
+fn search_full_strength<E: EngineInterface>(
+    engine: &mut E,
+    sfen: &str,
+    time_ms: u64,
+) -> Option<Vec<PvInfo>> {
+    // The game may have ended on a strength-limited White search
+    engine.set_strength(None)?;
+    // Uniqueness and rating count the other mating candidates
+    engine.set_multipv(MULTIPV_K)?;
+    engine.set_position(sfen, &[])?;
+    let (pv_infos, _) = engine.search_with_time(time_ms)?;
+    Some(pv_infos)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::engine::{Engine, EngineConfig};
+    use crate::variant::Variant;
+
+    fn mate_engine() -> Engine {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        Engine::spawn(&EngineConfig {
+            engine: fixtures.join("mate-engine.sh"),
+            variants_ini: fixtures.join("variants.ini"),
+            variant: Variant::Wildcatshogi,
+            ..EngineConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_known_tsume_and_non_tsume() {
+        let mut engine = mate_engine();
+        assert_eq!(
+            verify_puzzle_with_engine(&mut engine, "1k1/3/R2/3/K1B b G 1", 50),
+            VerifyResult::Matein(3)
+        );
+        assert_eq!(
+            verify_puzzle_with_engine(&mut engine, "bkr/p1p/3/P1P/RKB b - 1", 50),
+            VerifyResult::NoMate
+        );
+
+        engine.quit();
+        assert!(matches!(
+            verify_puzzle_with_engine(&mut engine, "1k1/3/R2/3/K1B b G 1", 50),
+            VerifyResult::Timeout(_)
+        ));
+    }
+
+    #[test]
+    fn test_verify_defense() {
+        let mut engine = mate_engine();
+        // The known tsume after its key move G*2b
+        assert_eq!(
+            verify_defense(&mut engine, "1k1/1G1/R2/3/K1B w - 2", 50),
+            VerifyResult::Matein(2)
+        );
+        assert_eq!(
+            verify_defense(&mut engine, "bkr/p1p/3/P1P/RKB w - 1", 50),
+            VerifyResult::NoMate
+        );
+        engine.quit();
+    }
+}
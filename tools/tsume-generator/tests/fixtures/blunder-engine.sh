@@ -0,0 +1,36 @@
+#!/bin/sh
+# Deterministic USI engine for simulation tests.
+#
+# Both kings shuffle back and forth as the principal variation. White's second
+# candidate is a pawn push that the engine treats as losing, so a game ends
+# two plies after White plays it, or after six plies otherwise.
+plies=0
+history=""
+while read -r line; do
+  case "$line" in
+    usi) echo "id name BlunderEngine"; echo "id author tsume-generator tests"; echo "usiok" ;;
+    isready) echo "readyok" ;;
+    position*" moves "*) history="${line#* moves }"; plies=$(echo "$history" | wc -w) ;;
+    position*) history=""; plies=0 ;;
+    go*)
+      case " $history " in
+        *" 1b1c "*) echo "bestmove resign"; continue ;;
+      esac
+      if [ "$plies" -ge 6 ]; then
+        echo "bestmove resign"
+      elif [ $((plies % 4)) = 0 ]; then
+        echo "info depth 1 multipv 1 score cp 0 pv 2e2d"; echo "bestmove 2e2d"
+      elif [ $((plies % 4)) = 2 ]; then
+        echo "info depth 1 multipv 1 score cp 0 pv 2d2e"; echo "bestmove 2d2e"
+      elif [ $((plies % 4)) = 1 ]; then
+        echo "info depth 1 multipv 1 score cp 0 pv 2a2b"
+        echo "info depth 1 multipv 2 score cp -900 pv 1b1c"
+        echo "bestmove 2a2b"
+      else
+        echo "info depth 1 multipv 1 score cp 0 pv 2b2a"
+        echo "info depth 1 multipv 2 score cp -900 pv 1b1c"
+        echo "bestmove 2b2a"
+      fi ;;
+    quit) exit 0 ;;
+  esac
+done
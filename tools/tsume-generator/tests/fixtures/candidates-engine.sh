@@ -0,0 +1,14 @@
+#!/bin/sh
+# USI engine that offers the same three scored candidates for any position.
+while read -r line; do
+  case "$line" in
+    usi) echo "id name CandidatesEngine"; echo "id author tsume-generator tests"; echo "usiok" ;;
+    isready) echo "readyok" ;;
+    go*)
+      echo "info depth 1 multipv 1 score cp 50 pv 3d3c"
+      echo "info depth 1 multipv 2 score cp 0 pv 1d1c"
+      echo "info depth 1 multipv 3 score cp -300 pv 2e2d"
+      echo "bestmove 3d3c" ;;
+    quit) exit 0 ;;
+  esac
+done
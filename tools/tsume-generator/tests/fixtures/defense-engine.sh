@@ -0,0 +1,37 @@
+#!/bin/sh
+# USI engine that announces mate in 3 for two tsume, each solved by the key
+# move G*2b. Once it is played, the defence search confirms the mate for
+# EXACT and stretches it by two plies for STALLED.
+EXACT="1k1/3/2R/3/B1K b G"
+STALLED="1k1/3/R2/3/K1B b G"
+position=""
+while read -r line; do
+  case "$line" in
+    usi) echo "id name DefenseEngine"; echo "id author tsume-generator tests"; echo "usiok" ;;
+    isready) echo "readyok" ;;
+    "position sfen $EXACT"*) position=exact ;;
+    "position sfen $STALLED"*) position=stalled ;;
+    "position sfen 1k1/1G1/2R/3/B1K w"*) position=exact-defense ;;
+    "position sfen 1k1/1G1/R2/3/K1B w"*) position=stalled-defense ;;
+    position*) position=other ;;
+    go*)
+      case "$position" in
+        exact)
+          echo "info depth 5 multipv 1 score mate 3 pv G*2b 2a2b 1c1b"
+          echo "bestmove G*2b" ;;
+        stalled)
+          echo "info depth 5 multipv 1 score mate 3 pv G*2b 2a2b 3c3b"
+          echo "bestmove G*2b" ;;
+        exact-defense)
+          echo "info depth 5 multipv 1 score mate -2 pv 2a2b 1c1b"
+          echo "bestmove 2a2b" ;;
+        stalled-defense)
+          echo "info depth 5 multipv 1 score mate -4 pv 2a2b 3c3b"
+          echo "bestmove 2a2b" ;;
+        *)
+          echo "info depth 5 multipv 1 score cp 120 pv 3d3c"
+          echo "bestmove 3d3c" ;;
+      esac ;;
+    quit) exit 0 ;;
+  esac
+done
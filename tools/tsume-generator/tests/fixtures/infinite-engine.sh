@@ -0,0 +1,15 @@
+#!/bin/sh
+# USI engine that searches `go infinite` until told to stop.
+while read -r line; do
+  case "$line" in
+    usi) echo "id name InfiniteEngine"; echo "id author tsume-generator tests"; echo "usiok" ;;
+    isready) echo "readyok" ;;
+    go*)
+      echo "info depth 1 multipv 1 score cp 20 pv 2e2d"
+      echo "info depth 1 multipv 2 score cp 10 pv 1e2d"
+      echo "info depth 4 multipv 1 score cp 40 pv 1e2d 2a2b"
+      echo "info depth 4 multipv 2 score cp 15 pv 2e2d" ;;
+    stop) echo "bestmove 1e2d" ;;
+    quit) exit 0 ;;
+  esac
+done
@@ -0,0 +1,27 @@
+#!/bin/sh
+# USI engine that announces mate in 3 for one known tsume, the matching
+# mated score once its key move G*2b is played, and a plain score elsewhere.
+TSUME="1k1/3/R2/3/K1B b G 1"
+DEFENSE="1k1/1G1/R2/3/K1B w -"
+position=""
+while read -r line; do
+  case "$line" in
+    usi) echo "id name MateEngine"; echo "id author tsume-generator tests"; echo "usiok" ;;
+    isready) echo "readyok" ;;
+    "position sfen $TSUME") position=tsume ;;
+    "position sfen $DEFENSE"*) position=defense ;;
+    position*) position=other ;;
+    go*)
+      if [ "$position" = tsume ]; then
+        echo "info depth 5 multipv 1 score mate 3 pv G*2b 2a1a 3c3a"
+        echo "bestmove G*2b"
+      elif [ "$position" = defense ]; then
+        echo "info depth 5 multipv 1 score mate -2 pv 2a1a 3c3a"
+        echo "bestmove 2a1a"
+      else
+        echo "info depth 5 multipv 1 score cp 120 pv 3d3c"
+        echo "bestmove 3d3c"
+      fi ;;
+    quit) exit 0 ;;
+  esac
+done
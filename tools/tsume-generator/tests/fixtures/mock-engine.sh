@@ -0,0 +1,27 @@
+#!/bin/sh
+# Minimal deterministic USI engine for integration tests.
+#
+# Offers two candidates for each of the first two plies, then resigns, so the
+# generated puzzle depends only on the generator's random choices.
+plies=0
+while read -r line; do
+  case "$line" in
+    usi) echo "id name MockEngine"; echo "id author tsume-generator tests"; echo "usiok" ;;
+    isready) echo "readyok" ;;
+    position*" moves "*) plies=$(echo "${line#* moves }" | wc -w) ;;
+    position*) plies=0 ;;
+    go*)
+      case "$plies" in
+        0)
+          echo "info depth 1 multipv 1 score cp 50 pv 3d3c"
+          echo "info depth 1 multipv 2 score cp 40 pv 1d1c"
+          echo "bestmove 3d3c" ;;
+        1)
+          echo "info depth 1 multipv 1 score cp 10 pv 2a2b"
+          echo "info depth 1 multipv 2 score cp 0 pv 1b1c"
+          echo "bestmove 2a2b" ;;
+        *) echo "bestmove resign" ;;
+      esac ;;
+    quit) exit 0 ;;
+  esac
+done
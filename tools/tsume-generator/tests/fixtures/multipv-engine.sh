@@ -0,0 +1,25 @@
+#!/bin/sh
+# USI engine that reports as many candidates as its MultiPV option asks for.
+#
+# From the initial position the candidates are 2e2d, 3d3c, 1d1c and 1e2d, in
+# that order; candidate i scores MultiPV * 1000 - i * 100, so the principal
+# variation's score tells which MultiPV the search ran with. The option only
+# takes effect after the next isready, as with a real engine.
+multipv=5
+pending=5
+while read -r line; do
+  case "$line" in
+    usi) echo "id name MultiPvEngine"; echo "id author tsume-generator tests"; echo "usiok" ;;
+    "setoption name MultiPV value "*) pending="${line##* }" ;;
+    isready) multipv=$pending; echo "readyok" ;;
+    go*)
+      i=1
+      for mv in 2e2d 3d3c 1d1c 1e2d; do
+        [ "$i" -gt "$multipv" ] && break
+        echo "info depth 1 multipv $i score cp $((multipv * 1000 - i * 100)) pv $mv"
+        i=$((i + 1))
+      done
+      echo "bestmove 2e2d" ;;
+    quit) exit 0 ;;
+  esac
+done
@@ -0,0 +1,14 @@
+#!/bin/sh
+# USI engine for option tests: knows Threads, from 1 to 4, but has no Hash option.
+while read -r line; do
+  case "$line" in
+    usi)
+      echo "id name OptionsEngine"
+      echo "id author tsume-generator tests"
+      echo "option name Threads type spin default 1 min 1 max 4"
+      echo "usiok" ;;
+    isready) echo "readyok" ;;
+    "setoption name Hash"*) echo "No such option: Hash" ;;
+    quit) exit 0 ;;
+  esac
+done
@@ -0,0 +1,20 @@
+#!/bin/sh
+# USI engine whose kings shuffle back and forth forever.
+plies=0
+while read -r line; do
+  case "$line" in
+    usi) echo "id name ShuffleEngine"; echo "id author tsume-generator tests"; echo "usiok" ;;
+    isready) echo "readyok" ;;
+    position*" moves "*) plies=$(echo "${line#* moves }" | wc -w) ;;
+    position*) plies=0 ;;
+    go*)
+      case $((plies % 4)) in
+        0) mv=2e2d ;;
+        1) mv=2a2b ;;
+        2) mv=2d2e ;;
+        *) mv=2b2a ;;
+      esac
+      echo "info depth 1 multipv 1 score cp 0 pv $mv"; echo "bestmove $mv" ;;
+    quit) exit 0 ;;
+  esac
+done
@@ -0,0 +1,21 @@
+#!/bin/sh
+# USI engine that only sees the mate in 3 when given at least 50 ms.
+while read -r line; do
+  case "$line" in
+    usi) echo "id name SlowMateEngine"; echo "id author tsume-generator tests"; echo "usiok" ;;
+    isready) echo "readyok" ;;
+    go*)
+      budget=$(echo "$line" | sed -n 's/.*byoyomi \([0-9]*\).*/\1/p')
+      if [ "${budget:-0}" -ge 50 ]; then
+        echo "info depth 5 multipv 1 score mate 3 pv G*2b 2a1a 3c3a"
+        echo "info depth 5 multipv 2 score cp 200 pv 3c3b"
+        echo "info depth 5 multipv 3 score cp -100 pv 1e2d"
+        echo "bestmove G*2b"
+      else
+        echo "info depth 2 multipv 1 score cp 300 pv 3c3b"
+        echo "info depth 2 multipv 2 score cp 100 pv G*2b"
+        echo "bestmove 3c3b"
+      fi ;;
+    quit) exit 0 ;;
+  esac
+done
@@ -0,0 +1,8 @@
+#!/bin/sh
+# USI engine that only answers the handshake, as a release build would.
+while read -r line; do
+  case "$line" in
+    usi) echo "Fairy-Stockfish 14.0.1 LB by Fabian Fichter"; echo "id name Fairy-Stockfish 14.0.1"; echo "id author Fabian Fichter"; echo "usiok" ;;
+    quit) exit 0 ;;
+  esac
+done